@@ -0,0 +1,57 @@
+//! Benchmark of the embedding core
+//!
+//! Times [`fit`] on a fixed, seeded synthetic dataset, so timings are
+//! comparable across runs and machines without downloading anything. Run it
+//! with `cargo bench`.
+//!
+//! Each sample is a full fit timed with [`Instant`], which takes long enough
+//! that timer resolution does not matter, and one warm-up fit runs first so
+//! page faults and thread start-up are not counted.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use anyhow::Result;
+use pacmap::Configuration;
+use pacmap_rs_example::fit;
+use std::time::{Duration, Instant};
+
+/// Number of Gaussian blobs in the synthetic dataset
+const BLOBS: usize = 10;
+
+/// Number of points drawn from each blob
+const POINTS_PER_BLOB: usize = 300;
+
+/// Number of features of each point
+const FEATURES: usize = 50;
+
+/// Number of timed fits
+const SAMPLES: usize = 3;
+
+/// Seed of both the dataset and PaCMAP
+const SEED: u64 = 42;
+
+fn main() -> Result<()> {
+    let (x, _) = common::gaussian_blobs(SEED, BLOBS, POINTS_PER_BLOB, FEATURES, 5.0);
+    let config = Configuration::default();
+    fit(x.view(), config.clone(), SEED)?;
+
+    let mut times: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            fit(x.view(), config.clone(), SEED)?;
+            Ok(start.elapsed())
+        })
+        .collect::<Result<_>>()?;
+    times.sort_unstable();
+
+    println!(
+        "fit/{}x{}: median {:.3}s, min {:.3}s, max {:.3}s over {SAMPLES} samples",
+        x.nrows(),
+        x.ncols(),
+        times[SAMPLES / 2].as_secs_f64(),
+        times[0].as_secs_f64(),
+        times[SAMPLES - 1].as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,25 @@
+//! Build script recording the resolved `pacmap` version for run manifests
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let lockfile = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lockfile.display());
+
+    // Cargo writes the lockfile before building, so it names the exact version in use
+    let version = fs::read_to_string(&lockfile)
+        .ok()
+        .and_then(|lock| pacmap_version(&lock))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=PACMAP_VERSION={version}");
+}
+
+/// Returns the version of the `pacmap` package listed in a lockfile
+fn pacmap_version(lock: &str) -> Option<String> {
+    let mut lines = lock.lines();
+    lines.find(|line| line.trim() == r#"name = "pacmap""#)?;
+    let version = lines.next()?.trim().strip_prefix("version = ")?;
+    Some(version.trim_matches('"').to_string())
+}
@@ -0,0 +1,152 @@
+//! Initial layouts with points pinned to known coordinates
+//!
+//! PaCMAP starts from a PCA projection of the data unless it is given initial
+//! coordinates. To orient an embedding like an earlier one, for example of an
+//! older version of the same data, a few anchor points can start where they
+//! were placed before. The other points start from the PCA projection, mapped
+//! into the anchors' frame by the affine transform that best fits the anchors'
+//! own projections to their coordinates, so the whole layout starts in one
+//! frame instead of the anchors sitting apart from the rest.
+//!
+//! Only the orientation of that frame carries over. PaCMAP centers the initial
+//! layout and rescales each axis separately to a tiny spread, so the earlier
+//! position and scale are lost, and it then moves anchors like any other
+//! point. The new embedding therefore faces roughly the same way as the
+//! earlier one, with clusters on the same sides, but is not in its coordinates.
+
+use crate::pca::pca_reduce;
+use anyhow::{ensure, Result};
+use ndarray::{Array2, ArrayView2};
+use std::collections::HashSet;
+
+/// A point started at known coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor {
+    /// Row of the point in the data
+    pub row: usize,
+
+    /// Initial coordinates of the point, one per embedding dimension
+    pub position: Vec<f32>,
+}
+
+/// Builds an initial layout placing each anchor at its coordinates
+///
+/// # Arguments
+/// * `x` - Data passed to PaCMAP, of shape (n_samples, n_features)
+/// * `anchors` - Points to pin; needs at least `dims + 1` to fit the transform
+/// * `dims` - Number of embedding dimensions
+///
+/// # Returns
+/// Initial coordinates of shape (n_samples, `dims`)
+///
+/// # Errors
+/// Returns an error if there are too few anchors, an anchor's row is out of
+/// range or repeated, its coordinates do not have `dims` finite values, the
+/// data cannot be projected onto `dims` principal components, or the anchors'
+/// projections lie on a line (or plane in 3D), which leaves the transform
+/// undetermined
+pub fn anchored_initialization(
+    x: ArrayView2<f32>,
+    anchors: &[Anchor],
+    dims: usize,
+) -> Result<Array2<f32>> {
+    ensure!(
+        anchors.len() > dims,
+        "{} anchors cannot fix a {dims}D layout; at least {} are needed",
+        anchors.len(),
+        dims + 1
+    );
+    let mut rows = HashSet::new();
+    for anchor in anchors {
+        ensure!(
+            anchor.row < x.nrows(),
+            "Anchor row {} is out of range for {} points",
+            anchor.row,
+            x.nrows()
+        );
+        ensure!(
+            rows.insert(anchor.row),
+            "Point {} is anchored twice",
+            anchor.row
+        );
+        ensure!(
+            anchor.position.len() == dims && anchor.position.iter().all(|v| v.is_finite()),
+            "Anchor of point {} has coordinates {:?}, expected {dims} finite values",
+            anchor.row,
+            anchor.position
+        );
+    }
+
+    let projection = pca_reduce(x, dims)?;
+    let transform = fit_affine(projection.view(), anchors, dims)?;
+    let mut layout = Array2::from_shape_fn((x.nrows(), dims), |(i, j)| {
+        let mapped = (0..dims).map(|l| f64::from(projection[[i, l]]) * transform[[l, j]]);
+        (mapped.sum::<f64>() + transform[[dims, j]]) as f32
+    });
+    for anchor in anchors {
+        for (j, &value) in anchor.position.iter().enumerate() {
+            layout[[anchor.row, j]] = value;
+        }
+    }
+    Ok(layout)
+}
+
+/// Fits the affine transform from the anchors' projections to their
+/// coordinates by least squares
+///
+/// # Returns
+/// The transform as a matrix of shape (`dims` + 1, `dims`): a point `p` maps to
+/// `p * M[..dims] + M[dims]`
+fn fit_affine(projection: ArrayView2<f32>, anchors: &[Anchor], dims: usize) -> Result<Array2<f64>> {
+    // Normal equations (AᵀA) M = AᵀB, with A holding [projection, 1] per anchor
+    let n = dims + 1;
+    let mut system = Array2::<f64>::zeros((n, n + dims));
+    for anchor in anchors {
+        let a: Vec<f64> = (0..dims)
+            .map(|l| f64::from(projection[[anchor.row, l]]))
+            .chain([1.0])
+            .collect();
+        for i in 0..n {
+            for j in 0..n {
+                system[[i, j]] += a[i] * a[j];
+            }
+            for (j, &b) in anchor.position.iter().enumerate() {
+                system[[i, n + j]] += a[i] * f64::from(b);
+            }
+        }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting
+    let scale = system.iter().fold(0.0_f64, |max, v| max.max(v.abs()));
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| {
+                system[[a, column]]
+                    .abs()
+                    .total_cmp(&system[[b, column]].abs())
+            })
+            .unwrap_or(column);
+        ensure!(
+            system[[pivot, column]].abs() > 1e-9 * scale,
+            "The anchors lie too close to a {} in the data's principal components to align the \
+             layout; pick anchors spread across the data",
+            if dims == 2 { "line" } else { "plane" }
+        );
+        for j in 0..n + dims {
+            system.swap([column, j], [pivot, j]);
+        }
+        let divisor = system[[column, column]];
+        for j in 0..n + dims {
+            system[[column, j]] /= divisor;
+        }
+        for i in (0..n).filter(|&i| i != column) {
+            let factor = system[[i, column]];
+            for j in 0..n + dims {
+                system[[i, j]] -= factor * system[[column, j]];
+            }
+        }
+    }
+    Ok(Array2::from_shape_fn((n, dims), |(i, j)| {
+        system[[i, n + j]]
+    }))
+}
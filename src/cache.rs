@@ -0,0 +1,149 @@
+//! Cache of fitted embeddings
+//!
+//! Seeded PaCMAP runs are deterministic, so a run repeating an earlier one's
+//! input and settings can reuse its embedding instead of fitting again. Each
+//! embedding is stored under a key hashing everything that determines it: the
+//! exact matrix handed to PaCMAP, which already reflects the dataset, sampling,
+//! normalization and PCA, together with the full PaCMAP configuration and the
+//! crate version. Any change to the data or settings therefore misses the
+//! cache rather than returning a stale embedding.
+
+use crate::manifest::PACMAP_VERSION;
+use anyhow::{Context, Result};
+use ndarray::{Array2, Array3, ArrayView2};
+use ndarray_npy::{read_npy, write_npy};
+use pacmap::{Configuration, Initialization, PairConfiguration};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Returns the directory cached embeddings are stored in
+///
+/// # Errors
+/// Returns an error if the user cache directory cannot be determined
+pub fn cache_dir() -> Result<PathBuf> {
+    let cache = dirs::cache_dir().context("Could not determine the user cache directory")?;
+    Ok(cache.join("pacmap-example").join("embeddings"))
+}
+
+/// Returns the key identifying the embedding of `x` under `config`
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features) exactly as passed to PaCMAP
+/// * `config` - PaCMAP configuration
+///
+/// # Returns
+/// A hex-encoded SHA-256 digest
+pub fn cache_key(x: ArrayView2<f32>, config: &Configuration) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "pacmap {PACMAP_VERSION}\n{config:?}\n{:?}\n",
+        x.dim()
+    ));
+    // Debug output elides the middle of large arrays, so provided pairs and
+    // initial layouts are hashed in full
+    if let PairConfiguration::NeighborsProvided { pair_neighbors } = &config.pair_configuration {
+        for index in pair_neighbors.iter() {
+            hasher.update(index.to_le_bytes());
+        }
+    }
+    if let Initialization::Value(layout) = &config.initialization {
+        for value in layout.iter() {
+            hasher.update(value.to_le_bytes());
+        }
+    }
+    for value in x.iter() {
+        hasher.update(value.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Loads a cached embedding, if one is stored under `key`
+///
+/// A cache entry that cannot be read is reported and treated as missing, so
+/// the embedding is fitted again and the entry replaced.
+///
+/// # Arguments
+/// * `dir` - Cache directory
+/// * `key` - Key returned by [`cache_key`]
+/// * `snapshots` - Whether the run captures layouts during the optimization
+///
+/// # Returns
+/// The embedding and, if requested, its captured layouts
+pub fn load(dir: &Path, key: &str, snapshots: bool) -> Option<(Array2<f32>, Option<Array3<f32>>)> {
+    let path = embedding_path(dir, key);
+    if !path.is_file() {
+        return None;
+    }
+    let read = || -> Result<_> {
+        let embedding = read_npy(&path)
+            .with_context(|| format!("Error reading cached embedding {}", path.display()))?;
+        let captured =
+            if snapshots {
+                let path = snapshots_path(dir, key);
+                Some(read_npy(&path).with_context(|| {
+                    format!("Error reading cached snapshots {}", path.display())
+                })?)
+            } else {
+                None
+            };
+        Ok((embedding, captured))
+    };
+    match read() {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            warn!("Ignoring unreadable cache entry: {e:#}");
+            None
+        }
+    }
+}
+
+/// Stores an embedding under `key`, creating the cache directory as needed
+///
+/// Files are written under a temporary name and then renamed, so an
+/// interrupted write never leaves a truncated entry behind.
+///
+/// # Arguments
+/// * `dir` - Cache directory
+/// * `key` - Key returned by [`cache_key`]
+/// * `embedding` - Embedding of shape (n_samples, n_dims)
+/// * `snapshots` - Layouts captured during the optimization, if any
+///
+/// # Errors
+/// Returns an error if the directory or a file cannot be written
+pub fn store(
+    dir: &Path,
+    key: &str,
+    embedding: &Array2<f32>,
+    snapshots: Option<&Array3<f32>>,
+) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Error creating cache directory {}", dir.display()))?;
+    // Snapshots go first, so an embedding is only ever found with its snapshots
+    if let Some(snapshots) = snapshots {
+        write_atomically(&snapshots_path(dir, key), |path| write_npy(path, snapshots))?;
+    }
+    write_atomically(&embedding_path(dir, key), |path| write_npy(path, embedding))
+}
+
+/// Writes a file through a temporary sibling that is renamed into place
+fn write_atomically<E>(path: &Path, write: impl FnOnce(&Path) -> Result<(), E>) -> Result<()>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let partial = path.with_extension("partial");
+    write(&partial).with_context(|| format!("Error writing cache entry {}", path.display()))?;
+    fs::rename(&partial, path)
+        .with_context(|| format!("Error moving cache entry into place at {}", path.display()))
+}
+
+/// Returns the path of the cached embedding stored under `key`
+fn embedding_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.npy"))
+}
+
+/// Returns the path of the cached snapshots stored under `key`
+fn snapshots_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.snapshots.npy"))
+}
@@ -0,0 +1,1521 @@
+//! Command-line options of the example
+//!
+//! [`Cli`] holds every option, read from the command line and from a TOML
+//! file given with `--config`, and resolves them into the settings a run
+//! uses, such as the PaCMAP [`Configuration`] and the plot style. Each
+//! [`Command`] takes only the options that apply to it.
+
+use crate::compare::Comparison;
+use crate::config::ConfigFile;
+use crate::dataset::{CachePolicy, Dataset, SplitSizes, DEFAULT_MAX_DOWNLOAD_SIZE};
+use crate::distance::Metric;
+use crate::grid::{grid_points, GridPoint, DEFAULT_SAMPLE_SIZE};
+use crate::input::{FeatureColumns, Input, LabelColumn};
+use crate::normalize::Normalize;
+use crate::plot::{AxisScale, OutputFormat, Palette, PlotStyle, PlotType, MAX_ANIMATION_FRAMES};
+use crate::sample::SampleStrategy;
+use crate::saved::RunConfig;
+use anyhow::{bail, ensure, Context, Result};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use pacmap::Configuration;
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use tracing::{info, Level};
+
+/// Number of rows `--preview` embeds when `--sample` is not given
+pub(crate) const PREVIEW_SAMPLE_SIZE: usize = 5_000;
+
+/// Iterations of PaCMAP's three optimization phases under `--preview`, a
+/// fifth of the defaults
+pub(crate) const PREVIEW_ITERATIONS: (usize, usize, usize) = (20, 20, 50);
+
+/// Number of rows `--verify-reproducible` embeds when `--sample` is not given
+pub(crate) const VERIFY_SAMPLE_SIZE: usize = 2_000;
+
+/// Command-line options controlling the PaCMAP run
+///
+/// Defaults reproduce the standard parameters used by the original example.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Cli {
+    /// Run only one step of the pipeline; without a command, the data is embedded and
+    /// plotted in one go
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML file of option values; flags given on the command line take precedence
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Log only warnings and errors, still printing the final scores; twice for errors only
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "verbose")]
+    pub quiet: u8,
+
+    /// Log debugging details; twice to also trace PaCMAP's internals
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Write logs as one JSON object per line, with phase durations and scores as fields
+    #[arg(long)]
+    pub log_json: bool,
+
+    /// Record each phase of the timing breakdown and write them as a Chrome trace JSON file,
+    /// which opens in chrome://tracing, Perfetto or speedscope, when the run ends
+    #[arg(long, value_name = "PATH")]
+    pub profile: Option<PathBuf>,
+
+    /// Validate the options and input files, print the resolved plan and exit before any
+    /// download or embedding
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Dataset to download and embed
+    #[arg(long, value_enum, default_value_t = Dataset::Mnist, conflicts_with = "input")]
+    pub dataset: Dataset,
+
+    /// Download the dataset again even if a verified copy is cached
+    #[arg(long, conflicts_with = "input")]
+    pub refresh: bool,
+
+    /// Never access the network: fail at once if the dataset is not cached instead of
+    /// downloading it
+    #[arg(long, conflicts_with = "refresh")]
+    pub offline: bool,
+
+    /// Number of images taken from the start of the dataset's training split [default: all
+    /// 60000, or 50000 for CIFAR-10]
+    #[arg(long, value_name = "N", conflicts_with = "input")]
+    pub train_len: Option<usize>,
+
+    /// Number of images taken from the start of the dataset's test split [default: all 10000]
+    #[arg(long, value_name = "N", conflicts_with = "input")]
+    pub test_len: Option<usize>,
+
+    /// Reject a downloaded dataset archive larger than this many bytes, e.g. an HTML page
+    /// served by a misconfigured mirror
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_DOWNLOAD_SIZE, conflicts_with = "input")]
+    pub max_download_size: u64,
+
+    /// Embed local files instead of downloading a dataset, e.g. `csv:data.csv`, `npy:data.npy`
+    /// or sparse `libsvm:data.svm`; repeat to embed several files with the same columns together
+    #[arg(long, value_name = "FORMAT:PATH")]
+    pub input: Vec<Input>,
+
+    /// Color the points by the input file they came from instead of their class
+    #[arg(long, conflicts_with = "color_column")]
+    pub color_by_source: bool,
+
+    /// CSV column holding integer or text class labels: a header name, a position counted
+    /// from 1 such as `1`, or `last`; `#1` is a position even if a column is named `1`
+    #[arg(long, visible_alias = "label-col", value_name = "COLUMN")]
+    pub label_column: Option<LabelColumn>,
+
+    /// CSV column of continuous values, e.g. a regression target, to color the points by
+    /// instead of their class: a header name, a position such as `3`, or `last`
+    #[arg(long, value_name = "COLUMN")]
+    pub color_column: Option<LabelColumn>,
+
+    /// CSV columns holding the features, skipping the others: comma-separated header names,
+    /// patterns such as `pixel_*`, and positions or ranges counted from 1, e.g. `2-785`
+    /// [default: every column but the label and color columns]
+    #[arg(long, value_name = "COLUMNS")]
+    pub features: Option<FeatureColumns>,
+
+    /// Trade quality for speed while iterating on the plot: embed a 5000-row sample (unless
+    /// `--sample` is given) with fewer iterations and without `--pca`
+    #[arg(long, conflicts_with_all = ["grid", "load_embedding"])]
+    pub preview: bool,
+
+    /// Plot only the points of these classes, e.g. `3,5,8`; the embedding is still computed
+    /// from every class unless `--classes-before-fit` is set
+    #[arg(long, value_name = "LABELS", value_delimiter = ',', conflicts_with_all = ["color_column", "color_by_source"])]
+    pub classes: Vec<i32>,
+
+    /// Drop the rows of other classes right after loading, so `--classes` also limits the
+    /// data PaCMAP embeds and its neighbor structure
+    #[arg(long, requires = "classes", conflicts_with = "load_embedding")]
+    pub classes_before_fit: bool,
+
+    /// Drop rows that exactly repeat an earlier row, keeping the first occurrence and its label
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// Drop features that have the same value in every embedded row; they are always counted
+    #[arg(long, conflicts_with = "hover_thumbnails")]
+    pub drop_constant: bool,
+
+    /// Embed a random subset of this many rows, drawn using `--seed` (or 0 if unset)
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+
+    /// How `--sample` chooses its rows: from the whole dataset, or from each class in
+    /// proportion to its size, which needs labels
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = SampleStrategy::Random)]
+    pub sample_strategy: SampleStrategy,
+
+    /// Rescale the input before embedding; built-in datasets are already scaled to [0,1]
+    #[arg(long, value_enum, default_value_t = Normalize::None)]
+    pub normalize: Normalize,
+
+    /// Distance metric deciding which samples are neighbors; PaCMAP itself only measures
+    /// Euclidean distances, so cosine scales each sample to unit length and manhattan searches
+    /// neighbors by brute force, keeping Euclidean mid-near and far pairs
+    #[arg(long, value_enum, default_value_t = Metric::Euclidean)]
+    pub metric: Metric,
+
+    /// Reduce the input to this many principal components before running PaCMAP
+    #[arg(long, value_name = "K")]
+    pub pca: Option<usize>,
+
+    /// Number of nearest neighbors used to form neighbor pairs
+    #[arg(long, default_value_t = 10)]
+    pub neighbors: usize,
+
+    /// Use this `.npy` array of shape (n_samples, k) listing each sample's nearest neighbor
+    /// indices instead of searching for neighbors; k must equal `--neighbors`
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["sample", "preview", "classes_before_fit", "grid", "verify_reproducible", "load_embedding"]
+    )]
+    pub neighbors_file: Option<PathBuf>,
+
+    /// Ratio of mid-near pairs to nearest neighbor pairs
+    #[arg(long, default_value_t = 0.5)]
+    pub mid_near_ratio: f32,
+
+    /// Ratio of far pairs to nearest neighbor pairs
+    #[arg(long, default_value_t = 2.0)]
+    pub far_pair_ratio: f32,
+
+    /// Step size of PaCMAP's Adam optimizer
+    #[arg(long, value_name = "RATE", default_value_t = 1.0)]
+    pub learning_rate: f32,
+
+    /// Iterations of PaCMAP's three optimization phases, emphasizing attraction, local
+    /// structure and global structure in turn [default: 100,100,250]
+    #[arg(long, value_name = "A,L,G", value_delimiter = ',')]
+    pub iterations: Vec<usize>,
+
+    /// Number of dimensions in the output embedding: 2, or 3 for a 3D plot
+    #[arg(long, default_value_t = 2)]
+    pub embedding_dimensions: usize,
+
+    /// Number of worker threads; 0 uses all cores
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub threads: usize,
+
+    /// Seed for PaCMAP's random sampling and initialization; seeded runs are single-threaded
+    /// so the same seed and parameters always reproduce the same embedding
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Fit seeded runs again instead of reusing an identical earlier run's cached embedding
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Number of nearest neighbors used to score the embedding's trustworthiness and neighbor
+    /// recall
+    #[arg(long, value_name = "K", default_value_t = 10)]
+    pub trustworthiness_k: usize,
+
+    /// Number of embedded neighbors voting on each point's class when scoring k-NN accuracy
+    #[arg(long, value_name = "K", default_value_t = 10)]
+    pub knn_accuracy_k: usize,
+
+    /// Also plot a heatmap of which classes each class's `--knn-accuracy-k` nearest embedded
+    /// neighbors belong to, written as neighbor_confusion.<FORMAT> next to the visualization
+    #[arg(long, conflicts_with_all = ["grid", "load_embedding", "no_plot"])]
+    pub neighbor_confusion: bool,
+
+    /// Draw points whose `--knn-accuracy-k` nearest embedded neighbors vote for another class
+    /// as larger crosses, so points sitting among another class stand out
+    #[arg(long, conflicts_with_all = ["color_column", "compare_with"])]
+    pub knn_agreement: bool,
+
+    /// Fit on the training split and transform the test split separately; not yet
+    /// supported, as the pacmap crate cannot embed points outside the fitted data
+    #[arg(long, conflicts_with_all = ["input", "grid"])]
+    pub transform_test: bool,
+
+    /// Embed a 2000-row sample (unless `--sample` is given) twice with the same seed (`--seed`,
+    /// or 0 if unset) and fail unless both embeddings match, instead of plotting
+    #[arg(
+        long,
+        conflicts_with_all = ["grid", "load_embedding", "compare_with", "animate", "neighbor_confusion"]
+    )]
+    pub verify_reproducible: bool,
+
+    /// Run a grid search over parameter lists instead of a single embedding
+    #[arg(long)]
+    pub grid: bool,
+
+    /// Candidate neighbor counts for `--grid`, e.g. `5,10,20` (default: `--neighbors`)
+    #[arg(long, value_name = "LIST", value_delimiter = ',', requires = "grid")]
+    pub grid_neighbors: Vec<usize>,
+
+    /// Candidate mid-near ratios for `--grid` (default: `--mid-near-ratio`)
+    #[arg(long, value_name = "LIST", value_delimiter = ',', requires = "grid")]
+    pub grid_mid_near_ratios: Vec<f32>,
+
+    /// Candidate far pair ratios for `--grid` (default: `--far-pair-ratio`)
+    #[arg(long, value_name = "LIST", value_delimiter = ',', requires = "grid")]
+    pub grid_far_pair_ratios: Vec<f32>,
+
+    /// Path of the CSV summary table written by `--grid`
+    #[arg(long, value_name = "PATH", default_value = "grid_search.csv")]
+    pub grid_out: PathBuf,
+
+    /// Embed the data N times with consecutive seeds starting at `--seed` (or 0 if unset) and
+    /// plot the embeddings as a grid of small panels, to show how much the layout varies
+    /// between runs
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "grid", "verify_reproducible", "compare_with", "animate", "split_output", "no_plot",
+            "load_embedding", "hover_thumbnails", "annotations", "knn_agreement",
+            "neighbor_confusion", "save_embedding", "embedding_out", "npy_out"
+        ]
+    )]
+    pub ensemble: Option<usize>,
+
+    /// Also embed the data with another algorithm and plot it beside PaCMAP's layout
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ALGORITHM",
+        conflicts_with_all = ["grid", "load_embedding"]
+    )]
+    pub compare_with: Option<Comparison>,
+
+    /// Path of the visualization to write, creating parent directories as needed
+    /// [default: pacmap_visualization.<FORMAT>]
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Also plot the training and test splits of a built-in dataset, or the points of the
+    /// first and second `--input`, on their own as `<OUTPUT>_train` and `<OUTPUT>_test`, with
+    /// the same axis ranges as the combined plot
+    #[arg(
+        long,
+        conflicts_with_all = ["grid", "compare_with", "animate", "no_plot", "load_embedding"]
+    )]
+    pub split_output: bool,
+
+    /// Skip the visualization, only computing the scores and writing the other outputs
+    #[arg(long, conflicts_with_all = ["format", "hover_thumbnails"])]
+    pub no_plot: bool,
+
+    /// Print each class's size, centroid and mean pairwise distance and the silhouette score
+    /// as text instead of plotting or writing anything
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "grid", "verify_reproducible", "ensemble", "compare_with", "animate", "split_output",
+            "no_plot", "format", "hover_thumbnails", "annotations", "knn_agreement",
+            "neighbor_confusion", "save_embedding", "embedding_out", "npy_out", "graph_out"
+        ]
+    )]
+    pub summary_only: bool,
+
+    /// How to draw the embedding: one marker per point, or a heatmap of point density for
+    /// embeddings too dense to read as a scatter plot
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PlotType::Scatter,
+        conflicts_with_all = ["compare_with", "hover_thumbnails", "color_by_source", "color_column"]
+    )]
+    pub plot_type: PlotType,
+
+    /// Title shown above the plot [default: PaCMAP Embedding of <DATASET> (<N> points)]
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Title of the plot's horizontal axis
+    #[arg(long, value_name = "LABEL")]
+    pub x_label: Option<String>,
+
+    /// Title of the plot's vertical axis
+    #[arg(long, value_name = "LABEL")]
+    pub y_label: Option<String>,
+
+    /// Fix the horizontal axis to this range instead of fitting the data, e.g. `-30,30`, so
+    /// plots of different runs share a scale
+    #[arg(
+        long,
+        value_name = "MIN,MAX",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub x_range: Vec<f64>,
+
+    /// Fix the vertical axis to this range instead of fitting the data
+    #[arg(
+        long,
+        value_name = "MIN,MAX",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub y_range: Vec<f64>,
+
+    /// Scale of the horizontal axis of 2D scatter plots: linear, log for positive coordinates
+    /// only, or symlog, logarithmic on both sides of zero
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCALE",
+        default_value_t = AxisScale::Linear,
+        conflicts_with_all = ["compare_with", "ensemble", "animate"]
+    )]
+    pub x_scale: AxisScale,
+
+    /// Scale of the vertical axis of 2D scatter plots, like `--x-scale`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCALE",
+        default_value_t = AxisScale::Linear,
+        conflicts_with_all = ["compare_with", "ensemble", "animate"]
+    )]
+    pub y_scale: AxisScale,
+
+    /// Draw both axes of 2D plots at the same scale, so clusters are not stretched; 3D plots
+    /// always keep the data's proportions
+    #[arg(long)]
+    pub equal_aspect: bool,
+
+    /// Diameter of the scatter plot's point markers in pixels [default: 2]
+    #[arg(long, value_name = "PIXELS")]
+    pub marker_size: Option<usize>,
+
+    /// Opacity of the scatter plot's point markers from 0 to 1 [default: fully opaque up to
+    /// 10000 points, fading automatically for more]
+    #[arg(long, value_name = "OPACITY")]
+    pub marker_opacity: Option<f64>,
+
+    /// Draw at most this many evenly chosen points per class, decluttering dense plots; the
+    /// embedding and metrics still use every point
+    #[arg(long, value_name = "N")]
+    pub max_points_per_class: Option<usize>,
+
+    /// Draw at most about this many points in total, chosen evenly from each class in
+    /// proportion to its size, keeping the HTML of large embeddings small enough to open
+    #[arg(long, value_name = "N")]
+    pub max_plot_points: Option<usize>,
+
+    /// Draw classes from the largest to the smallest, so small classes are not buried under
+    /// large ones; the legend follows the same order
+    #[arg(long, conflicts_with = "color_column")]
+    pub small_classes_on_top: bool,
+
+    /// Outline the markers of classes with fewer than a quarter of the largest class's points
+    #[arg(long, conflicts_with = "color_column")]
+    pub outline_small_classes: bool,
+
+    /// CSV file of `index,x,y` rows (`index,x,y,z` in 3D) giving the initial coordinates of
+    /// some points, with indices as shown when hovering, to orient the layout like an earlier
+    /// one; PaCMAP recenters and rescales the start, so only the orientation carries over
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["grid", "verify_reproducible", "load_embedding"]
+    )]
+    pub anchors: Option<PathBuf>,
+
+    /// CSV file of `index,text` rows labeling individual points of the 2D plot, with indices
+    /// as shown when hovering
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["grid", "compare_with", "animate", "no_plot"]
+    )]
+    pub annotations: Option<PathBuf>,
+
+    /// Draw the points of the 2D scatter plot with WebGL, keeping the browser responsive with
+    /// hundreds of thousands of points; 3D plots always use WebGL
+    #[arg(long, conflicts_with = "animate")]
+    pub webgl: bool,
+
+    /// Mark each class's centroid in the 2D scatter plot with a larger labeled marker
+    #[arg(long, conflicts_with = "color_column")]
+    pub centroids: bool,
+
+    /// Also outline one standard deviation around each centroid with an ellipse
+    #[arg(long, requires = "centroids")]
+    pub centroid_spread: bool,
+
+    /// Color scale of continuous `--color-column` values and density heatmaps, or `okabe-ito`
+    /// for colorblind-safe class colors
+    #[arg(long, value_enum, default_value_t = Palette::Portland)]
+    pub palette: Palette,
+
+    /// Show each point's source image when hovering over it in the HTML plot
+    #[arg(long, conflicts_with = "input")]
+    pub hover_thumbnails: bool,
+
+    /// Animate how the 2D layout settles over PaCMAP's iterations in the HTML plot, with a
+    /// play button and an iteration slider
+    #[arg(
+        long,
+        conflicts_with_all = ["grid", "compare_with", "load_embedding", "no_plot", "hover_thumbnails", "centroids"]
+    )]
+    pub animate: bool,
+
+    /// Number of layouts captured for `--animate`, including the initialization; each adds
+    /// a copy of the coordinates to the HTML
+    #[arg(long, value_name = "N", default_value_t = 20, requires = "animate")]
+    pub animation_frames: usize,
+
+    /// File format of the visualization; png and svg require Kaleido
+    #[arg(long, value_enum, default_value_t = OutputFormat::Html)]
+    pub format: OutputFormat,
+
+    /// Width of png and svg images in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 800)]
+    pub image_width: usize,
+
+    /// Height of png and svg images in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 800)]
+    pub image_height: usize,
+
+    /// Pixel density multiplier of png images, e.g. 2 for roughly 192 DPI
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    pub image_scale: f64,
+
+    /// Also write the embedding coordinates and labels to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub embedding_out: Option<PathBuf>,
+
+    /// Also write the embedding as a NumPy `.npy` file, with labels alongside
+    #[arg(long, value_name = "PATH")]
+    pub npy_out: Option<PathBuf>,
+
+    /// Also write the nearest neighbor graph PaCMAP fits to, as `.npy` arrays of shape
+    /// (n_samples, k): neighbor indices to this path and their distances beside it. The graph
+    /// is searched by the example rather than by PaCMAP, so the embedding can differ from a run
+    /// without this flag at the same seed, most of all for data with more than 100 features
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["neighbors_file", "grid", "verify_reproducible", "load_embedding"]
+    )]
+    pub graph_out: Option<PathBuf>,
+
+    /// Also write the embedding as an Arrow IPC (Feather) file; not yet supported, as the
+    /// arrow crate is not a dependency
+    #[arg(long, value_name = "PATH")]
+    pub arrow_out: Option<PathBuf>,
+
+    /// Save the embedding, labels and run settings for replotting with `--load-embedding`
+    #[arg(long, value_name = "PATH")]
+    pub save_embedding: Option<PathBuf>,
+
+    /// Plot an embedding saved with `--save-embedding` instead of loading data and running
+    /// PaCMAP
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["input", "refresh", "grid", "transform_test", "hover_thumbnails", "save_embedding"]
+    )]
+    pub load_embedding: Option<PathBuf>,
+}
+
+/// Single steps of the pipeline, each taking only the options that apply to it
+///
+/// `fit` and `plot` split a run at the saved embedding file, so embeddings can
+/// be computed once and restyled, scored or compared in later commands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Embed the data and save the embedding without plotting it
+    Fit {
+        /// File the embedding, labels and run settings are saved to
+        embedding: PathBuf,
+    },
+
+    /// Plot an embedding saved by `fit` or `--save-embedding`
+    Plot {
+        /// Saved embedding file
+        embedding: PathBuf,
+
+        /// Keep running and plot the embedding again whenever the file changes, until Ctrl-C
+        #[arg(long)]
+        watch: bool,
+    },
+
+    /// Print the k-NN accuracy of a saved embedding
+    Metrics {
+        /// Saved embedding file
+        embedding: PathBuf,
+    },
+
+    /// Plot two saved 2D embeddings of the same points side by side
+    Compare {
+        /// Saved embedding drawn on the left
+        left: PathBuf,
+
+        /// Saved embedding drawn on the right
+        right: PathBuf,
+    },
+}
+
+/// Options every command takes, which control logging and threads
+pub(crate) const GENERAL_OPTIONS: [&str; 6] = [
+    "config", "quiet", "verbose", "log_json", "profile", "threads",
+];
+
+/// Options of `fit`, which loads, embeds and exports the data
+pub(crate) const FIT_OPTIONS: [&str; 39] = [
+    "dry_run",
+    "dataset",
+    "refresh",
+    "offline",
+    "train_len",
+    "test_len",
+    "max_download_size",
+    "input",
+    "color_by_source",
+    "label_column",
+    "color_column",
+    "features",
+    "preview",
+    "classes",
+    "classes_before_fit",
+    "dedup",
+    "drop_constant",
+    "sample",
+    "sample_strategy",
+    "normalize",
+    "metric",
+    "pca",
+    "neighbors",
+    "neighbors_file",
+    "mid_near_ratio",
+    "far_pair_ratio",
+    "learning_rate",
+    "iterations",
+    "embedding_dimensions",
+    "seed",
+    "no_cache",
+    "trustworthiness_k",
+    "knn_accuracy_k",
+    "transform_test",
+    "anchors",
+    "embedding_out",
+    "npy_out",
+    "graph_out",
+    "arrow_out",
+];
+
+/// Options of `plot` and `compare`, which style and write the visualization
+pub(crate) const PLOT_OPTIONS: [&str; 26] = [
+    "knn_accuracy_k",
+    "classes",
+    "output",
+    "plot_type",
+    "title",
+    "x_label",
+    "y_label",
+    "x_range",
+    "y_range",
+    "x_scale",
+    "y_scale",
+    "equal_aspect",
+    "marker_size",
+    "marker_opacity",
+    "max_points_per_class",
+    "max_plot_points",
+    "small_classes_on_top",
+    "outline_small_classes",
+    "webgl",
+    "centroids",
+    "centroid_spread",
+    "palette",
+    "format",
+    "image_width",
+    "image_height",
+    "image_scale",
+];
+
+/// Options `plot` takes besides [`PLOT_OPTIONS`], as it replots and exports a
+/// single embedding
+pub(crate) const REPLOT_OPTIONS: [&str; 6] = [
+    "dry_run",
+    "summary_only",
+    "annotations",
+    "knn_agreement",
+    "embedding_out",
+    "npy_out",
+];
+
+/// Options only a full run takes, as they need both the input data and the
+/// plot, or choose the saved embedding files the commands name themselves
+pub(crate) const FULL_RUN_OPTIONS: [&str; 16] = [
+    "neighbor_confusion",
+    "verify_reproducible",
+    "grid",
+    "grid_neighbors",
+    "grid_mid_near_ratios",
+    "grid_far_pair_ratios",
+    "grid_out",
+    "ensemble",
+    "compare_with",
+    "split_output",
+    "no_plot",
+    "hover_thumbnails",
+    "animate",
+    "animation_frames",
+    "save_embedding",
+    "load_embedding",
+];
+
+impl Command {
+    /// Returns the command's name as typed on the command line
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Command::Fit { .. } => "fit",
+            Command::Plot { .. } => "plot",
+            Command::Metrics { .. } => "metrics",
+            Command::Compare { .. } => "compare",
+        }
+    }
+
+    /// Returns whether the command takes the option with this id
+    ///
+    /// A full run takes every option; a command only those that change what
+    /// it does, so an option it would ignore is rejected instead.
+    pub(crate) fn takes(&self, id: &str) -> bool {
+        let options: &[&[&str]] = match self {
+            Command::Fit { .. } => &[&FIT_OPTIONS],
+            Command::Plot { .. } => &[&PLOT_OPTIONS, &REPLOT_OPTIONS],
+            Command::Metrics { .. } => &[&["knn_accuracy_k"]],
+            Command::Compare { .. } => &[&PLOT_OPTIONS],
+        };
+        GENERAL_OPTIONS.contains(&id) || options.iter().any(|options| options.contains(&id))
+    }
+}
+
+impl Cli {
+    /// Parses the command line, filling in options left unset from `--config`
+    ///
+    /// # Errors
+    /// Returns an error if the config file cannot be read or parsed, or a
+    /// command is given an option it does not take
+    pub fn parse_with_config() -> Result<Self> {
+        Self::parse_with_config_from(env::args_os().collect())
+    }
+
+    /// Parses the given arguments, filling in options left unset from `--config`
+    ///
+    /// The config file's options are parsed again together with the command
+    /// line as the flags they stand for, so clap rejects options of the file
+    /// that conflict with each other or lack an option they require. Flags
+    /// take precedence: an option of the file is left out when the same
+    /// option is given as a flag, or one it cannot be combined with, such as
+    /// `no-plot = true` in the file with `--format png` on the command line. A command only takes the options that apply to it, and the
+    /// file's other options are left out too.
+    ///
+    /// # Arguments
+    /// * `args` - Program name followed by the command-line arguments
+    ///
+    /// # Errors
+    /// Returns an error if the config file cannot be read or parsed, sets
+    /// conflicting options, or a command is given an option it does not take
+    pub(crate) fn parse_with_config_from(args: Vec<OsString>) -> Result<Self> {
+        let mut matches = Cli::clap_command()
+            .try_get_matches_from(&args)
+            .unwrap_or_else(|e| e.exit());
+        let command = Cli::from_arg_matches(&matches)
+            .unwrap_or_else(|e| e.exit())
+            .command;
+        let takes = |id: &str| command.as_ref().is_none_or(|command| command.takes(id));
+        if let Some(path) = matches.get_one::<PathBuf>("config").cloned() {
+            let overridden = |flags: &[OsString]| {
+                let probe = args[..1].iter().chain(flags).chain(&args[1..]);
+                Cli::clap_command()
+                    .try_get_matches_from(probe)
+                    .is_err_and(|e| e.kind() == ErrorKind::ArgumentConflict)
+            };
+            let mut merged = args[..1].to_vec();
+            for (id, flags) in ConfigFile::load_flags(&path)? {
+                let flags: Vec<OsString> = flags.into_iter().map(OsString::from).collect();
+                if takes(&id)
+                    && matches.value_source(&id) != Some(ValueSource::CommandLine)
+                    && !overridden(&flags)
+                {
+                    merged.extend(flags);
+                }
+            }
+            merged.extend_from_slice(&args[1..]);
+            matches = Cli::clap_command()
+                .try_get_matches_from(merged)
+                .with_context(|| format!("Invalid options in config file {}", path.display()))?;
+        }
+        if let Some(command) = &command {
+            for arg in Cli::command().get_arguments() {
+                let id = arg.get_id().as_str();
+                let needs = if FULL_RUN_OPTIONS.contains(&id) {
+                    "; it needs a full run without a command"
+                } else {
+                    ""
+                };
+                ensure!(
+                    takes(id) || matches.value_source(id) != Some(ValueSource::CommandLine),
+                    "--{} does not apply to the {} command{needs}",
+                    arg.get_long().unwrap_or(id),
+                    command.name()
+                );
+            }
+        }
+        let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        cli.apply_command();
+        Ok(cli)
+    }
+
+    /// Returns the command-line parser, which takes options both before and
+    /// after a command
+    pub(crate) fn clap_command() -> clap::Command {
+        Cli::command().mut_args(|arg| arg.global(true))
+    }
+
+    /// Expresses `fit` and `plot` through the options of a full run
+    pub(crate) fn apply_command(&mut self) {
+        match &self.command {
+            Some(Command::Fit { embedding }) => {
+                self.save_embedding = Some(embedding.clone());
+                self.no_plot = true;
+            }
+            Some(Command::Plot { embedding, .. }) => {
+                self.load_embedding = Some(embedding.clone());
+            }
+            Some(Command::Metrics { .. } | Command::Compare { .. }) | None => {}
+        }
+    }
+
+    /// Checks that the PaCMAP parameters describe a usable configuration
+    ///
+    /// PaCMAP panics or fails deep inside the optimization on some of these
+    /// values, so they are rejected up front with a descriptive message.
+    ///
+    /// # Errors
+    /// Returns an error if any parameter is out of range
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.annotations.is_none() || self.embedding_dimensions == 2,
+            "--annotations can only label points of 2D plots"
+        );
+        if self.split_output {
+            ensure!(
+                self.embedding_dimensions == 2 && self.plot_type == PlotType::Scatter,
+                "--split-output only splits 2D scatter plots"
+            );
+            ensure!(
+                self.input.is_empty() || self.input.len() == 2,
+                "--split-output with --input needs exactly two inputs, the training and the test \
+                 data, got {}",
+                self.input.len()
+            );
+        }
+        if let Some(runs) = self.ensemble {
+            ensure!(
+                runs >= 2,
+                "--ensemble needs at least 2 runs to compare, got {runs}"
+            );
+            ensure!(
+                self.command.is_none(),
+                "--ensemble needs a full run, as fit writes no plot and plot replots a single \
+                 saved embedding"
+            );
+            ensure!(
+                self.embedding_dimensions == 2 && self.plot_type == PlotType::Scatter,
+                "--ensemble draws its runs as 2D scatter plots"
+            );
+        }
+        if self.summary_only {
+            ensure!(
+                matches!(self.command, None | Some(Command::Plot { .. })),
+                "--summary-only replaces the outputs of a full run or plot, so it cannot be used \
+                 with fit, metrics or compare"
+            );
+            ensure!(
+                self.has_labels() || self.load_embedding.is_some(),
+                "--summary-only summarizes classes, so the data needs labels; pass --label-column"
+            );
+        }
+        if self.transform_test {
+            bail!(
+                "--transform-test is not supported: pacmap::fit_transform returns only the \
+                 embedding and optional snapshots, and the crate keeps no fitted state that \
+                 could place unseen points. Embed the training and test splits together instead"
+            );
+        }
+        if let Some(path) = &self.graph_out {
+            ensure!(
+                path.extension().is_none_or(|extension| extension != "npz"),
+                "--graph-out cannot write .npz archives, as ndarray-npy is built without zip \
+                 support; pass a .npy path for the indices, and the distances are written beside it"
+            );
+        }
+        if self.arrow_out.is_some() {
+            bail!(
+                "--arrow-out is not supported yet, as writing Arrow IPC files needs the arrow \
+                 crate. Use --npy-out for typed float32 and int32 arrays, which polars and pandas \
+                 read through numpy, or --embedding-out for CSV"
+            );
+        }
+        // Options of the built-in datasets and of input files cannot be mixed
+        if self.input.is_empty() {
+            for (set, flag) in [
+                (self.color_by_source, "--color-by-source"),
+                (self.label_column.is_some(), "--label-column"),
+                (self.color_column.is_some(), "--color-column"),
+                (self.features.is_some(), "--features"),
+            ] {
+                ensure!(!set, "{flag} requires --input");
+            }
+            self.split_sizes().check(self.dataset)?;
+            ensure!(
+                !self.hover_thumbnails || self.dataset != Dataset::Cifar10,
+                "--hover-thumbnails draws 28x28 grayscale images and does not support the \
+                 color images of CIFAR-10"
+            );
+        } else {
+            for (set, flag) in [
+                (self.refresh, "--refresh"),
+                (self.train_len.is_some(), "--train-len"),
+                (self.test_len.is_some(), "--test-len"),
+                (self.hover_thumbnails, "--hover-thumbnails"),
+                (self.load_embedding.is_some(), "--load-embedding"),
+            ] {
+                ensure!(!set, "{flag} cannot be used with --input");
+            }
+        }
+        if !self.classes.is_empty() {
+            ensure!(
+                self.has_labels(),
+                "--classes selects points by label and needs --label-column with --input"
+            );
+            ensure!(
+                self.plot_type == PlotType::Scatter || self.classes_before_fit,
+                "--plot-type density does not draw classes; add --classes-before-fit to embed \
+                 only the selected classes"
+            );
+        }
+        ensure!(
+            !self.neighbor_confusion || self.has_labels(),
+            "--neighbor-confusion compares classes and needs --label-column with --input"
+        );
+        if self.knn_agreement {
+            ensure!(
+                self.has_labels(),
+                "--knn-agreement compares votes with labels and needs --label-column with --input"
+            );
+            ensure!(
+                self.plot_type == PlotType::Scatter,
+                "--knn-agreement marks points and needs --plot-type scatter"
+            );
+        }
+        if self.metric == Metric::Manhattan {
+            for (set, flag) in [
+                (self.neighbors_file.is_some(), "--neighbors-file"),
+                (self.grid, "--grid"),
+                (self.verify_reproducible, "--verify-reproducible"),
+            ] {
+                ensure!(!set, "--metric manhattan cannot be used with {flag}");
+            }
+        }
+        if let Some(n) = self.sample {
+            ensure!(n >= 2, "--sample must be at least 2 rows, got {n}");
+        }
+        ensure!(
+            self.sample_strategy != SampleStrategy::Stratified || self.has_labels(),
+            "--sample-strategy stratified samples each class and needs --label-column with --input"
+        );
+        if let Some(k) = self.pca {
+            ensure!(k > 0, "--pca must keep at least 1 component");
+        }
+        for point in self.grid_points() {
+            validate_pairs(&point)?;
+        }
+        ensure!(
+            self.learning_rate.is_finite() && self.learning_rate > 0.0,
+            "--learning-rate must be a positive number, got {}",
+            self.learning_rate
+        );
+        if !self.iterations.is_empty() {
+            ensure!(
+                self.iterations.len() == 3,
+                "--iterations takes one count per optimization phase, e.g. 100,100,250, got {} values",
+                self.iterations.len()
+            );
+            ensure!(
+                self.iterations.iter().sum::<usize>() > 0,
+                "--iterations must run at least one iteration"
+            );
+        }
+        ensure!(
+            matches!(self.embedding_dimensions, 2 | 3),
+            "--embedding-dimensions must be 2 or 3 to plot the embedding, got {}",
+            self.embedding_dimensions
+        );
+        if self.compare_with.is_some() {
+            ensure!(
+                self.embedding_dimensions == 2,
+                "--compare-with plots the embeddings side by side and needs --embedding-dimensions 2"
+            );
+        }
+        if let Some(size) = self.marker_size {
+            ensure!(size >= 1, "--marker-size must be at least 1 pixel");
+        }
+        if let Some(opacity) = self.marker_opacity {
+            ensure!(
+                opacity > 0.0 && opacity <= 1.0,
+                "--marker-opacity must be greater than 0 and at most 1, got {opacity}"
+            );
+        }
+        if let Some(max) = self.max_points_per_class {
+            ensure!(max >= 1, "--max-points-per-class must be at least 1");
+        }
+        if let Some(max) = self.max_plot_points {
+            ensure!(max >= 1, "--max-plot-points must be at least 1");
+        }
+        if self.centroids {
+            ensure!(
+                self.embedding_dimensions == 2 && self.plot_type == PlotType::Scatter,
+                "--centroids marks classes in 2D scatter plots and needs --embedding-dimensions 2 \
+                 with --plot-type scatter"
+            );
+        }
+        if self.animate {
+            ensure!(
+                self.embedding_dimensions == 2
+                    && self.plot_type == PlotType::Scatter
+                    && self.format == OutputFormat::Html,
+                "--animate needs --embedding-dimensions 2 with --plot-type scatter and --format html"
+            );
+            ensure!(
+                (2..=MAX_ANIMATION_FRAMES).contains(&self.animation_frames),
+                "--animation-frames must be between 2 and {MAX_ANIMATION_FRAMES}, got {}",
+                self.animation_frames
+            );
+        }
+        if self.plot_type == PlotType::Density {
+            ensure!(
+                self.embedding_dimensions == 2,
+                "--plot-type density needs --embedding-dimensions 2"
+            );
+        }
+        for (range, flag) in [(&self.x_range, "--x-range"), (&self.y_range, "--y-range")] {
+            if !range.is_empty() {
+                ensure!(
+                    range.len() == 2 && range.iter().all(|v| v.is_finite()) && range[0] < range[1],
+                    "{flag} takes a minimum and a larger maximum, e.g. -30,30"
+                );
+            }
+        }
+        for (scale, range, flag) in [
+            (self.x_scale, &self.x_range, "--x-scale"),
+            (self.y_scale, &self.y_range, "--y-scale"),
+        ] {
+            if scale != AxisScale::Linear {
+                ensure!(
+                    self.plot_type == PlotType::Scatter,
+                    "{flag} scales scatter plots and needs --plot-type scatter"
+                );
+            }
+            if scale == AxisScale::Log {
+                ensure!(
+                    range.iter().all(|&v| v > 0.0),
+                    "{flag} log needs a positive axis range, got {range:?}; use symlog to include \
+                     zero and negative coordinates"
+                );
+            }
+        }
+        ensure!(
+            self.trustworthiness_k > 0,
+            "--trustworthiness-k must be at least 1"
+        );
+        ensure!(
+            self.knn_accuracy_k > 0,
+            "--knn-accuracy-k must be at least 1"
+        );
+        ensure!(
+            self.image_width > 0 && self.image_height > 0,
+            "--image-width and --image-height must be at least 1 pixel"
+        );
+        ensure!(
+            self.image_scale.is_finite() && self.image_scale > 0.0,
+            "--image-scale must be a positive number, got {}",
+            self.image_scale
+        );
+        Ok(())
+    }
+
+    /// Returns the most detailed level of log messages to show
+    pub fn log_level(&self) -> Level {
+        match (self.quiet, self.verbose) {
+            (0, 0) => Level::INFO,
+            (0, 1) => Level::DEBUG,
+            (0, _) => Level::TRACE,
+            (1, _) => Level::WARN,
+            _ => Level::ERROR,
+        }
+    }
+
+    /// Reports a final result, printing it to stdout with `--quiet` as it
+    /// would otherwise be hidden
+    pub(crate) fn report(&self, message: &str) {
+        if self.quiet > 0 {
+            println!("{message}");
+        } else {
+            info!("{message}");
+        }
+    }
+
+    /// Reports a quality score like [`Cli::report`], attaching its value as
+    /// fields for JSON logs
+    ///
+    /// # Arguments
+    /// * `algorithm` - Algorithm that produced the scored embedding, if known
+    /// * `metric` - Name of the score
+    /// * `k` - Number of neighbors the score considers
+    /// * `value` - The score
+    /// * `message` - Human-readable report of the score
+    pub(crate) fn report_metric(
+        &self,
+        algorithm: Option<&str>,
+        metric: &str,
+        k: usize,
+        value: f64,
+        message: &str,
+    ) {
+        if self.quiet > 0 {
+            println!("{message}");
+        } else {
+            info!(algorithm, metric, k, value, "{message}");
+        }
+    }
+
+    /// Returns how the dataset's cached files are used, from `--refresh` and `--offline`
+    pub(crate) fn cache_policy(&self) -> CachePolicy {
+        match (self.refresh, self.offline) {
+            (true, _) => CachePolicy::Refresh,
+            (_, true) => CachePolicy::Offline,
+            _ => CachePolicy::Reuse,
+        }
+    }
+
+    /// Returns the number of images loaded from each split of the dataset
+    pub(crate) fn split_sizes(&self) -> SplitSizes {
+        let full = SplitSizes::full(self.dataset);
+        SplitSizes {
+            train: self.train_len.unwrap_or(full.train),
+            test: self.test_len.unwrap_or(full.test),
+        }
+    }
+
+    /// Returns the appearance settings of the plot
+    pub(crate) fn plot_style(&self) -> PlotStyle {
+        PlotStyle {
+            palette: self.palette,
+            x_label: self.x_label.clone(),
+            y_label: self.y_label.clone(),
+            centroids: self.centroids,
+            centroid_spread: self.centroid_spread,
+            classes: (!self.classes.is_empty() && !self.classes_before_fit)
+                .then(|| self.classes.clone()),
+            shown_rows: None,
+            x_range: axis_range(&self.x_range),
+            y_range: axis_range(&self.y_range),
+            x_scale: self.x_scale,
+            y_scale: self.y_scale,
+            equal_aspect: self.equal_aspect,
+            marker_size: self.marker_size,
+            marker_opacity: self.marker_opacity,
+            max_points_per_class: self.max_points_per_class,
+            max_points: self.max_plot_points,
+            knn_predictions: None,
+            small_classes_on_top: self.small_classes_on_top,
+            outline_small_classes: self.outline_small_classes,
+            annotations: Vec::new(),
+            webgl: self.webgl,
+        }
+    }
+
+    /// Returns whether `plot --watch` asked to replot the saved embedding
+    /// whenever it changes
+    pub fn watches(&self) -> bool {
+        matches!(self.command, Some(Command::Plot { watch: true, .. }))
+    }
+
+    /// Returns whether the data comes with class labels: built-in datasets and
+    /// LIBSVM files always do, and CSV files given a `--label-column`
+    pub(crate) fn has_labels(&self) -> bool {
+        self.label_column.is_some()
+            || self
+                .input
+                .iter()
+                .all(|input| matches!(input, Input::Libsvm(_)))
+    }
+
+    /// Returns the path the visualization is written to
+    pub(crate) fn output_path(&self) -> PathBuf {
+        match &self.output {
+            Some(path) => path.clone(),
+            None => PathBuf::from(format!("pacmap_visualization.{}", self.format.extension())),
+        }
+    }
+
+    /// Returns the path the run manifest is written to
+    ///
+    /// The manifest goes next to the visualization. Without one it is named
+    /// after the first file the run writes, such as `n15.manifest.json` for
+    /// `fit n15.json`, so runs saving to the same directory each keep their
+    /// own manifest; a run writing no files at all puts it where the
+    /// visualization would have gone.
+    pub(crate) fn manifest_path(&self) -> PathBuf {
+        let outputs = [
+            &self.save_embedding,
+            &self.embedding_out,
+            &self.npy_out,
+            &self.graph_out,
+        ];
+        match outputs.into_iter().flatten().next() {
+            Some(path) if self.no_plot => {
+                let stem = path.file_stem().unwrap_or(path.as_os_str());
+                path.with_file_name(format!("{}.manifest.json", stem.to_string_lossy()))
+            }
+            _ => self.output_path().with_file_name("manifest.json"),
+        }
+    }
+
+    /// Returns the parameter combinations to embed
+    ///
+    /// Outside of `--grid` mode this is the single combination given by
+    /// `--neighbors`, `--mid-near-ratio` and `--far-pair-ratio`. In grid mode
+    /// each candidate list defaults to that single value when not given.
+    pub(crate) fn grid_points(&self) -> Vec<GridPoint> {
+        fn candidates<T: Copy>(list: &[T], value: T) -> Vec<T> {
+            if list.is_empty() {
+                vec![value]
+            } else {
+                list.to_vec()
+            }
+        }
+
+        grid_points(
+            &candidates(&self.grid_neighbors, self.neighbors),
+            &candidates(&self.grid_mid_near_ratios, self.mid_near_ratio),
+            &candidates(&self.grid_far_pair_ratios, self.far_pair_ratio),
+        )
+    }
+
+    /// Returns the name of the embedded dataset or input files
+    pub(crate) fn dataset_name(&self) -> String {
+        if self.input.is_empty() {
+            self.dataset.display_name().to_string()
+        } else {
+            let names: Vec<_> = self.input.iter().map(Input::name).collect();
+            names.join(", ")
+        }
+    }
+
+    /// Returns the number of rows to sample, if any
+    ///
+    /// Grid searches, reproducibility checks and previews sample a default
+    /// number of rows when `--sample` is not given.
+    pub(crate) fn sample_size(&self) -> Option<usize> {
+        self.sample
+            .or(self.grid.then_some(DEFAULT_SAMPLE_SIZE))
+            .or(self.verify_reproducible.then_some(VERIFY_SAMPLE_SIZE))
+            .or(self.preview.then_some(PREVIEW_SAMPLE_SIZE))
+    }
+
+    /// Returns the number of principal components to reduce the input to, if
+    /// any; previews skip the reduction
+    pub(crate) fn pca_components(&self) -> Option<usize> {
+        self.pca.filter(|_| !self.preview)
+    }
+
+    /// Returns the run settings recorded alongside a saved embedding
+    pub(crate) fn run_config(&self) -> RunConfig {
+        RunConfig {
+            dataset: self.dataset_name(),
+            sample: self.sample_size(),
+            // Without a sample every row is embedded, however rows would be chosen
+            sample_strategy: self
+                .sample_size()
+                .map_or(SampleStrategy::Random, |_| self.sample_strategy)
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+            normalize: self
+                .normalize
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+            metric: self
+                .metric
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+            pca: self.pca_components(),
+            classes: self.classes_before_fit.then(|| self.classes.clone()),
+            neighbors: self.neighbors,
+            mid_near_ratio: self.mid_near_ratio,
+            far_pair_ratio: self.far_pair_ratio,
+            embedding_dimensions: self.embedding_dimensions,
+            seed: self.seed,
+        }
+    }
+
+    /// Builds the PaCMAP configuration described by these options
+    pub(crate) fn pacmap_config(&self) -> Configuration {
+        let mut config = Configuration::builder()
+            .embedding_dimensions(self.embedding_dimensions)
+            .override_neighbors(self.neighbors)
+            .mid_near_ratio(self.mid_near_ratio)
+            .far_pair_ratio(self.far_pair_ratio)
+            .maybe_seed(self.seed)
+            .learning_rate(self.learning_rate)
+            .build();
+        if let [attraction, local, global] = self.iterations[..] {
+            config.num_iters = (attraction, local, global);
+        } else if self.preview {
+            config.num_iters = PREVIEW_ITERATIONS;
+        }
+        if self.animate {
+            let (attraction, local, global) = config.num_iters;
+            config.snapshots = Some(animation_iterations(
+                attraction + local + global,
+                self.animation_frames,
+            ));
+        }
+        config
+    }
+}
+
+/// Returns the (min, max) range given as a two-element list, if one was given
+pub(crate) fn axis_range(range: &[f64]) -> Option<(f64, f64)> {
+    match *range {
+        [min, max] => Some((min, max)),
+        _ => None,
+    }
+}
+
+/// Returns evenly spaced iterations at which to capture the layout for an animation
+///
+/// # Arguments
+/// * `total` - Number of optimization iterations
+/// * `frames` - Number of layouts to capture, at least 2
+///
+/// # Returns
+/// Up to `frames` distinct iterations from 0, the initialization, to `total`
+pub(crate) fn animation_iterations(total: usize, frames: usize) -> Vec<usize> {
+    let mut iterations: Vec<_> = (0..frames).map(|i| i * total / (frames - 1)).collect();
+    iterations.dedup();
+    iterations
+}
+
+/// Checks that a combination of pair parameters is usable by PaCMAP
+///
+/// # Errors
+/// Returns an error if any parameter is out of range
+pub(crate) fn validate_pairs(point: &GridPoint) -> Result<()> {
+    let GridPoint {
+        neighbors,
+        mid_near_ratio,
+        far_pair_ratio,
+    } = *point;
+    ensure!(neighbors > 0, "Neighbor count must be at least 1");
+    ensure!(
+        mid_near_ratio.is_finite() && mid_near_ratio >= 0.0,
+        "Mid-near ratio must be a non-negative number, got {mid_near_ratio}"
+    );
+    ensure!(
+        far_pair_ratio.is_finite() && far_pair_ratio > 0.0,
+        "Far pair ratio must be a positive number, got {far_pair_ratio}"
+    );
+    ensure!(
+        (neighbors as f32 * far_pair_ratio).round() >= 1.0,
+        "Far pair ratio {far_pair_ratio} with {neighbors} neighbors yields no far pairs"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed;
+    use ndarray::Array2;
+
+    /// Builds a small dataset of three well-separated clusters
+    fn synthetic_data() -> Array2<f32> {
+        Array2::from_shape_fn((150, 8), |(i, j)| {
+            let cluster = (i % 3) as f32 * 10.0;
+            let jitter = ((i * 31 + j * 17) % 97) as f32 / 97.0;
+            cluster + jitter
+        })
+    }
+
+    #[test]
+    fn same_seed_reproduces_embedding() -> Result<()> {
+        let cli = Cli::parse_from(["pacmap-rs-example", "--seed", "42"]);
+        cli.validate()?;
+        let x = synthetic_data();
+
+        let first = embed(x.view(), cli.pacmap_config())?;
+        let second = embed(x.view(), cli.pacmap_config())?;
+
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    /// Parses `args` after the program name
+    fn parse(args: &[&str]) -> Result<Cli> {
+        let program = ["pacmap-rs-example"].iter().chain(args);
+        Cli::parse_with_config_from(program.map(OsString::from).collect())
+    }
+
+    #[test]
+    fn commands_take_options_before_and_after_them() -> Result<()> {
+        for args in [
+            &["--seed", "3", "fit", "out.json"][..],
+            &["fit", "out.json", "--seed", "3"],
+        ] {
+            let cli = parse(args)?;
+            assert_eq!(cli.save_embedding, Some(PathBuf::from("out.json")));
+            assert!(cli.no_plot);
+            assert_eq!(cli.seed, Some(3));
+        }
+
+        let cli = parse(&["plot", "--watch", "saved.json", "--palette", "viridis"])?;
+        assert_eq!(cli.load_embedding, Some(PathBuf::from("saved.json")));
+        assert!(cli.watches());
+        assert_eq!(cli.palette, Palette::Viridis);
+
+        let cli = parse(&["-q", "metrics", "saved.json", "--knn-accuracy-k", "5"])?;
+        assert!(matches!(cli.command, Some(Command::Metrics { .. })));
+        assert_eq!((cli.quiet, cli.knn_accuracy_k), (1, 5));
+
+        let cli = parse(&[
+            "compare",
+            "left.json",
+            "right.json",
+            "--output",
+            "both.html",
+        ])?;
+        assert!(matches!(cli.command, Some(Command::Compare { .. })));
+        assert_eq!(cli.output, Some(PathBuf::from("both.html")));
+        Ok(())
+    }
+
+    #[test]
+    fn commands_reject_options_they_do_not_take() {
+        for args in [
+            &["metrics", "saved.json", "--neighbors", "5"][..],
+            &["--neighbors", "5", "metrics", "saved.json"],
+            &["plot", "saved.json", "--seed", "1"],
+            &["fit", "out.json", "--output", "plot.html"],
+            &["fit", "out.json", "--save-embedding", "other.json"],
+            &[
+                "compare",
+                "left.json",
+                "right.json",
+                "--annotations",
+                "notes.csv",
+            ],
+        ] {
+            assert!(parse(args).is_err(), "{args:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn commands_leave_out_config_options_they_do_not_take() -> Result<()> {
+        let config = "neighbors = 15\npalette = \"viridis\"\nno-plot = true\n";
+        let cli = parse_with_config_file(config, &["plot", "saved.json"])?;
+        assert_eq!((cli.neighbors, cli.palette), (10, Palette::Viridis));
+        assert!(!cli.no_plot);
+
+        let cli = parse_with_config_file(config, &["fit", "out.json"])?;
+        assert_eq!((cli.neighbors, cli.palette), (15, Palette::Portland));
+        Ok(())
+    }
+
+    #[test]
+    fn command_options_classify_every_option() {
+        let command = Cli::command();
+        let lists = [
+            &GENERAL_OPTIONS[..],
+            &FIT_OPTIONS,
+            &PLOT_OPTIONS,
+            &REPLOT_OPTIONS,
+            &FULL_RUN_OPTIONS,
+        ];
+        for id in lists.into_iter().flatten() {
+            assert!(
+                command.get_arguments().any(|arg| arg.get_id() == id),
+                "{id} is not an option"
+            );
+        }
+        for id in FULL_RUN_OPTIONS {
+            assert!(
+                !lists[..4].iter().any(|list| list.contains(&id)),
+                "{id} is listed both as taken by a command and only by full runs"
+            );
+        }
+        for arg in command.get_arguments() {
+            let id = arg.get_id().as_str();
+            assert!(
+                lists.iter().any(|list| list.contains(&id)),
+                "--{} is not listed as taken by any command or only by full runs",
+                arg.get_long().unwrap_or(id)
+            );
+        }
+    }
+
+    /// Parses `args` after the program name with a config file holding `toml`
+    fn parse_with_config_file(toml: &str, args: &[&str]) -> Result<Cli> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("run.toml");
+        std::fs::write(&path, toml)?;
+        let mut argv = vec![
+            OsString::from("pacmap-rs-example"),
+            "--config".into(),
+            path.into(),
+        ];
+        argv.extend(args.iter().map(OsString::from));
+        Cli::parse_with_config_from(argv)
+    }
+
+    #[test]
+    fn config_fills_in_options_left_unset() -> Result<()> {
+        let cli = parse_with_config_file(
+            "seed = 4\nneighbors = 15\nx-range = [-30, 30]\nwebgl = true\n",
+            &["--seed", "5"],
+        )?;
+        assert_eq!(cli.seed, Some(5));
+        assert_eq!(cli.neighbors, 15);
+        assert_eq!(cli.x_range, [-30.0, 30.0]);
+        assert!(cli.webgl);
+        Ok(())
+    }
+
+    #[test]
+    fn config_options_conflicting_with_flags_are_left_out() -> Result<()> {
+        let cli = parse_with_config_file("no-plot = true\nseed = 4\n", &["--format", "png"])?;
+        assert!(!cli.no_plot);
+        assert_eq!((cli.format, cli.seed), (OutputFormat::Png, Some(4)));
+        Ok(())
+    }
+
+    #[test]
+    fn config_conflicting_options_are_rejected() {
+        assert!(parse_with_config_file("no-plot = true\nformat = \"png\"\n", &[]).is_err());
+        assert!(parse_with_config_file("animation-frames = 5\n", &[]).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Baseline embeddings to compare PaCMAP against
+//!
+//! Embeds the same data with another algorithm so both layouts can be shown
+//! side by side and scored with the same metrics.
+
+use crate::pca::pca_reduce;
+use anyhow::Result;
+use clap::ValueEnum;
+use ndarray::{Array2, ArrayView2};
+use serde::Deserialize;
+
+/// Algorithms whose embedding can be shown next to PaCMAP's
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Comparison {
+    /// Linear projection onto the leading principal components
+    Pca,
+}
+
+impl Comparison {
+    /// Returns the name used for the algorithm in logs and plots
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Comparison::Pca => "PCA",
+        }
+    }
+
+    /// Embeds the data with the algorithm
+    ///
+    /// # Arguments
+    /// * `x` - Input data of shape (n_samples, n_features)
+    /// * `dims` - Number of dimensions of the embedding
+    ///
+    /// # Returns
+    /// Embedding of shape (n_samples, dims)
+    ///
+    /// # Errors
+    /// Returns an error if the algorithm fails
+    pub fn embed(self, x: ArrayView2<f32>, dims: usize) -> Result<Array2<f32>> {
+        match self {
+            Comparison::Pca => pca_reduce(x, dims),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Experiment settings read from a TOML file
+//!
+//! Lets a fixed experiment setup be kept in a file versioned alongside the
+//! code instead of being retyped as command-line flags on every run. Keys are
+//! the command-line option names without their leading dashes:
+//!
+//! ```toml
+//! dataset = "fashion-mnist"
+//! sample = 10000
+//! seed = 42
+//! neighbors = 15
+//! embedding-out = "runs/fashion/embedding.csv"
+//! ```
+//!
+//! The options are handed to the command-line parser as the flags they stand
+//! for, so a file is checked for conflicting options just like a command line.
+
+use crate::compare::Comparison;
+use crate::dataset::Dataset;
+use crate::distance::Metric;
+use crate::input::{FeatureColumns, Input, LabelColumn};
+use crate::normalize::Normalize;
+use crate::plot::{AxisScale, OutputFormat, Palette, PlotType};
+use crate::sample::SampleStrategy;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Options a config file can set, which fill in those left off the command line
+///
+/// The file is checked against these fields before its options are passed on
+/// as flags by [`ConfigFile::load_flags`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    /// Dataset to download and embed
+    pub dataset: Option<Dataset>,
+
+    /// Read the dataset only from the cache, never downloading it
+    pub offline: Option<bool>,
+
+    /// Number of images taken from the dataset's training split
+    pub train_len: Option<usize>,
+
+    /// Number of images taken from the dataset's test split
+    pub test_len: Option<usize>,
+
+    /// Largest number of bytes a single dataset download may have
+    pub max_download_size: Option<u64>,
+
+    /// Local files to embed instead of a dataset, as `<format>:<path>`
+    pub input: Option<Vec<Input>>,
+
+    /// Color the points by the input file they came from
+    pub color_by_source: Option<bool>,
+
+    /// CSV column holding integer class labels
+    pub label_column: Option<LabelColumn>,
+
+    /// CSV column of continuous values to color the points by
+    pub color_column: Option<LabelColumn>,
+
+    /// CSV columns holding the features
+    pub features: Option<FeatureColumns>,
+
+    /// Embed a small sample quickly at reduced quality
+    pub preview: Option<bool>,
+
+    /// Labels of the classes to plot
+    pub classes: Option<Vec<i32>>,
+
+    /// Drop the rows of other classes before embedding
+    pub classes_before_fit: Option<bool>,
+
+    /// Drop rows that exactly repeat an earlier row
+    pub dedup: Option<bool>,
+
+    /// Drop features that have the same value in every row
+    pub drop_constant: Option<bool>,
+
+    /// Number of rows randomly sampled before embedding
+    pub sample: Option<usize>,
+
+    /// How the sampled rows are chosen
+    pub sample_strategy: Option<SampleStrategy>,
+
+    /// Normalization applied to the input
+    pub normalize: Option<Normalize>,
+
+    /// Distance metric deciding which samples are neighbors
+    pub metric: Option<Metric>,
+
+    /// Number of principal components the input is reduced to
+    pub pca: Option<usize>,
+
+    /// Number of nearest neighbors used to form neighbor pairs
+    pub neighbors: Option<usize>,
+
+    /// NumPy file of precomputed neighbor indices replacing PaCMAP's search
+    pub neighbors_file: Option<PathBuf>,
+
+    /// Ratio of mid-near pairs to nearest neighbor pairs
+    pub mid_near_ratio: Option<f32>,
+
+    /// Ratio of far pairs to nearest neighbor pairs
+    pub far_pair_ratio: Option<f32>,
+
+    /// Step size of PaCMAP's optimizer
+    pub learning_rate: Option<f32>,
+
+    /// Iterations of PaCMAP's three optimization phases
+    pub iterations: Option<Vec<usize>>,
+
+    /// Number of dimensions in the embedding
+    pub embedding_dimensions: Option<usize>,
+
+    /// Number of worker threads; 0 uses all cores
+    pub threads: Option<usize>,
+
+    /// Seed of PaCMAP's random sampling and initialization
+    pub seed: Option<u64>,
+
+    /// Fit seeded runs again instead of reusing a cached embedding
+    pub no_cache: Option<bool>,
+
+    /// Number of nearest neighbors used to score trustworthiness
+    pub trustworthiness_k: Option<usize>,
+
+    /// Number of embedded neighbors voting on each point's class
+    pub knn_accuracy_k: Option<usize>,
+
+    /// Also plot which classes each class's nearest embedded neighbors belong to
+    pub neighbor_confusion: Option<bool>,
+
+    /// Mark points whose nearest embedded neighbors vote for another class
+    pub knn_agreement: Option<bool>,
+
+    /// Embed once per combination of the grid's parameter lists
+    pub grid: Option<bool>,
+
+    /// Neighbor counts tried by the grid search
+    pub grid_neighbors: Option<Vec<usize>>,
+
+    /// Mid-near pair ratios tried by the grid search
+    pub grid_mid_near_ratios: Option<Vec<f32>>,
+
+    /// Far pair ratios tried by the grid search
+    pub grid_far_pair_ratios: Option<Vec<f32>>,
+
+    /// CSV file the grid search summary is written to
+    pub grid_out: Option<PathBuf>,
+
+    /// Number of runs with consecutive seeds plotted as a grid of panels
+    pub ensemble: Option<usize>,
+
+    /// Another algorithm whose embedding is plotted beside PaCMAP's
+    pub compare_with: Option<Comparison>,
+
+    /// Path of the visualization
+    pub output: Option<PathBuf>,
+
+    /// Also plot the training and test data on their own
+    pub split_output: Option<bool>,
+
+    /// Skip the visualization
+    pub no_plot: Option<bool>,
+
+    /// Print a table of the classes instead of plotting
+    pub summary_only: Option<bool>,
+
+    /// How to draw the embedding
+    pub plot_type: Option<PlotType>,
+
+    /// Title shown above the plot
+    pub title: Option<String>,
+
+    /// Title of the plot's horizontal axis
+    pub x_label: Option<String>,
+
+    /// Title of the plot's vertical axis
+    pub y_label: Option<String>,
+
+    /// Fixed range of the horizontal axis, as `[min, max]`
+    pub x_range: Option<Vec<f64>>,
+
+    /// Fixed range of the vertical axis, as `[min, max]`
+    pub y_range: Option<Vec<f64>>,
+
+    /// Scale of the horizontal axis of 2D scatter plots
+    pub x_scale: Option<AxisScale>,
+
+    /// Scale of the vertical axis of 2D scatter plots
+    pub y_scale: Option<AxisScale>,
+
+    /// Draw both axes of 2D plots at the same scale
+    pub equal_aspect: Option<bool>,
+
+    /// Diameter of the point markers in pixels
+    pub marker_size: Option<usize>,
+
+    /// Opacity of the point markers from 0 to 1
+    pub marker_opacity: Option<f64>,
+
+    /// Largest number of points drawn per class
+    pub max_points_per_class: Option<usize>,
+
+    /// Largest number of points drawn in total
+    pub max_plot_points: Option<usize>,
+
+    /// Draw classes from the largest to the smallest
+    pub small_classes_on_top: Option<bool>,
+
+    /// Outline the markers of small classes
+    pub outline_small_classes: Option<bool>,
+
+    /// CSV file of coordinates some points start the optimization at
+    pub anchors: Option<PathBuf>,
+
+    /// CSV file of `index,text` notes drawn next to individual points
+    pub annotations: Option<PathBuf>,
+
+    /// Draw the points of 2D scatter plots with WebGL
+    pub webgl: Option<bool>,
+
+    /// Mark each class's centroid
+    pub centroids: Option<bool>,
+
+    /// Outline one standard deviation around each centroid
+    pub centroid_spread: Option<bool>,
+
+    /// Color scale of continuous values and density heatmaps
+    pub palette: Option<Palette>,
+
+    /// Show each point's source image when hovering over it
+    pub hover_thumbnails: Option<bool>,
+
+    /// Animate the layout over PaCMAP's iterations in the HTML plot
+    pub animate: Option<bool>,
+
+    /// Number of layouts captured for the animation
+    pub animation_frames: Option<usize>,
+
+    /// File format of the visualization
+    pub format: Option<OutputFormat>,
+
+    /// Width of png and svg images in pixels
+    pub image_width: Option<usize>,
+
+    /// Height of png and svg images in pixels
+    pub image_height: Option<usize>,
+
+    /// Pixel density multiplier of png images
+    pub image_scale: Option<f64>,
+
+    /// CSV file the embedding coordinates are written to
+    pub embedding_out: Option<PathBuf>,
+
+    /// NumPy file the embedding is written to
+    pub npy_out: Option<PathBuf>,
+
+    /// NumPy file the neighbor graph's indices are written to
+    pub graph_out: Option<PathBuf>,
+
+    /// JSON file the embedding is saved to for replotting
+    pub save_embedding: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Reads a config file as the command-line flags its options stand for
+    ///
+    /// # Arguments
+    /// * `path` - Config file
+    ///
+    /// # Returns
+    /// Each option's field name with the flags setting it, such as
+    /// `("x_range", ["--x-range=-30", "--x-range=30"])`; a switch set to
+    /// `false` has no flags
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, is not valid TOML, or sets
+    /// an unknown option or an invalid value
+    pub fn load_flags(path: &Path) -> Result<Vec<(String, Vec<String>)>> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Error reading config file {}", path.display()))?;
+        let parse = || -> Result<Table> {
+            let table: Table = toml::from_str(&text)?;
+            Self::deserialize(table.clone())?;
+            Ok(table)
+        };
+        let table =
+            parse().with_context(|| format!("Error parsing config file {}", path.display()))?;
+        Ok(table
+            .into_iter()
+            .map(|(key, value)| {
+                let values = match value {
+                    Value::Array(values) => values,
+                    value => vec![value],
+                };
+                let flags = values
+                    .into_iter()
+                    .filter_map(|value| match value {
+                        Value::Boolean(true) => Some(format!("--{key}")),
+                        Value::Boolean(false) => None,
+                        Value::String(text) => Some(format!("--{key}={text}")),
+                        value => Some(format!("--{key}={value}")),
+                    })
+                    .collect();
+                (key.replace('-', "_"), flags)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,44 @@
+//! Detection of constant input features
+//!
+//! A feature with the same value in every row carries no information: it adds
+//! nothing to any distance, yet still costs memory and time in the neighbor
+//! search, and per-feature normalizations have no spread to scale it by.
+//! Image datasets often have many, such as the always-blank border pixels of
+//! MNIST.
+
+use ndarray::ArrayView2;
+
+/// Finds the columns whose value is the same in every row
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+///
+/// # Returns
+/// The indices of the constant columns, in ascending order
+pub fn constant_columns(x: ArrayView2<f32>) -> Vec<usize> {
+    x.columns()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, column)| {
+            column
+                .first()
+                .is_some_and(|&first| column.iter().all(|&value| value == first))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Returns the columns kept after dropping the constant ones
+///
+/// The position of each kept column in the result is its index in the
+/// reduced data, so the result maps reduced features back to the input's.
+///
+/// # Arguments
+/// * `n_features` - Number of columns of the input
+/// * `constant` - Indices of the constant columns in ascending order, as from
+///   [`constant_columns`]
+pub fn varying_columns(n_features: usize, constant: &[usize]) -> Vec<usize> {
+    (0..n_features)
+        .filter(|column| constant.binary_search(column).is_err())
+        .collect()
+}
@@ -0,0 +1,1022 @@
+//! Downloadable image datasets
+//!
+//! Provides the image datasets the example can embed, along with the
+//! human-readable class names used to label them in the visualization: the
+//! grayscale MNIST-format datasets and the color photos of CIFAR-10.
+//! Downloaded files are kept in a per-user cache directory and verified before
+//! reuse, so later runs work offline. With [`CachePolicy::Offline`], a dataset
+//! that is not cached is an error rather than a download.
+
+use crate::error::PipelineError;
+use crate::progress::Spinner;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use mnist::MnistBuilder;
+use ndarray::{s, Array1, Array2, ArrayViewMut2};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Number of images in the training split of the MNIST-format datasets
+const TRAINING_SET_SIZE: u32 = 60_000;
+
+/// Number of images in the test split of the MNIST-format datasets
+const TEST_SET_SIZE: u32 = 10_000;
+
+/// Number of pixels in each 28x28 image
+const IMAGE_PIXELS: usize = 28 * 28;
+
+/// Number of color values in each 32x32 CIFAR-10 image, one per pixel and channel
+const CIFAR10_FEATURES: usize = 32 * 32 * 3;
+
+/// Number of images in each CIFAR-10 batch file
+const CIFAR10_BATCH_SIZE: usize = 10_000;
+
+/// Extracted CIFAR-10 batch files: five training batches, then the test batch
+const CIFAR10_FILES: [&str; 6] = [
+    "data_batch_1.bin",
+    "data_batch_2.bin",
+    "data_batch_3.bin",
+    "data_batch_4.bin",
+    "data_batch_5.bin",
+    "test_batch.bin",
+];
+
+/// Name of the CIFAR-10 archive holding the batch files
+const CIFAR10_ARCHIVE: &str = "cifar-10-binary.tar.gz";
+
+/// Known-good SHA-256 digest of the CIFAR-10 archive, keyed by file name
+const CIFAR10_CHECKSUMS: [(&str, &str); 1] = [(
+    CIFAR10_ARCHIVE,
+    "c4a38c50a1bc5f3a1c5537f2155ab9d68f9f25eb1ed8d9ddda3db29a59bca1dd",
+)];
+
+/// Number of attempts made to download a dataset before giving up
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Delay before the first download retry, doubled after each further failure
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Extracted files of each dataset with their IDX magic number and exact size
+///
+/// The IDX header encodes the element type and number of dimensions, so the
+/// magic number tells image files (3 dimensions) apart from label files (1).
+const DATASET_FILES: [(&str, u32, u64); 4] = [
+    ("train-images-idx3-ubyte", 0x0803, 16 + 60_000 * 784),
+    ("train-labels-idx1-ubyte", 0x0801, 8 + 60_000),
+    ("t10k-images-idx3-ubyte", 0x0803, 16 + 10_000 * 784),
+    ("t10k-labels-idx1-ubyte", 0x0801, 8 + 10_000),
+];
+
+/// Default limit in bytes on the size of each downloaded archive
+///
+/// The largest archive, CIFAR-10's, is about 170 MB; the largest MNIST-format
+/// archive, Fashion-MNIST's training images, is about 26 MB.
+pub const DEFAULT_MAX_DOWNLOAD_SIZE: u64 = 256_000_000;
+
+/// Known-good SHA-256 digests of the MNIST archives, keyed by file name
+const MNIST_CHECKSUMS: [(&str, &str); 4] = [
+    (
+        "train-images-idx3-ubyte.gz",
+        "440fcabf73cc546fa21475e81ea370265605f56be210a4024d2ca8f203523609",
+    ),
+    (
+        "train-labels-idx1-ubyte.gz",
+        "3552534a0a558bbed6aed32b30c495cca23d567ec52cac8be1a0730e8010255c",
+    ),
+    (
+        "t10k-images-idx3-ubyte.gz",
+        "8d422c7b0a1c1c79245a5bcf07fe86e33eeafee792b84584aec276f5a2dbc4e6",
+    ),
+    (
+        "t10k-labels-idx1-ubyte.gz",
+        "f7ae60f92e00ec6debd23a6088c31dbd2371eca3ffa0defaefb259924204aec6",
+    ),
+];
+
+/// Known-good SHA-256 digests of the Fashion-MNIST archives, keyed by file name
+const FASHION_MNIST_CHECKSUMS: [(&str, &str); 4] = [
+    (
+        "train-images-idx3-ubyte.gz",
+        "3aede38d61863908ad78613f6a32ed271626dd12800ba2636569512369268a84",
+    ),
+    (
+        "train-labels-idx1-ubyte.gz",
+        "a04f17134ac03560a47e3764e11b92fc97de4d1bfaf8ba1a3aa29af54cc90845",
+    ),
+    (
+        "t10k-images-idx3-ubyte.gz",
+        "346e55b948d973a97e58d2351dde16a484bd415d4595297633bb08f03db6a073",
+    ),
+    (
+        "t10k-labels-idx1-ubyte.gz",
+        "67da17c76eaffca5446c3361aaab5c3cd6d1c2608764d35dfb1850b086bf8dd5",
+    ),
+];
+
+/// Size in bytes of the header of IDX image files
+const IMAGE_HEADER_SIZE: usize = 16;
+
+/// Size in bytes of the header of IDX label files
+const LABEL_HEADER_SIZE: usize = 8;
+
+/// Class names of the MNIST handwritten digits
+const MNIST_CLASSES: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+/// Class names of the Fashion-MNIST clothing categories
+const FASHION_MNIST_CLASSES: [&str; 10] = [
+    "T-shirt/top",
+    "Trouser",
+    "Pullover",
+    "Dress",
+    "Coat",
+    "Sandal",
+    "Shirt",
+    "Sneaker",
+    "Bag",
+    "Ankle boot",
+];
+
+/// Class names of the CIFAR-10 object categories
+const CIFAR10_CLASSES: [&str; 10] = [
+    "Airplane",
+    "Automobile",
+    "Bird",
+    "Cat",
+    "Deer",
+    "Dog",
+    "Frog",
+    "Horse",
+    "Ship",
+    "Truck",
+];
+
+/// Image datasets that can be downloaded and embedded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dataset {
+    /// Handwritten digits 0-9
+    Mnist,
+
+    /// Zalando article images in ten clothing categories
+    FashionMnist,
+
+    /// 32x32 color photos in ten object categories
+    Cifar10,
+}
+
+/// How the cached files of a dataset are used when loading it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Use the verified cached files, downloading any that are missing or corrupt
+    #[default]
+    Reuse,
+
+    /// Discard any cached copy and download the dataset again
+    Refresh,
+
+    /// Use only the cached files, failing instead of downloading any
+    Offline,
+}
+
+/// Numbers of images taken from the start of the training and test splits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitSizes {
+    /// Number of training images
+    pub train: usize,
+
+    /// Number of test images
+    pub test: usize,
+}
+
+impl SplitSizes {
+    /// Returns the sizes of both splits of a dataset in full
+    ///
+    /// The MNIST-format datasets have 60,000 training and 10,000 test images,
+    /// CIFAR-10 50,000 and 10,000.
+    pub fn full(dataset: Dataset) -> Self {
+        match dataset {
+            Dataset::Mnist | Dataset::FashionMnist => Self {
+                train: TRAINING_SET_SIZE as usize,
+                test: TEST_SET_SIZE as usize,
+            },
+            Dataset::Cifar10 => Self {
+                train: 5 * CIFAR10_BATCH_SIZE,
+                test: CIFAR10_BATCH_SIZE,
+            },
+        }
+    }
+
+    /// Returns the number of images after combining both splits
+    pub fn total(self) -> usize {
+        self.train + self.test
+    }
+
+    /// Checks that the splits of a dataset hold enough images for the requested sizes
+    ///
+    /// # Errors
+    /// Returns an error if either size exceeds its split or no image is requested
+    pub fn check(self, dataset: Dataset) -> Result<()> {
+        let full = Self::full(dataset);
+        ensure!(
+            self.train <= full.train,
+            "The {} training split has only {} images, but {} were requested",
+            dataset.display_name(),
+            full.train,
+            self.train
+        );
+        ensure!(
+            self.test <= full.test,
+            "The {} test split has only {} images, but {} were requested",
+            dataset.display_name(),
+            full.test,
+            self.test
+        );
+        ensure!(self.total() > 0, "At least one image must be loaded");
+        Ok(())
+    }
+}
+
+impl Dataset {
+    /// Returns the name used for the dataset in logs and plot titles
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Dataset::Mnist => "MNIST",
+            Dataset::FashionMnist => "Fashion-MNIST",
+            Dataset::Cifar10 => "CIFAR-10",
+        }
+    }
+
+    /// Returns the number of features of each flattened image
+    ///
+    /// MNIST-format images have one grayscale value per pixel, CIFAR-10
+    /// images a red, green and blue value.
+    pub fn features(self) -> usize {
+        match self {
+            Dataset::Mnist | Dataset::FashionMnist => IMAGE_PIXELS,
+            Dataset::Cifar10 => CIFAR10_FEATURES,
+        }
+    }
+
+    /// Returns the URL the compressed dataset files are downloaded from
+    fn base_url(self) -> &'static str {
+        match self {
+            Dataset::Mnist => "https://ossci-datasets.s3.amazonaws.com/mnist/",
+            Dataset::FashionMnist => "http://fashion-mnist.s3-website.eu-central-1.amazonaws.com/",
+            Dataset::Cifar10 => "https://www.cs.toronto.edu/~kriz/",
+        }
+    }
+
+    /// Returns the SHA-256 digest of each downloaded archive, keyed by file name
+    fn checksums(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Dataset::Mnist => &MNIST_CHECKSUMS,
+            Dataset::FashionMnist => &FASHION_MNIST_CHECKSUMS,
+            Dataset::Cifar10 => &CIFAR10_CHECKSUMS,
+        }
+    }
+
+    /// Returns the dataset's extracted files with their magic number, if any, and exact size
+    fn expected_files(self) -> Vec<(&'static str, Option<u32>, u64)> {
+        match self {
+            Dataset::Mnist | Dataset::FashionMnist => DATASET_FILES
+                .iter()
+                .map(|&(name, magic, size)| (name, Some(magic), size))
+                .collect(),
+            Dataset::Cifar10 => CIFAR10_FILES
+                .iter()
+                .map(|&name| {
+                    (
+                        name,
+                        None,
+                        (CIFAR10_BATCH_SIZE * (1 + CIFAR10_FEATURES)) as u64,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the names of the dataset's extracted files
+    fn file_names(self) -> Vec<&'static str> {
+        self.expected_files()
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect()
+    }
+
+    /// Returns the name of the dataset's cache subdirectory
+    ///
+    /// The MNIST-format datasets share file names, so each needs its own directory.
+    fn cache_name(self) -> &'static str {
+        match self {
+            Dataset::Mnist => "mnist",
+            Dataset::FashionMnist => "fashion-mnist",
+            Dataset::Cifar10 => "cifar10",
+        }
+    }
+
+    /// Returns the directory the dataset files are downloaded and extracted into
+    ///
+    /// This is `pacmap-example/<dataset>` under the platform's user cache
+    /// directory, e.g. `~/.cache/pacmap-example/mnist` on Linux.
+    ///
+    /// # Errors
+    /// Returns an error if the platform has no user cache directory
+    pub fn cache_dir(self) -> Result<PathBuf> {
+        let cache = dirs::cache_dir().context("Could not determine the user cache directory")?;
+        Ok(cache.join("pacmap-example").join(self.cache_name()))
+    }
+}
+
+/// Returns the human-readable name of each class, indexed by label
+///
+/// # Arguments
+/// * `dataset` - Dataset whose labels are being named
+pub fn class_names(dataset: Dataset) -> &'static [&'static str] {
+    match dataset {
+        Dataset::Mnist => &MNIST_CLASSES,
+        Dataset::FashionMnist => &FASHION_MNIST_CLASSES,
+        Dataset::Cifar10 => &CIFAR10_CLASSES,
+    }
+}
+
+/// Loads a dataset and combines its training and test splits
+///
+/// The dataset is read from its [cache directory](Dataset::cache_dir) when
+/// every file there passes verification, and downloaded otherwise. Pixel
+/// values are normalized to [0,1] and each image is flattened into a row of
+/// [`Dataset::features`] values: 784 for the 28x28 grayscale MNIST-format
+/// images, and 3072 for the 32x32 CIFAR-10 images, whose red, green and blue
+/// planes follow one another.
+///
+/// Images are decoded straight from the extracted files into a single
+/// preallocated matrix, so the images are never held twice.
+///
+/// Fresh downloads are checked against a size limit and known-good SHA-256
+/// digests of their archives before any file is read, so a
+/// corrupted or spoofed mirror is rejected rather than silently embedded.
+///
+/// # Arguments
+/// * `dataset` - Dataset to load
+/// * `cache` - Whether to reuse, refresh or only use the cached files
+/// * `max_download_size` - Largest accepted size in bytes of each downloaded archive
+///
+/// # Returns
+/// A tuple containing:
+/// * Image matrix of shape (n_images, n_features), e.g. (70000, 784) for MNIST
+/// * Class labels of shape (n_images,)
+///
+/// # Errors
+/// Returns [`PipelineError::Io`] if the cache cannot be accessed or read, and
+/// [`PipelineError::Download`] if the download still fails after retrying, a
+/// downloaded archive is too large or has the wrong checksum, or files are
+/// missing from the cache in [`CachePolicy::Offline`] mode
+pub fn load_dataset(
+    dataset: Dataset,
+    cache: CachePolicy,
+    max_download_size: u64,
+) -> Result<(Array2<f32>, Array1<i32>), PipelineError> {
+    load_dataset_subset(dataset, SplitSizes::full(dataset), cache, max_download_size)
+}
+
+/// Loads the first images of each split of a dataset, downloading it if needed
+///
+/// Works like [`load_dataset`], but only decodes the first `sizes.train`
+/// training and `sizes.test` test images, which makes quick runs on a slice of
+/// the data cheaper. The full files are still downloaded and verified.
+///
+/// # Arguments
+/// * `dataset` - Dataset to load
+/// * `sizes` - Number of images taken from the start of each split
+/// * `cache` - Whether to reuse, refresh or only use the cached files
+/// * `max_download_size` - Largest accepted size in bytes of each downloaded archive
+///
+/// # Returns
+/// A tuple containing:
+/// * Image matrix of shape (sizes.total(), n_features), training images first
+/// * Class labels of shape (sizes.total(),)
+///
+/// # Errors
+/// Returns [`PipelineError::Shape`] if the sizes exceed the splits, or fails
+/// like [`load_dataset`]
+pub fn load_dataset_subset(
+    dataset: Dataset,
+    sizes: SplitSizes,
+    cache: CachePolicy,
+    max_download_size: u64,
+) -> Result<(Array2<f32>, Array1<i32>), PipelineError> {
+    sizes.check(dataset).map_err(PipelineError::Shape)?;
+    let dir = dataset.cache_dir().map_err(PipelineError::Io)?;
+    if cache == CachePolicy::Refresh {
+        info!("Discarding cached {} files...", dataset.display_name());
+        remove_files(&dir, &dataset.file_names()).map_err(PipelineError::Io)?;
+    }
+
+    let invalid = invalid_files(dataset, &dir).map_err(PipelineError::Io)?;
+    let cached = invalid.is_empty();
+    if !cached && cache == CachePolicy::Offline {
+        // Checked before anything is removed, so offline runs never touch the cache
+        return Err(PipelineError::Download(anyhow!(
+            "{} is not cached in {}: {} missing or corrupt, and offline mode forbids \
+             downloading it. Run once with network access, or copy verified files there",
+            dataset.display_name(),
+            dir.display(),
+            invalid.join(", ")
+        )));
+    }
+    if cached {
+        info!(
+            "Using cached {} from {}",
+            dataset.display_name(),
+            dir.display()
+        );
+    } else {
+        for name in &invalid {
+            if dir.join(name).exists() {
+                warn!("Cached file {name} is corrupt; downloading it again");
+            }
+        }
+        remove_files(&dir, &invalid).map_err(PipelineError::Io)?;
+        info!(
+            "Downloading {} into {}{}...",
+            dataset.display_name(),
+            dir.display(),
+            if dataset == Dataset::Cifar10 {
+                ""
+            } else {
+                "; progress is shown for each file"
+            }
+        );
+    }
+
+    if !cached {
+        if dataset == Dataset::Cifar10 {
+            download_cifar10(&dir, max_download_size).map_err(PipelineError::Download)?;
+        } else {
+            // The mnist crate appends file names to the base path without a separator
+            let base_path = dir
+                .to_str()
+                .context("Dataset cache directory path is not valid UTF-8")
+                .map_err(PipelineError::Io)?
+                .to_string()
+                + MAIN_SEPARATOR_STR;
+            download_with_retry(dataset, &dir, &base_path).map_err(PipelineError::Download)?;
+            verify_archives(dataset, &dir, max_download_size).map_err(PipelineError::Download)?;
+        }
+        let invalid = invalid_files(dataset, &dir).map_err(PipelineError::Io)?;
+        if !invalid.is_empty() {
+            return Err(PipelineError::Download(anyhow!(
+                "Downloaded {} files failed verification: {}",
+                dataset.display_name(),
+                invalid.join(", ")
+            )));
+        }
+    }
+
+    // Downloads draw their own progress bars, so only spin while reading files
+    let mut x = Array2::zeros((sizes.total(), dataset.features()));
+    let mut labels = vec![0; sizes.total()];
+    Spinner::run("Reading dataset files", || match dataset {
+        Dataset::Mnist | Dataset::FashionMnist => {
+            load_mnist_into(&dir, sizes.train, x.view_mut(), &mut labels)
+        }
+        Dataset::Cifar10 => load_cifar10_into(&dir, sizes.train, x.view_mut(), &mut labels),
+    })
+    .map_err(PipelineError::Io)?;
+
+    Ok((x, Array1::from_vec(labels)))
+}
+
+/// Decodes the first images of the training and test splits into preallocated arrays
+///
+/// Training images fill the first `train_len` rows and test images the rest,
+/// with pixel values normalized to [0,1] as they are read.
+///
+/// # Arguments
+/// * `dir` - Dataset cache directory holding verified extracted files
+/// * `train_len` - Number of training images to read
+/// * `x` - Destination of shape (n_images, 784) for the images
+/// * `labels` - Destination of length n_images for the class labels
+///
+/// # Errors
+/// Returns an error if a file cannot be opened or read
+fn load_mnist_into(
+    dir: &Path,
+    train_len: usize,
+    mut x: ArrayViewMut2<f32>,
+    labels: &mut [i32],
+) -> Result<()> {
+    let (train_labels, test_labels) = labels.split_at_mut(train_len);
+    let (train, test) = (..train_len, train_len..);
+    read_images_into(
+        &dir.join("train-images-idx3-ubyte"),
+        x.slice_mut(s![train, ..]),
+    )?;
+    read_images_into(
+        &dir.join("t10k-images-idx3-ubyte"),
+        x.slice_mut(s![test, ..]),
+    )?;
+    read_labels_into(&dir.join("train-labels-idx1-ubyte"), train_labels)?;
+    read_labels_into(&dir.join("t10k-labels-idx1-ubyte"), test_labels)
+}
+
+/// Reads the images of an IDX file into the rows of `dst`, one at a time
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or holds too few images
+fn read_images_into(path: &Path, mut dst: ArrayViewMut2<f32>) -> Result<()> {
+    let mut reader = open_idx(path, IMAGE_HEADER_SIZE)?;
+    let mut pixels = [0u8; IMAGE_PIXELS];
+    for mut row in dst.rows_mut() {
+        reader
+            .read_exact(&mut pixels)
+            .with_context(|| format!("Error reading images from {}", path.display()))?;
+        for (value, &pixel) in row.iter_mut().zip(&pixels) {
+            *value = pixel as f32 / 255.0;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the labels of an IDX file into `dst`
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or holds too few labels
+fn read_labels_into(path: &Path, dst: &mut [i32]) -> Result<()> {
+    let mut bytes = vec![0u8; dst.len()];
+    open_idx(path, LABEL_HEADER_SIZE)?
+        .read_exact(&mut bytes)
+        .with_context(|| format!("Error reading labels from {}", path.display()))?;
+    for (label, byte) in dst.iter_mut().zip(bytes) {
+        *label = byte as i32;
+    }
+    Ok(())
+}
+
+/// Opens an IDX file positioned just past its header
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or is shorter than its header
+fn open_idx(path: &Path, header_size: usize) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Error opening {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut header = vec![0u8; header_size];
+    reader
+        .read_exact(&mut header)
+        .with_context(|| format!("Error reading header of {}", path.display()))?;
+    Ok(reader)
+}
+
+/// Decodes the first images of the CIFAR-10 training and test batches into preallocated arrays
+///
+/// Each record of a batch file is a label byte followed by the image's red,
+/// green and blue planes of 32x32 bytes, which are kept in that order. The
+/// training images are read from the five training batches in turn.
+///
+/// # Arguments
+/// * `dir` - Dataset cache directory holding verified batch files
+/// * `train_len` - Number of training images to read
+/// * `x` - Destination of shape (n_images, 3072) for the images
+/// * `labels` - Destination of length n_images for the class labels
+///
+/// # Errors
+/// Returns an error if a file cannot be opened or read, or holds an unknown label
+fn load_cifar10_into(
+    dir: &Path,
+    train_len: usize,
+    mut x: ArrayViewMut2<f32>,
+    labels: &mut [i32],
+) -> Result<()> {
+    // Number of records read from each batch file, in file order
+    let test_len = labels.len() - train_len;
+    let counts = (0..5)
+        .map(|batch| {
+            train_len
+                .saturating_sub(batch * CIFAR10_BATCH_SIZE)
+                .min(CIFAR10_BATCH_SIZE)
+        })
+        .chain([test_len]);
+
+    let mut rows = x.rows_mut().into_iter().zip(labels.iter_mut());
+    let mut record = [0u8; 1 + CIFAR10_FEATURES];
+    for (name, count) in CIFAR10_FILES.iter().zip(counts) {
+        if count == 0 {
+            continue;
+        }
+        let path = dir.join(name);
+        let file =
+            File::open(&path).with_context(|| format!("Error opening {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        for (mut row, label) in rows.by_ref().take(count) {
+            reader
+                .read_exact(&mut record)
+                .with_context(|| format!("Error reading images from {}", path.display()))?;
+            ensure!(
+                (record[0] as usize) < CIFAR10_CLASSES.len(),
+                "{} holds unknown label {}",
+                path.display(),
+                record[0]
+            );
+            *label = record[0] as i32;
+            for (value, &byte) in row.iter_mut().zip(&record[1..]) {
+                *value = byte as f32 / 255.0;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns a builder downloading the dataset's files into `base_path`
+fn mnist_builder(dataset: Dataset, base_path: &str) -> MnistBuilder<'_> {
+    let mut builder = MnistBuilder::new();
+    builder
+        .base_url(dataset.base_url())
+        .base_path(base_path)
+        .label_format_digit()
+        .training_set_length(TRAINING_SET_SIZE)
+        .test_set_length(TEST_SET_SIZE);
+    builder
+}
+
+/// Downloads and extracts the dataset, retrying transient failures with backoff
+///
+/// The mnist crate panics rather than returning an error when a download
+/// fails, so each attempt runs under [`panic::catch_unwind`] with the panic
+/// message silenced. Partially downloaded files are removed before retrying,
+/// as the crate would otherwise skip them.
+///
+/// # Arguments
+/// * `dataset` - Dataset to download
+/// * `dir` - Dataset cache directory
+/// * `base_path` - `dir` as a string ending in a path separator
+///
+/// # Errors
+/// Returns an error naming the download URL if every attempt fails
+fn download_with_retry(dataset: Dataset, dir: &Path, base_path: &str) -> Result<()> {
+    let _silenced = SilencedPanics::new();
+    with_retries(dataset, || {
+        let mut builder = mnist_builder(dataset, base_path);
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            builder.download_and_extract().finalize()
+        })) {
+            Ok(_) => Ok(Ok(())),
+            Err(payload) => {
+                // Clear partial downloads so the next attempt fetches them again
+                remove_files(dir, &invalid_files(dataset, dir)?)?;
+                Ok(Err(panic_message(payload)))
+            }
+        }
+    })
+}
+
+/// Runs a download until it succeeds, retrying transient failures with backoff
+///
+/// # Arguments
+/// * `dataset` - Dataset being downloaded, named in messages
+/// * `try_once` - Makes one attempt, returning the message of a transient
+///   failure as the inner error and a failure not worth retrying as the outer one
+///
+/// # Errors
+/// Returns an error naming the download URL if every attempt fails, or the
+/// first error not worth retrying
+fn with_retries(
+    dataset: Dataset,
+    mut try_once: impl FnMut() -> Result<Result<(), String>>,
+) -> Result<()> {
+    let mut delay = RETRY_DELAY;
+    for attempt in 1..=DOWNLOAD_ATTEMPTS {
+        let Err(failure) = try_once()? else {
+            return Ok(());
+        };
+        if attempt == DOWNLOAD_ATTEMPTS {
+            bail!(
+                "Failed to download {} from {} after {DOWNLOAD_ATTEMPTS} attempts: {failure}. \
+                 Check your network connection, or embed a local file instead with --input",
+                dataset.display_name(),
+                dataset.base_url()
+            );
+        }
+        warn!(
+            "Download attempt {attempt}/{DOWNLOAD_ATTEMPTS} of {} failed: {failure}; retrying in {} s",
+            dataset.display_name(),
+            delay.as_secs()
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+    unreachable!("the last attempt returns or fails")
+}
+
+/// Downloads the CIFAR-10 archive and extracts its batch files into `dir`
+///
+/// The archive is checked against its known digest before it is extracted,
+/// and removed once extracted, as the batch files hold the same data. A
+/// download growing past `max_size` is aborted rather than retried.
+///
+/// # Arguments
+/// * `dir` - Dataset cache directory
+/// * `max_size` - Largest accepted archive size in bytes
+///
+/// # Errors
+/// Returns an error if the download still fails after retrying, the archive
+/// is too large or has the wrong checksum, or it cannot be extracted
+fn download_cifar10(dir: &Path, max_size: u64) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Error creating dataset directory {}", dir.display()))?;
+    let url = format!("{}{CIFAR10_ARCHIVE}", Dataset::Cifar10.base_url());
+    let archive = dir.join(CIFAR10_ARCHIVE);
+    with_retries(Dataset::Cifar10, || {
+        Spinner::run(format!("Downloading {CIFAR10_ARCHIVE}"), || {
+            download_file(&url, &archive, max_size)
+        })
+    })?;
+    if let Err(e) = verify_archives(Dataset::Cifar10, dir, max_size) {
+        // The batch files are not extracted yet, so only the archive is discarded
+        let _ = fs::remove_file(&archive);
+        return Err(e);
+    }
+
+    let extracted = Spinner::run("Extracting CIFAR-10", || extract_cifar10(&archive, dir));
+    let removed =
+        fs::remove_file(&archive).with_context(|| format!("Error removing {}", archive.display()));
+    extracted.and(removed)
+}
+
+/// Downloads `url` into `path` through a temporary sibling file
+///
+/// The temporary file is moved into place once complete, and removed if the
+/// download fails in any way.
+///
+/// # Returns
+/// The message of a transient failure, such as a network error, as the inner error
+///
+/// # Errors
+/// Returns an error if the file cannot be written or the download exceeds `max_size`
+fn download_file(url: &str, path: &Path, max_size: u64) -> Result<Result<(), String>> {
+    let partial = path.with_extension("partial");
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let downloaded = download_to(url, &partial, &name, max_size);
+    if !matches!(downloaded, Ok(Ok(()))) {
+        let _ = fs::remove_file(&partial);
+        return downloaded;
+    }
+    if let Err(e) = fs::rename(&partial, path) {
+        let _ = fs::remove_file(&partial);
+        return Err(e)
+            .with_context(|| format!("Error moving download into place at {}", path.display()));
+    }
+    Ok(Ok(()))
+}
+
+/// Downloads `url` into `partial`, which is left for the caller to move or remove
+///
+/// # Arguments
+/// * `url` - Address to download
+/// * `partial` - Temporary file written
+/// * `name` - Name of the final file, used in errors
+/// * `max_size` - Largest accepted download size in bytes
+///
+/// # Returns
+/// The message of a transient failure, such as a network error, as the inner error
+///
+/// # Errors
+/// Returns an error if the file cannot be written or the download exceeds `max_size`
+fn download_to(url: &str, partial: &Path, name: &str, max_size: u64) -> Result<Result<(), String>> {
+    let mut file =
+        File::create(partial).with_context(|| format!("Error creating {}", partial.display()))?;
+    let mut written = 0u64;
+    let mut write_error = None;
+
+    let mut easy = curl::easy::Easy::new();
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    easy.fail_on_error(true)?;
+    let performed = {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            written += data.len() as u64;
+            if written > max_size {
+                // Accepting fewer bytes than offered aborts the transfer
+                return Ok(0);
+            }
+            match file.write_all(data) {
+                Ok(()) => Ok(data.len()),
+                Err(e) => {
+                    write_error = Some(e);
+                    Ok(0)
+                }
+            }
+        })?;
+        transfer.perform()
+    };
+
+    if written > max_size {
+        bail!(
+            "Download of {name} exceeded the --max-download-size limit of {max_size} bytes; \
+             check that {url} serves the CIFAR-10 archive"
+        );
+    }
+    if let Some(e) = write_error {
+        return Err(e).with_context(|| format!("Error writing {}", partial.display()));
+    }
+    if let Err(e) = performed {
+        return Ok(Err(format!("{e}")));
+    }
+    file.flush()
+        .with_context(|| format!("Error writing {}", partial.display()))?;
+    Ok(Ok(()))
+}
+
+/// Extracts the batch files of the CIFAR-10 archive into `dir`
+///
+/// The archive keeps the batches in a `cifar-10-batches-bin` folder; they are
+/// written straight into `dir`, and the archive's other files are skipped.
+///
+/// # Errors
+/// Returns an error if the archive cannot be read or a batch cannot be written
+fn extract_cifar10(archive: &Path, dir: &Path) -> Result<()> {
+    let file =
+        File::open(archive).with_context(|| format!("Error opening {}", archive.display()))?;
+    let mut tar = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let context = || format!("Error extracting {}", archive.display());
+    for entry in tar.entries().with_context(context)? {
+        let mut entry = entry.with_context(context)?;
+        let entry_path = entry.path().with_context(context)?;
+        // Only known names are written, so entries cannot escape `dir`
+        let Some(&name) = CIFAR10_FILES
+            .iter()
+            .find(|&&name| entry_path.file_name() == Some(name.as_ref()))
+        else {
+            continue;
+        };
+        let path = dir.join(name);
+        let mut out =
+            File::create(&path).with_context(|| format!("Error creating {}", path.display()))?;
+        io::copy(&mut entry, &mut out).with_context(context)?;
+    }
+    Ok(())
+}
+
+/// A panic hook as returned by [`panic::take_hook`]
+type PanicHook = Box<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+/// Silences panic messages until dropped, then restores the previous hook
+///
+/// Besides the panicking download itself, the mnist crate's progress bar
+/// thread panics once a partial download is removed, so messages are
+/// silenced for the whole retry loop rather than each attempt.
+struct SilencedPanics(Option<PanicHook>);
+
+impl SilencedPanics {
+    fn new() -> Self {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        Self(Some(hook))
+    }
+}
+
+impl Drop for SilencedPanics {
+    fn drop(&mut self) {
+        if let Some(hook) = self.0.take() {
+            panic::set_hook(hook);
+        }
+    }
+}
+
+/// Returns the message of a caught panic
+///
+/// The `unwrap` prefix of the mnist crate's panics is dropped, leaving the
+/// underlying download error.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown error".to_string(),
+        },
+    };
+    match message.strip_prefix("called `Result::unwrap()` on an `Err` value: ") {
+        Some(error) => error.to_string(),
+        None => message,
+    }
+}
+
+/// Checks the size and SHA-256 digest of every downloaded archive
+///
+/// The mnist crate extracts each archive as soon as it is downloaded, so this
+/// runs after extraction but before any extracted file is read. On failure
+/// all of the dataset's files are removed, so the next run downloads them
+/// afresh instead of reusing the rejected copies.
+///
+/// # Arguments
+/// * `dataset` - Dataset whose archives were downloaded
+/// * `dir` - Dataset cache directory
+/// * `max_size` - Largest accepted archive size in bytes
+///
+/// # Errors
+/// Returns an error if an archive cannot be read, exceeds `max_size`, or does
+/// not match its known digest
+fn verify_archives(dataset: Dataset, dir: &Path, max_size: u64) -> Result<()> {
+    let all_files = dataset.file_names();
+    for &(archive, expected) in dataset.checksums() {
+        let path = dir.join(archive);
+        let mut file =
+            File::open(&path).with_context(|| format!("Error opening {}", path.display()))?;
+        let size = file
+            .metadata()
+            .with_context(|| format!("Error reading metadata of {}", path.display()))?
+            .len();
+        if size > max_size {
+            remove_files(dir, &all_files)?;
+            bail!(
+                "Downloaded {archive} is {size} bytes, over the --max-download-size limit of \
+                 {max_size}; check that {} serves the {} archives",
+                dataset.base_url(),
+                dataset.display_name()
+            );
+        }
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Error reading {}", path.display()))?;
+        let actual = format!("{:x}", hasher.finalize());
+        if actual != expected {
+            remove_files(dir, &all_files)?;
+            bail!(
+                "Downloaded {archive} from {} has SHA-256 {actual}, expected {expected}; the \
+                 mirror may be corrupted or compromised, so the files were discarded",
+                dataset.base_url()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the extracted dataset files that are missing or corrupt
+///
+/// A file is intact if it has exactly the expected size and, for the IDX files
+/// of the MNIST-format datasets, starts with the expected magic number.
+///
+/// # Errors
+/// Returns an error if a file exists but cannot be read
+fn invalid_files(dataset: Dataset, dir: &Path) -> Result<Vec<&'static str>> {
+    let mut invalid = Vec::new();
+    for (name, magic, size) in dataset.expected_files() {
+        let path = dir.join(name);
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                invalid.push(name);
+                continue;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Error opening {}", path.display())),
+        };
+
+        let actual_size = file
+            .metadata()
+            .with_context(|| format!("Error reading metadata of {}", path.display()))?
+            .len();
+        let mut header = [0; 4];
+        let intact = actual_size == size
+            && magic.is_none_or(|magic| {
+                file.read_exact(&mut header).is_ok() && u32::from_be_bytes(header) == magic
+            });
+        if !intact {
+            invalid.push(name);
+        }
+    }
+    Ok(invalid)
+}
+
+/// Removes extracted dataset files along with their downloaded archives
+///
+/// Archives are removed too because the mnist crate skips downloading any
+/// archive that already exists, even if a previous download was interrupted.
+///
+/// # Arguments
+/// * `dir` - Dataset cache directory
+/// * `names` - Names of the extracted files to remove
+///
+/// # Errors
+/// Returns an error if an existing file cannot be removed
+fn remove_files(dir: &Path, names: &[&str]) -> Result<()> {
+    for name in names {
+        for path in [dir.join(name), dir.join(format!("{name}.gz"))] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Error removing {}", path.display()))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
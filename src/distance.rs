@@ -0,0 +1,87 @@
+//! Distance metrics for the neighbor search
+//!
+//! PaCMAP measures every distance as Euclidean, so other metrics are emulated
+//! around it:
+//! * Cosine distance is exact: once each sample is scaled to unit length, the
+//!   squared Euclidean distance between two samples is `2 * (1 - cos)`, which
+//!   ranks every pair the same way cosine distance does.
+//! * Manhattan distance cannot be reproduced by rescaling the input, so the
+//!   nearest neighbors are searched under Manhattan distance by brute force
+//!   and handed to PaCMAP as its neighbor graph. Mid-near and far pairs are
+//!   still chosen by Euclidean distance, making this an approximation that
+//!   only changes which points count as neighbors. The search costs
+//!   O(n² * d) time for n samples with d features, which is slow beyond a
+//!   few tens of thousands of samples.
+
+use clap::ValueEnum;
+use ndarray::{Array2, ArrayView2};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Distance metrics that decide which samples are neighbors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    /// Straight-line distance, as PaCMAP measures it
+    #[default]
+    Euclidean,
+
+    /// Angle between samples, ignoring their length
+    Cosine,
+
+    /// Sum of absolute feature differences, approximated for neighbor pairs only
+    Manhattan,
+}
+
+/// Finds each sample's nearest neighbors under Manhattan distance
+///
+/// Each sample is compared with every other one, in parallel over samples.
+/// Ties are broken by the lower index, so the graph is deterministic.
+///
+/// # Arguments
+/// * `x` - Data of shape (n_samples, n_features)
+/// * `k` - Number of neighbors per sample, below n_samples
+///
+/// # Returns
+/// Neighbor pairs of shape (n_samples * k, 2) as PaCMAP expects them, with each
+/// sample's `k` pairs in order of increasing distance
+pub fn manhattan_neighbors(x: ArrayView2<f32>, k: usize) -> Array2<u32> {
+    let n = x.nrows();
+    let neighbors: Vec<Vec<usize>> = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let row = x.row(i);
+            let mut distances: Vec<(f32, usize)> = x
+                .rows()
+                .into_iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(j, other)| {
+                    let distance = row
+                        .iter()
+                        .zip(other.iter())
+                        .map(|(a, b)| (a - b).abs())
+                        .sum();
+                    (distance, j)
+                })
+                .collect();
+            let by_distance =
+                |a: &(f32, usize), b: &(f32, usize)| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1));
+            if k < distances.len() {
+                distances.select_nth_unstable_by(k, by_distance);
+                distances.truncate(k);
+            }
+            distances.sort_unstable_by(by_distance);
+            distances.into_iter().map(|(_, j)| j).collect()
+        })
+        .collect();
+
+    let mut pairs = Array2::zeros((n * k, 2));
+    for (i, row) in neighbors.iter().enumerate() {
+        for (column, &j) in row.iter().enumerate() {
+            pairs[[i * k + column, 0]] = i as u32;
+            pairs[[i * k + column, 1]] = j as u32;
+        }
+    }
+    pairs
+}
@@ -0,0 +1,72 @@
+//! Detection of exactly repeated input rows
+//!
+//! Identical feature vectors sit at distance zero from each other, so they
+//! crowd each other's neighbor lists and pull the embedding into tight
+//! clumps. They are also often a sign of a data bug, such as a file
+//! concatenated with itself.
+
+use ndarray::{ArrayView1, ArrayView2};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// A row whose values exactly repeat those of an earlier row
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+    /// Index of the repeated row
+    pub row: usize,
+
+    /// Index of the first row with the same values
+    pub first: usize,
+}
+
+/// Finds every row that exactly repeats an earlier row
+///
+/// Rows are grouped by a hash of their values and only compared within a
+/// group, so the data is scanned once without holding a second copy of it.
+/// Zero and negative zero are treated as equal.
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+///
+/// # Returns
+/// The duplicates in ascending row order, each paired with its first occurrence
+pub fn find_duplicates(x: ArrayView2<f32>) -> Vec<Duplicate> {
+    let mut groups: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (row, values) in x.rows().into_iter().enumerate() {
+        let candidates = groups.entry(hash_row(values)).or_default();
+        match candidates.iter().find(|&&first| x.row(first) == values) {
+            Some(&first) => duplicates.push(Duplicate { row, first }),
+            None => candidates.push(row),
+        }
+    }
+    duplicates
+}
+
+/// Returns the indices of the rows that remain after dropping duplicates
+///
+/// # Arguments
+/// * `n_rows` - Number of rows in the data
+/// * `duplicates` - Duplicates in ascending row order, as from [`find_duplicates`]
+pub fn unique_rows(n_rows: usize, duplicates: &[Duplicate]) -> Vec<usize> {
+    let mut duplicates = duplicates.iter().map(|duplicate| duplicate.row).peekable();
+    (0..n_rows)
+        .filter(|&row| {
+            let repeated = duplicates.peek() == Some(&row);
+            if repeated {
+                duplicates.next();
+            }
+            !repeated
+        })
+        .collect()
+}
+
+/// Hashes the bit patterns of a row's values
+fn hash_row(values: ArrayView1<f32>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in values {
+        // Adding zero turns negative zero into zero, which compares equal to it
+        (value + 0.0).to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,34 @@
+//! Error kinds of the library API
+//!
+//! Internally the pipeline reports failures with [`anyhow`] contexts, which
+//! read well in the command-line example but cannot be told apart by a
+//! program. The entry points of the library instead return a
+//! [`PipelineError`], whose variant names the kind of failure while its
+//! message and source chain stay those of the underlying error.
+
+use thiserror::Error;
+
+/// Kind of failure of a library entry point
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    /// A dataset could not be downloaded, or the download failed verification
+    #[error(transparent)]
+    Download(anyhow::Error),
+
+    /// Data does not have the expected shape, such as an embedding with the
+    /// wrong number of dimensions or a request for more images than a split holds
+    #[error(transparent)]
+    Shape(anyhow::Error),
+
+    /// A file or directory could not be read or written
+    #[error(transparent)]
+    Io(anyhow::Error),
+
+    /// PaCMAP, or the thread pool it runs in, failed
+    #[error(transparent)]
+    Embedding(anyhow::Error),
+
+    /// A plot could not be built
+    #[error(transparent)]
+    Plot(anyhow::Error),
+}
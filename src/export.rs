@@ -0,0 +1,112 @@
+//! Writing embedding results to disk
+//!
+//! Exports the embedded coordinates so they can be analyzed outside of the
+//! interactive visualization.
+
+use anyhow::{Context, Result};
+use csv::Writer;
+use ndarray::{Array1, ArrayView2};
+use ndarray_npy::write_npy;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Creates the parent directory of `path` if it does not already exist
+///
+/// # Errors
+/// Returns an error if the directory cannot be created
+pub fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Error creating output directory {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// Writes embedding coordinates and labels to a CSV file
+///
+/// Two-dimensional embeddings use the columns `x,y`; higher dimensions use
+/// `dim0..dimN`. A trailing `label` column is added when labels are present.
+/// Rows are streamed through a buffered writer rather than assembled in
+/// memory, and coordinates are written in their shortest round-trip form so no
+/// `f32` precision is lost.
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, n_dims) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,), if known
+/// * `path` - Destination of the CSV file
+///
+/// # Errors
+/// Returns an error if the file cannot be created or written
+pub fn write_embedding_csv(
+    embedding: ArrayView2<f32>,
+    labels: Option<&Array1<i32>>,
+    path: &Path,
+) -> Result<()> {
+    create_parent_dir(path)?;
+    let file = File::create(path)
+        .with_context(|| format!("Error creating embedding file {}", path.display()))?;
+    let mut writer = Writer::from_writer(BufWriter::new(file));
+
+    let mut header: Vec<String> = match embedding.ncols() {
+        2 => vec!["x".to_string(), "y".to_string()],
+        n => (0..n).map(|i| format!("dim{i}")).collect(),
+    };
+    if labels.is_some() {
+        header.push("label".to_string());
+    }
+    writer.write_record(&header)?;
+
+    for (i, row) in embedding.rows().into_iter().enumerate() {
+        for value in row {
+            writer.write_field(value.to_string())?;
+        }
+        if let Some(labels) = labels {
+            writer.write_field(labels[i].to_string())?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("Error writing embedding file {}", path.display()))?;
+    Ok(())
+}
+
+/// Writes embedding coordinates as a NumPy `.npy` file
+///
+/// The embedding is stored as a float32 array of shape (n_samples, n_dims),
+/// readable with `numpy.load`. Labels, when present, are written as an int32
+/// array to the sibling path returned by [`labels_npy_path`].
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, n_dims) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,), if known
+/// * `path` - Destination of the embedding `.npy` file
+///
+/// # Errors
+/// Returns an error if either file cannot be written
+pub fn write_embedding_npy(
+    embedding: ArrayView2<f32>,
+    labels: Option<&Array1<i32>>,
+    path: &Path,
+) -> Result<()> {
+    create_parent_dir(path)?;
+    write_npy(path, &embedding)
+        .with_context(|| format!("Error writing embedding file {}", path.display()))?;
+
+    if let Some(labels) = labels {
+        let labels_path = labels_npy_path(path);
+        write_npy(&labels_path, labels)
+            .with_context(|| format!("Error writing labels file {}", labels_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Returns the path of the labels file written alongside an embedding `.npy`
+///
+/// The `.npy` extension is replaced by `.labels.npy`, so `embedding.npy` is
+/// accompanied by `embedding.labels.npy`.
+pub fn labels_npy_path(path: &Path) -> PathBuf {
+    path.with_extension("labels.npy")
+}
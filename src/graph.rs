@@ -0,0 +1,199 @@
+//! Nearest neighbor graph PaCMAP fits to
+//!
+//! PaCMAP searches each sample's neighbors itself and keeps no copy of the
+//! graph: `fit_transform` returns only the embedding and its snapshots. To
+//! export the graph, [`pacmap_neighbor_graph`] repeats PaCMAP's selection with
+//! the search functions the crate exposes, and the result is handed to PaCMAP
+//! as its neighbor pairs, so the exported graph is the one the fit used.
+//!
+//! The repetition is not exact, so exporting the graph changes the fit: wide
+//! data is reduced with [`pca_reduce`](crate::pca::pca_reduce) where PaCMAP
+//! uses a randomized PCA, and candidates at equal distances may be ordered
+//! differently. A run with the export can therefore embed differently from
+//! one without it at the same seed.
+//!
+//! PaCMAP does not simply keep the nearest neighbors. It finds `k + 50`
+//! candidates, exactly below `approx_threshold` samples and approximately
+//! above it, scales each squared distance by the local density around both
+//! samples, `d² / (σᵢ σⱼ)` with σ the mean distance to a sample's 4th to 6th
+//! nearest candidates, and keeps the `k` candidates with the smallest scaled
+//! distance. Neighbors in sparse regions therefore reach further than in
+//! dense ones.
+
+use crate::distance::Metric;
+use crate::export::create_parent_dir;
+use anyhow::{anyhow, ensure, Context, Result};
+use ndarray::{s, Array1, Array2, ArrayView2, Axis};
+use ndarray_npy::write_npy;
+use pacmap::knn::{find_k_nearest_neighbors, find_k_nearest_neighbors_approx};
+use std::path::{Path, PathBuf};
+
+/// Number of features above which PaCMAP, when initialized by PCA, searches
+/// neighbors among this many principal components of the data instead
+pub const PACMAP_PCA_COMPONENTS: usize = 100;
+
+/// Extra candidates PaCMAP searches beyond the neighbors it keeps
+const EXTRA_CANDIDATES: usize = 50;
+
+/// Each sample's neighbors with their distances
+#[derive(Clone, Debug, PartialEq)]
+pub struct NeighborGraph {
+    /// Neighbor indices of shape (n_samples, k), row `i` listing sample `i`'s neighbors
+    pub indices: Array2<u32>,
+
+    /// Distances of shape (n_samples, k) from each sample to the neighbor at the
+    /// same position in `indices`
+    pub distances: Array2<f32>,
+}
+
+impl NeighborGraph {
+    /// Returns the graph as neighbor pairs of shape (n_samples * k, 2), sample by
+    /// sample in the layout PaCMAP expects
+    pub fn pair_neighbors(&self) -> Array2<u32> {
+        let (n, k) = self.indices.dim();
+        Array2::from_shape_fn((n * k, 2), |(row, column)| match column {
+            0 => (row / k) as u32,
+            _ => self.indices[[row / k, row % k]],
+        })
+    }
+}
+
+/// Selects each sample's neighbors the way PaCMAP does when it searches them
+///
+/// # Arguments
+/// * `x` - Data searched, of shape (n_samples, n_features)
+/// * `k` - Number of neighbors per sample, below n_samples
+/// * `approx_threshold` - Number of samples from which the candidates are
+///   searched approximately, as in PaCMAP's configuration
+///
+/// # Returns
+/// The graph, with each sample's neighbors in order of increasing scaled
+/// distance and their Euclidean distances in the units of `x`
+///
+/// # Errors
+/// Returns an error if `k` is zero or not below the number of samples, or the
+/// approximate search fails
+pub fn pacmap_neighbor_graph(
+    x: ArrayView2<f32>,
+    k: usize,
+    approx_threshold: usize,
+) -> Result<NeighborGraph> {
+    let n = x.nrows();
+    ensure!(
+        k > 0 && k < n,
+        "Cannot select {k} neighbors per sample among {n} samples"
+    );
+
+    // The search reads rows as contiguous slices
+    let x = x.as_standard_layout();
+    let candidates = (k + EXTRA_CANDIDATES).min(n - 1);
+    let (neighbors, distances) = if n < approx_threshold {
+        find_k_nearest_neighbors(x.view(), candidates)
+    } else {
+        find_k_nearest_neighbors_approx(x.view(), candidates)
+            .map_err(|e| anyhow!("Error searching approximate nearest neighbors: {e}"))?
+    };
+
+    let start = 3.min(distances.ncols() - 1);
+    let end = 6.min(distances.ncols());
+    let sig = distances
+        .slice(s![.., start..end])
+        .mean_axis(Axis(1))
+        .map_or_else(|| Array1::from_elem(n, 1e-10), |d| d.mapv(|v| v.max(1e-10)));
+
+    let mut indices = Array2::zeros((n, k));
+    let mut kept = Array2::zeros((n, k));
+    for i in 0..n {
+        let mut order: Vec<(f32, usize)> = (0..candidates)
+            .map(|c| {
+                let d = distances[[i, c]];
+                (d * d / (sig[i] * sig[neighbors[[i, c]] as usize]), c)
+            })
+            .collect();
+        order.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        for (position, &(_, c)) in order.iter().take(k).enumerate() {
+            indices[[i, position]] = neighbors[[i, c]];
+            kept[[i, position]] = distances[[i, c]];
+        }
+    }
+    Ok(NeighborGraph {
+        indices,
+        distances: kept,
+    })
+}
+
+/// Builds the graph of neighbor pairs chosen elsewhere, measuring their distances
+///
+/// # Arguments
+/// * `x` - Data the neighbors were chosen in, of shape (n_samples, n_features)
+/// * `pairs` - Neighbor pairs of shape (n_samples * k, 2), sample by sample
+/// * `metric` - Metric the distances are measured with; Manhattan distance is
+///   measured as such, and every other metric as Euclidean distance in `x`
+///
+/// # Panics
+/// Panics if the pairs are not `k` per sample in the layout PaCMAP expects
+pub fn graph_from_pairs(
+    x: ArrayView2<f32>,
+    pairs: ArrayView2<u32>,
+    metric: Metric,
+) -> NeighborGraph {
+    let n = x.nrows();
+    assert!(
+        n > 0 && pairs.nrows().is_multiple_of(n),
+        "{} neighbor pairs do not split evenly over {n} samples",
+        pairs.nrows()
+    );
+    let k = pairs.nrows() / n;
+    let indices = Array2::from_shape_fn((n, k), |(i, j)| pairs[[i * k + j, 1]]);
+    let distances = Array2::from_shape_fn((n, k), |(i, j)| {
+        let (a, b) = (x.row(i), x.row(indices[[i, j]] as usize));
+        let differences = a.iter().zip(b.iter()).map(|(a, b)| a - b);
+        match metric {
+            Metric::Manhattan => differences.map(f32::abs).sum(),
+            _ => differences.map(|d| d * d).sum::<f32>().sqrt(),
+        }
+    });
+    NeighborGraph { indices, distances }
+}
+
+/// Writes the graph as two NumPy `.npy` files
+///
+/// The neighbor indices are written to `path` as a uint32 array of shape
+/// (n_samples, k) and the distances as a float32 array of the same shape to
+/// the sibling path returned by [`graph_distances_path`].
+///
+/// # Arguments
+/// * `graph` - Graph to write
+/// * `path` - Destination of the indices `.npy` file
+///
+/// # Errors
+/// Returns an error if `path` names a `.npz` archive, which needs the zip
+/// support of `ndarray-npy` this crate is built without, or either file
+/// cannot be written
+pub fn write_graph_npy(graph: &NeighborGraph, path: &Path) -> Result<()> {
+    ensure!(
+        path.extension().is_none_or(|extension| extension != "npz"),
+        "Cannot write the neighbor graph to {}: .npz archives need the zip support of \
+         ndarray-npy, which is not enabled; pass a .npy path, and the distances are written \
+         beside it",
+        path.display()
+    );
+    create_parent_dir(path)?;
+    write_npy(path, &graph.indices)
+        .with_context(|| format!("Error writing neighbor indices {}", path.display()))?;
+    let distances_path = graph_distances_path(path);
+    write_npy(&distances_path, &graph.distances).with_context(|| {
+        format!(
+            "Error writing neighbor distances {}",
+            distances_path.display()
+        )
+    })
+}
+
+/// Returns the path of the distances file written alongside the graph's indices
+///
+/// The `.npy` extension is replaced by `.distances.npy`, so `graph.npy` is
+/// accompanied by `graph.distances.npy`.
+pub fn graph_distances_path(path: &Path) -> PathBuf {
+    path.with_extension("distances.npy")
+}
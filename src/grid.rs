@@ -0,0 +1,142 @@
+//! Grid search over PaCMAP parameters
+//!
+//! Embeds the same data under every combination of candidate parameter values
+//! and scores each run, so settings can be compared on equal footing.
+
+use crate::export::create_parent_dir;
+use anyhow::{Context, Result};
+use csv::Writer;
+use pacmap::Configuration;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Number of rows a grid search embeds when `--sample` is not given
+pub const DEFAULT_SAMPLE_SIZE: usize = 10_000;
+
+/// One combination of PaCMAP parameters to evaluate
+#[derive(Clone, Copy, Debug)]
+pub struct GridPoint {
+    /// Number of nearest neighbors used to form neighbor pairs
+    pub neighbors: usize,
+
+    /// Ratio of mid-near pairs to nearest neighbor pairs
+    pub mid_near_ratio: f32,
+
+    /// Ratio of far pairs to nearest neighbor pairs
+    pub far_pair_ratio: f32,
+}
+
+impl GridPoint {
+    /// Returns `base` with this point's parameters applied
+    pub fn configure(&self, base: &Configuration) -> Configuration {
+        Configuration {
+            override_neighbors: Some(self.neighbors),
+            mid_near_ratio: self.mid_near_ratio,
+            far_pair_ratio: self.far_pair_ratio,
+            ..base.clone()
+        }
+    }
+}
+
+impl fmt::Display for GridPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "neighbors = {}, mid-near ratio = {}, far pair ratio = {}",
+            self.neighbors, self.mid_near_ratio, self.far_pair_ratio
+        )
+    }
+}
+
+/// Timing and quality scores of one grid search run
+#[derive(Clone, Copy, Debug)]
+pub struct GridResult {
+    /// Parameters the embedding was computed with
+    pub point: GridPoint,
+
+    /// Wall-clock time of the PaCMAP fit in milliseconds
+    pub millis: u128,
+
+    /// Trustworthiness of the embedding
+    pub trustworthiness: f64,
+
+    /// k-NN classification accuracy in the embedding, if the data is labeled
+    pub knn_accuracy: Option<f64>,
+}
+
+/// Returns every combination of the candidate parameter values
+///
+/// # Arguments
+/// * `neighbors` - Candidate neighbor counts
+/// * `mid_near_ratios` - Candidate mid-near pair ratios
+/// * `far_pair_ratios` - Candidate far pair ratios
+pub fn grid_points(
+    neighbors: &[usize],
+    mid_near_ratios: &[f32],
+    far_pair_ratios: &[f32],
+) -> Vec<GridPoint> {
+    let mut points = Vec::new();
+    for &neighbors in neighbors {
+        for &mid_near_ratio in mid_near_ratios {
+            for &far_pair_ratio in far_pair_ratios {
+                points.push(GridPoint {
+                    neighbors,
+                    mid_near_ratio,
+                    far_pair_ratio,
+                });
+            }
+        }
+    }
+    points
+}
+
+/// Writes a summary table of grid search results to a CSV file
+///
+/// The `knn_accuracy` column is left empty for unlabeled data.
+///
+/// # Arguments
+/// * `results` - Results of each run, in the order they were run
+/// * `path` - Destination of the CSV file
+///
+/// # Errors
+/// Returns an error if the file cannot be created or written
+pub fn write_grid_csv(results: &[GridResult], path: &Path) -> Result<()> {
+    create_parent_dir(path)?;
+    let file = File::create(path)
+        .with_context(|| format!("Error creating grid summary {}", path.display()))?;
+    let mut writer = Writer::from_writer(BufWriter::new(file));
+
+    writer.write_record([
+        "neighbors",
+        "mid_near_ratio",
+        "far_pair_ratio",
+        "millis",
+        "trustworthiness",
+        "knn_accuracy",
+    ])?;
+    for result in results {
+        let GridPoint {
+            neighbors,
+            mid_near_ratio,
+            far_pair_ratio,
+        } = result.point;
+        writer.write_record([
+            neighbors.to_string(),
+            mid_near_ratio.to_string(),
+            far_pair_ratio.to_string(),
+            result.millis.to_string(),
+            result.trustworthiness.to_string(),
+            result
+                .knn_accuracy
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        ])?;
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("Error writing grid summary {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,1401 @@
+//! Loading user-supplied feature matrices
+//!
+//! Lets the example embed arbitrary numeric data instead of downloading MNIST.
+//! Inputs are selected with a `<format>:<path>` specification such as
+//! `csv:features.csv`, and several inputs can be embedded together.
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use csv::{ReaderBuilder, StringRecord, StringRecordsIter};
+use flate2::bufread::MultiGzDecoder;
+use ndarray::{concatenate, Array1, Array2, ArrayView2, Axis};
+use ndarray_npy::read_npy;
+use serde::de::{self, Deserialize, Deserializer};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::iter::{self, Peekable};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::info;
+
+/// A user-supplied input file and the format used to read it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// Comma-separated numeric matrix, one sample per row
+    Csv(PathBuf),
+
+    /// NumPy `.npy` file holding a 2D float32 array, one sample per row
+    Npy(PathBuf),
+
+    /// Sparse LIBSVM (SVMlight) file, one sample per line with its label first
+    Libsvm(PathBuf),
+}
+
+impl Input {
+    /// Returns the path of the input file
+    pub fn path(&self) -> &Path {
+        match self {
+            Input::Csv(path) | Input::Npy(path) | Input::Libsvm(path) => path,
+        }
+    }
+
+    /// Returns the file name of the input, used to name it in logs and plots
+    pub fn name(&self) -> String {
+        let path = self.path();
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        name.to_string_lossy().into_owned()
+    }
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let (format, path) = spec
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected <format>:<path>, e.g. csv:data.csv"))?;
+        ensure!(!path.is_empty(), "missing path after '{format}:'");
+
+        match format {
+            "csv" => Ok(Input::Csv(PathBuf::from(path))),
+            "npy" => Ok(Input::Npy(PathBuf::from(path))),
+            "libsvm" => Ok(Input::Libsvm(PathBuf::from(path))),
+            // Reading Parquet needs the parquet and arrow crates, which are not dependencies yet
+            "parquet" => bail!(
+                "Parquet input is not supported yet; export the feature table to CSV and use csv:<path>"
+            ),
+            // Reading HDF5 needs the hdf5 crate and the native HDF5 library, neither of which is
+            // a dependency yet
+            "hdf5" => bail!(
+                "HDF5 input is not supported yet; save the dataset as a float32 .npy file, e.g. with \
+                 h5py, and use npy:<path>"
+            ),
+            _ => bail!("unsupported input format '{format}', expected one of: csv, npy, libsvm"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Input {
+    /// Parses the same `<format>:<path>` specification as [`FromStr`]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Which CSV column holds the class labels or the values to color by
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelColumn {
+    /// The last column holds the labels
+    Last,
+
+    /// The column with this header name holds the labels
+    Named(String),
+
+    /// The column at this 1-based position holds the labels
+    Index(usize),
+
+    /// A bare number names the column of that header name if the header has
+    /// one, and otherwise the column at that 1-based position
+    Number {
+        /// The number as given, compared with the header names
+        name: String,
+
+        /// The number as a 1-based position
+        index: usize,
+    },
+}
+
+impl FromStr for LabelColumn {
+    type Err = anyhow::Error;
+
+    /// Parses `last`, a 1-based position such as `#5` or `5`, or else a header
+    /// name
+    ///
+    /// A bare number is only a position if the header has no column of that
+    /// name, such as the `5` of a pandas export; `#` always marks a position.
+    fn from_str(name: &str) -> Result<Self> {
+        Ok(match (name, name.strip_prefix('#')) {
+            ("last", _) => LabelColumn::Last,
+            (_, Some(position)) => LabelColumn::Index(parse_position(position)?),
+            _ => match parse_position(name) {
+                Ok(index) => LabelColumn::Number {
+                    name: name.to_string(),
+                    index,
+                },
+                Err(_) => LabelColumn::Named(name.to_string()),
+            },
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelColumn {
+    /// Parses a header name, a 1-based position, or `last`, as [`FromStr`] does
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Which CSV columns hold the features
+///
+/// A selection is a comma-separated list of header names, name patterns in
+/// which `*` matches any run of characters, such as `pixel_*`, and 1-based
+/// positions or inclusive ranges of them, such as `2-785`. A number or range
+/// that is also a header name, such as the `2024` column of a table of years,
+/// selects that column instead; a leading `#`, as in `#2-785`, always marks
+/// positions. Selected columns are read in the order of the file, whatever
+/// the order of the list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureColumns(Vec<ColumnPattern>);
+
+/// One entry of a [`FeatureColumns`] selection
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ColumnPattern {
+    /// The column with this header name
+    Named(String),
+
+    /// Every column whose header name matches this pattern with `*` wildcards
+    Glob(String),
+
+    /// The columns from the first to the second 1-based position, inclusive
+    Range(usize, usize),
+
+    /// A bare number or range, such as `2-785`: the column of that header
+    /// name if the header has one, and otherwise the range of positions
+    Numbered {
+        /// The entry as given, compared with the header names
+        name: String,
+
+        /// First 1-based position of the range
+        start: usize,
+
+        /// Last 1-based position of the range, inclusive
+        end: usize,
+    },
+}
+
+impl FromStr for FeatureColumns {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for entry in spec.split(',').map(str::trim) {
+            ensure!(
+                !entry.is_empty(),
+                "empty entry in column selection '{spec}'"
+            );
+            let pattern = match entry.strip_prefix('#') {
+                Some(range) => {
+                    let (start, end) = parse_range(range)?;
+                    ColumnPattern::Range(start, end)
+                }
+                None if entry.contains('*') => ColumnPattern::Glob(entry.to_string()),
+                None => match parse_range(entry) {
+                    Ok((start, end)) => ColumnPattern::Numbered {
+                        name: entry.to_string(),
+                        start,
+                        end,
+                    },
+                    Err(_) => ColumnPattern::Named(entry.to_string()),
+                },
+            };
+            patterns.push(pattern);
+        }
+        Ok(Self(patterns))
+    }
+}
+
+impl<'de> Deserialize<'de> for FeatureColumns {
+    /// Parses a comma-separated selection, as [`FromStr`] does
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FeatureColumns {
+    /// Returns whether the selection names a column, which needs a header row
+    fn names_columns(&self) -> bool {
+        self.0
+            .iter()
+            .any(|pattern| matches!(pattern, ColumnPattern::Named(_) | ColumnPattern::Glob(_)))
+    }
+
+    /// Returns whether each of `width` columns is selected by position,
+    /// ignoring positions beyond the last column
+    fn positions(&self, width: usize) -> Vec<bool> {
+        let mut selected = vec![false; width];
+        for pattern in &self.0 {
+            if let ColumnPattern::Range(start, end) | ColumnPattern::Numbered { start, end, .. } =
+                pattern
+            {
+                let end = (*end).min(width);
+                if *start <= end {
+                    selected[start - 1..end].fill(true);
+                }
+            }
+        }
+        selected
+    }
+
+    /// Finds the selected columns within rows of `width` cells
+    ///
+    /// # Arguments
+    /// * `header` - Header row, if the CSV has one
+    /// * `width` - Number of cells in each row
+    ///
+    /// # Returns
+    /// Whether each of the `width` columns is selected
+    ///
+    /// # Errors
+    /// Returns an error if a name or pattern is given without a header, a name
+    /// is not in the header, a pattern matches no column, or a column number
+    /// is beyond the last column
+    fn resolve(&self, header: Option<&StringRecord>, width: usize) -> Result<Vec<bool>> {
+        let mut selected = vec![false; width];
+        for pattern in &self.0 {
+            if let ColumnPattern::Numbered { name, .. } = pattern {
+                if let Some(column) = header.and_then(|header| header_position(header, name)) {
+                    selected[column] = true;
+                    continue;
+                }
+            }
+            match pattern {
+                ColumnPattern::Range(start, end) | ColumnPattern::Numbered { start, end, .. } => {
+                    ensure!(
+                        *end <= width,
+                        "Feature column #{end} requested but the CSV has only {width} columns"
+                    );
+                    selected[start - 1..*end].fill(true);
+                }
+                ColumnPattern::Named(name) | ColumnPattern::Glob(name) => {
+                    let header = header.ok_or_else(|| {
+                        anyhow!(
+                            "Feature column '{name}' requested but the CSV has no header row; \
+                             select columns by position instead, such as #2 or #2-785"
+                        )
+                    })?;
+                    let mut found = false;
+                    for (column, cell) in header.iter().enumerate() {
+                        let cell = cell.trim();
+                        let matched = match pattern {
+                            ColumnPattern::Glob(glob) => glob_matches(glob, cell),
+                            _ => cell == name,
+                        };
+                        if matched {
+                            selected[column] = true;
+                            found = true;
+                        }
+                    }
+                    match pattern {
+                        ColumnPattern::Glob(_) => {
+                            ensure!(found, "Feature pattern '{name}' matches no CSV column")
+                        }
+                        _ => ensure!(
+                            found,
+                            "Feature column '{name}' not found in CSV header; select columns by \
+                             position instead, such as #2 or #2-785"
+                        ),
+                    }
+                }
+            }
+        }
+        Ok(selected)
+    }
+}
+
+/// Parses a 1-based column position or an inclusive range of them, such as
+/// `2-785`, as written after a `#`
+///
+/// # Returns
+/// The first and last position of the range, which are the same for a
+/// single position
+///
+/// # Errors
+/// Returns an error if a position is not a positive integer or the range is
+/// reversed
+fn parse_range(range: &str) -> Result<(usize, usize)> {
+    match range.split_once('-') {
+        Some((start, end)) => {
+            let start = parse_position(start.trim())?;
+            let end = parse_position(end.trim().trim_start_matches('#'))?;
+            ensure!(start <= end, "column range '#{range}' is reversed");
+            Ok((start, end))
+        }
+        None => {
+            let index = parse_position(range)?;
+            Ok((index, index))
+        }
+    }
+}
+
+/// Returns the index of the header cell named `name`, if any
+fn header_position(header: &StringRecord, name: &str) -> Option<usize> {
+    header.iter().position(|cell| cell.trim() == name)
+}
+
+/// Parses a 1-based column position, as written after a `#`
+///
+/// # Errors
+/// Returns an error if the position is not a positive integer
+fn parse_position(position: &str) -> Result<usize> {
+    let index = position
+        .parse()
+        .with_context(|| format!("invalid column position '#{position}'"))?;
+    ensure!(index > 0, "column positions are counted from #1");
+    Ok(index)
+}
+
+/// Returns whether `text` matches `pattern`, in which `*` matches any run of
+/// characters, including none
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard at all, so the pattern must be the whole text
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Columns read from a CSV input file
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvData {
+    /// Feature matrix of shape (n_samples, n_features)
+    pub x: Array2<f32>,
+
+    /// Class labels of shape (n_samples,), if a label column was selected
+    pub labels: Option<Array1<i32>>,
+
+    /// Name of each class, indexed by label, if the label column holds text;
+    /// empty for integer labels
+    pub class_names: Vec<String>,
+
+    /// Values to color the points by of shape (n_samples,), if a color column
+    /// was selected
+    pub colors: Option<Array1<f32>>,
+}
+
+/// Data combined from one or more input files
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputData {
+    /// Feature matrix of shape (n_samples, n_features)
+    pub x: Array2<f32>,
+
+    /// Class labels of shape (n_samples,), if a label column was selected
+    pub labels: Option<Array1<i32>>,
+
+    /// Name of each class, indexed by label, if any input's label column holds
+    /// text; empty for integer labels
+    pub class_names: Vec<String>,
+
+    /// Values to color the points by of shape (n_samples,), if a color column
+    /// was selected
+    pub colors: Option<Array1<f32>>,
+
+    /// Index of the input file each sample came from, of shape (n_samples,)
+    pub sources: Array1<i32>,
+
+    /// Name of each input file, indexed by source
+    pub source_names: Vec<String>,
+}
+
+/// Loads several input files and stacks their samples into one matrix
+///
+/// Samples keep the order of the files and of the rows within each file. Each
+/// sample is tagged with the index of its file, so the embedding can be colored
+/// by origin. LIBSVM files only list the features they use, so they are widened
+/// with zero features to the widest of them. If any file has text labels, the
+/// classes of all files are numbered together in alphabetical order, with
+/// integer labels of the other files read as text.
+///
+/// # Arguments
+/// * `inputs` - Files to load; must not be empty
+/// * `label_column` - CSV column holding integer or text class labels, if any
+/// * `color_column` - CSV column holding continuous values to color by, if any
+/// * `feature_columns` - CSV columns holding the features, if not all others
+///
+/// # Errors
+/// Returns an error if any file fails to load, two files have different
+/// numbers of features, a label, color or feature column is requested for a
+/// file that cannot have one, or only some of the files have labels
+pub fn load_inputs(
+    inputs: &[Input],
+    label_column: Option<&LabelColumn>,
+    color_column: Option<&LabelColumn>,
+    feature_columns: Option<&FeatureColumns>,
+) -> Result<InputData> {
+    ensure!(!inputs.is_empty(), "No input files given");
+
+    let mut parts: Vec<CsvData> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let data = match input {
+            Input::Csv(path) => load_csv(path, label_column, color_column, feature_columns)?,
+            Input::Npy(path) => {
+                ensure!(
+                    label_column.is_none() && color_column.is_none() && feature_columns.is_none(),
+                    "{} is a NumPy array without named columns, so --label-column, \
+                     --color-column and --features cannot be used with it",
+                    path.display()
+                );
+                CsvData {
+                    x: load_npy(path)?,
+                    labels: None,
+                    class_names: Vec::new(),
+                    colors: None,
+                }
+            }
+            Input::Libsvm(path) => {
+                ensure!(
+                    label_column.is_none() && color_column.is_none() && feature_columns.is_none(),
+                    "{} is a LIBSVM file, whose labels come first on each line, so \
+                     --label-column, --color-column and --features cannot be used with it",
+                    path.display()
+                );
+                load_libsvm(path)?
+            }
+        };
+        info!(
+            "Read {} samples with {} features from {}",
+            data.x.nrows(),
+            data.x.ncols(),
+            input.path().display()
+        );
+        parts.push(data);
+    }
+
+    let sparse_width = inputs
+        .iter()
+        .zip(&parts)
+        .filter(|(input, _)| matches!(input, Input::Libsvm(_)))
+        .map(|(_, part)| part.x.ncols())
+        .max();
+    for (input, part) in inputs.iter().zip(&mut parts) {
+        if let (Input::Libsvm(_), Some(width)) = (input, sparse_width) {
+            if part.x.ncols() < width {
+                let padding = Array2::zeros((part.x.nrows(), width - part.x.ncols()));
+                part.x = concatenate(Axis(1), &[part.x.view(), padding.view()])
+                    .context("Error widening LIBSVM input")?;
+            }
+        }
+    }
+    for (input, part) in inputs.iter().zip(&parts).skip(1) {
+        ensure!(
+            part.x.ncols() == parts[0].x.ncols(),
+            "{} has {} features but {} has {}; all inputs must have the same columns",
+            input.path().display(),
+            part.x.ncols(),
+            inputs[0].path().display(),
+            parts[0].x.ncols()
+        );
+    }
+    let labeled = parts.iter().filter(|part| part.labels.is_some()).count();
+    ensure!(
+        labeled == 0 || labeled == parts.len(),
+        "Only {labeled} of {} inputs have class labels; LIBSVM files always do, so combine them \
+         with CSV files only when those have a --label-column",
+        parts.len()
+    );
+
+    let views: Vec<_> = parts.iter().map(|part| part.x.view()).collect();
+    let x = concatenate(Axis(0), &views).context("Error combining input files")?;
+    let class_names = merge_class_names(&mut parts);
+    let labels = (labeled > 0).then(|| {
+        let labels: Vec<_> = parts
+            .iter()
+            .flat_map(|part| part.labels.iter().flatten().copied())
+            .collect();
+        Array1::from_vec(labels)
+    });
+    let colors = color_column.map(|_| {
+        let colors: Vec<_> = parts
+            .iter()
+            .flat_map(|part| part.colors.iter().flatten().copied())
+            .collect();
+        Array1::from_vec(colors)
+    });
+    let sources = parts
+        .iter()
+        .enumerate()
+        .flat_map(|(source, part)| iter::repeat_n(source as i32, part.x.nrows()))
+        .collect();
+
+    Ok(InputData {
+        x,
+        labels,
+        class_names,
+        colors,
+        sources,
+        source_names: inputs.iter().map(Input::name).collect(),
+    })
+}
+
+/// Numbers the classes of inputs with text labels together, in alphabetical order
+///
+/// Each input's labels are rewritten to index the returned names. If no input
+/// has text labels, the labels are left as they are and no names are returned.
+fn merge_class_names(parts: &mut [CsvData]) -> Vec<String> {
+    if parts.iter().all(|part| part.class_names.is_empty()) {
+        return Vec::new();
+    }
+
+    // Integer labels of the other inputs become names of their own
+    let integer_labels =
+        |part: &CsvData| -> BTreeSet<i32> { part.labels.iter().flatten().copied().collect() };
+    let mut names = BTreeSet::new();
+    for part in parts.iter() {
+        if part.class_names.is_empty() {
+            names.extend(integer_labels(part).iter().map(i32::to_string));
+        } else {
+            names.extend(part.class_names.iter().cloned());
+        }
+    }
+    let names: Vec<String> = names.into_iter().collect();
+    let code = |name: &str| {
+        names
+            .binary_search_by(|probe| probe.as_str().cmp(name))
+            .map_or(0, |i| i as i32)
+    };
+
+    for part in parts.iter_mut() {
+        let codes: HashMap<i32, i32> = if part.class_names.is_empty() {
+            let labels = integer_labels(part);
+            labels
+                .into_iter()
+                .map(|label| (label, code(&label.to_string())))
+                .collect()
+        } else {
+            (0..)
+                .zip(part.class_names.iter().map(|name| code(name)))
+                .collect()
+        };
+        if let Some(labels) = &mut part.labels {
+            labels.mapv_inplace(|label| codes[&label]);
+        }
+    }
+    names
+}
+
+/// Loads a feature matrix from a NumPy `.npy` file
+///
+/// # Arguments
+/// * `path` - File holding a 2D float32 array, one sample per row
+///
+/// # Errors
+/// Returns an error if the file cannot be read or does not hold a 2D float32
+/// array with at least one sample and feature
+pub fn load_npy(path: &Path) -> Result<Array2<f32>> {
+    let x: Array2<f32> = read_npy(path)
+        .with_context(|| format!("Error reading 2D float32 array from {}", path.display()))?;
+    ensure!(x.nrows() > 0, "{} contains no samples", path.display());
+    ensure!(
+        x.ncols() > 0,
+        "{} contains no feature columns",
+        path.display()
+    );
+    Ok(x)
+}
+
+/// Loads a sparse feature matrix and its labels from a LIBSVM file
+///
+/// Each line holds one sample as `<label> <index>:<value> ...`, listing only
+/// its nonzero features, optionally followed by a `#` comment; `qid:` ranking
+/// groups are ignored. Indices are 1-based unless the file uses index 0,
+/// which marks a 0-based file, and the matrix is as wide as the largest index.
+/// Gzipped files are decompressed transparently.
+///
+/// PaCMAP only embeds dense matrices, so the data is densified as it is read:
+/// a first pass over the file finds the matrix's shape, and a second fills it
+/// in line by line, so no sparse copy of the data is held next to the dense
+/// one.
+///
+/// # Arguments
+/// * `path` - LIBSVM file with one sample per line
+///
+/// # Errors
+/// Returns an error if the file cannot be read, a label is not an integer, a
+/// feature is malformed or a line's indices are not ascending, or the file
+/// contains no samples or no features
+pub fn load_libsvm(path: &Path) -> Result<CsvData> {
+    // The first pass finds the shape, the second fills in the matrix
+    let (mut n_samples, mut max_index, mut zero_based) = (0, 0, false);
+    for_each_libsvm_line(path, |_, features| {
+        n_samples += 1;
+        for &(index, _) in features {
+            max_index = max_index.max(index);
+            zero_based |= index == 0;
+        }
+    })?;
+    ensure!(n_samples > 0, "{} contains no samples", path.display());
+    let n_features = max_index + usize::from(zero_based);
+    ensure!(n_features > 0, "{} contains no features", path.display());
+
+    let mut x = Array2::zeros((n_samples, n_features));
+    let mut labels = Vec::with_capacity(n_samples);
+    let mut row = 0;
+    for_each_libsvm_line(path, |label, features| {
+        labels.push(label);
+        for &(index, value) in features {
+            let column = if zero_based { index } else { index - 1 };
+            x[[row, column]] = value;
+        }
+        row += 1;
+    })?;
+
+    Ok(CsvData {
+        x,
+        labels: Some(Array1::from_vec(labels)),
+        class_names: Vec::new(),
+        colors: None,
+    })
+}
+
+/// Parses each sample of a LIBSVM file and passes it to `visit`
+///
+/// `visit` is called with the label and the (index, value) pairs of the
+/// sample's features, as written in the file. Blank and comment-only lines
+/// are skipped.
+fn for_each_libsvm_line(path: &Path, mut visit: impl FnMut(i32, &[(usize, f32)])) -> Result<()> {
+    let reader = BufReader::new(open_text(path, "LIBSVM")?);
+    let mut features = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.with_context(|| format!("Error reading {}", path.display()))?;
+        let data = line.split_once('#').map_or(line.as_str(), |(data, _)| data);
+        let mut tokens = data.split_whitespace();
+        let Some(label) = tokens.next() else {
+            continue;
+        };
+        let location = || format!("line {line_number} of {}", path.display());
+        let label = parse_libsvm_label(label)
+            .with_context(|| format!("Invalid integer label '{label}' on {}", location()))?;
+
+        features.clear();
+        for token in tokens.filter(|token| !token.starts_with("qid:")) {
+            let parsed = token.split_once(':').and_then(|(index, value)| {
+                Some((index.parse::<usize>().ok()?, value.parse::<f32>().ok()?))
+            });
+            let Some(feature) = parsed else {
+                bail!(
+                    "Malformed feature '{token}' on {}; expected <index>:<value>",
+                    location()
+                );
+            };
+            if let Some(&(previous, _)) = features.last() {
+                ensure!(
+                    feature.0 > previous,
+                    "Feature indices must be ascending, but {} follows {previous} on {}",
+                    feature.0,
+                    location()
+                );
+            }
+            features.push(feature);
+        }
+        visit(label, &features);
+    }
+    Ok(())
+}
+
+/// Parses a LIBSVM class label, which may be written with a sign or as a
+/// whole-valued float such as `+1` or `3.0`
+fn parse_libsvm_label(label: &str) -> Option<i32> {
+    if let Ok(label) = label.parse::<i32>() {
+        return Some(label);
+    }
+    let value = label.parse::<f64>().ok()?;
+    (value.fract() == 0.0 && value.abs() <= i32::MAX as f64).then_some(value as i32)
+}
+
+/// Loads a precomputed nearest neighbor graph from a NumPy `.npy` file
+///
+/// Row `i` of the array lists the indices of the `k` nearest neighbors of
+/// sample `i`, as returned by the `kneighbors` query of most approximate
+/// nearest neighbor libraries. Integer arrays of any common width are
+/// accepted; distances are not needed, as PaCMAP only uses the neighbor pairs.
+///
+/// # Arguments
+/// * `path` - File holding a 2D integer array of shape (n_samples, k)
+/// * `n_samples` - Number of samples being embedded
+///
+/// # Returns
+/// Neighbor pairs of shape (n_samples * k, 2), each row holding a sample and
+/// one of its neighbors, sample by sample in the layout PaCMAP expects
+///
+/// # Errors
+/// Returns an error if the file does not hold a 2D integer array, its row
+/// count differs from `n_samples`, it has no neighbor columns, or an index is
+/// out of range or refers to the sample itself
+pub fn load_neighbors(path: &Path, n_samples: usize) -> Result<Array2<u32>> {
+    let read = || -> Result<Array2<i64>> {
+        if let Ok(indices) = read_npy::<_, Array2<i64>>(path) {
+            return Ok(indices);
+        }
+        if let Ok(indices) = read_npy::<_, Array2<i32>>(path) {
+            return Ok(indices.mapv(i64::from));
+        }
+        if let Ok(indices) = read_npy::<_, Array2<u32>>(path) {
+            return Ok(indices.mapv(i64::from));
+        }
+        let indices: Array2<u64> = read_npy(path)?;
+        Ok(indices.mapv(|index| i64::try_from(index).unwrap_or(i64::MAX)))
+    };
+    let indices = read().with_context(|| {
+        format!(
+            "Error reading 2D integer array of neighbor indices from {}",
+            path.display()
+        )
+    })?;
+
+    let (rows, k) = indices.dim();
+    ensure!(
+        rows == n_samples,
+        "Neighbor graph {} has {rows} rows, but {n_samples} samples are embedded; it must hold \
+         one row per sample, in the same order",
+        path.display()
+    );
+    ensure!(
+        k > 0,
+        "Neighbor graph {} has no neighbor columns",
+        path.display()
+    );
+
+    let mut pairs = Array2::zeros((rows * k, 2));
+    for ((i, column), &j) in indices.indexed_iter() {
+        ensure!(
+            (0..n_samples as i64).contains(&j) && j != i as i64,
+            "Neighbor graph {} lists invalid neighbor {j} of sample {i}; indices must be between \
+             0 and {} and exclude the sample itself",
+            path.display(),
+            n_samples - 1
+        );
+        pairs[[i * k + column, 0]] = i as u32;
+        pairs[[i * k + column, 1]] = j as u32;
+    }
+    Ok(pairs)
+}
+
+/// Loads point annotations from a CSV file of `index,text` rows
+///
+/// The index is the number a point is shown with when hovering over it, and
+/// the text may be quoted to hold commas. A header row is skipped if its
+/// first cell is not a number; a negative or fractional index is an error
+/// even on the first row.
+///
+/// # Arguments
+/// * `path` - CSV file with one annotation per row
+///
+/// # Returns
+/// The index and text of each annotation, in file order
+///
+/// # Errors
+/// Returns an error if the file cannot be read, a row does not have exactly
+/// two cells, or an index is not a non-negative integer
+pub fn load_annotations(path: &Path) -> Result<Vec<(usize, String)>> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(open_csv(path)?);
+    let mut annotations = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Error reading {}", path.display()))?;
+        let line = record.position().map_or(0, |p| p.line());
+        ensure!(
+            record.len() == 2,
+            "Annotation on line {line} of {} has {} cells; expected `index,text`",
+            path.display(),
+            record.len()
+        );
+        let cell = record[0].trim();
+        match cell.parse::<usize>() {
+            Ok(index) => annotations.push((index, record[1].trim().to_string())),
+            Err(_) if row == 0 && cell.parse::<f64>().is_err() => {}
+            Err(_) => bail!(
+                "Invalid point index '{cell}' on line {line} of {}",
+                path.display()
+            ),
+        }
+    }
+    Ok(annotations)
+}
+
+/// Loads anchor coordinates from a CSV file of `index,x,y` rows
+///
+/// Rows of 3D layouts add a `z` coordinate. As for annotations, the index is
+/// the number a point is shown with when hovering over it, and a header row
+/// is skipped if its first cell is not a number.
+///
+/// # Arguments
+/// * `path` - CSV file with one anchor per row
+/// * `dims` - Number of coordinates each row must give
+///
+/// # Returns
+/// The index and coordinates of each anchor, in file order
+///
+/// # Errors
+/// Returns an error if the file cannot be read, a row does not have an index
+/// and `dims` coordinates, the index is not a non-negative integer, or a
+/// coordinate is not a finite number
+pub fn load_anchors(path: &Path, dims: usize) -> Result<Vec<(usize, Vec<f32>)>> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(open_csv(path)?);
+    let expected = ["index", "x", "y", "z"][..=dims].join(",");
+    let mut anchors = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Error reading {}", path.display()))?;
+        let line = record.position().map_or(0, |p| p.line());
+        ensure!(
+            record.len() == dims + 1,
+            "Anchor on line {line} of {} has {} cells; expected `{expected}` for a {dims}D \
+             embedding",
+            path.display(),
+            record.len()
+        );
+        let cell = record[0].trim();
+        let index = match cell.parse::<usize>() {
+            Ok(index) => index,
+            Err(_) if row == 0 && cell.parse::<f64>().is_err() => continue,
+            Err(_) => bail!(
+                "Invalid point index '{cell}' on line {line} of {}",
+                path.display()
+            ),
+        };
+        let position = record
+            .iter()
+            .skip(1)
+            .map(|cell| {
+                let cell = cell.trim();
+                cell.parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .with_context(|| {
+                        format!(
+                            "Invalid coordinate '{cell}' on line {line} of {}",
+                            path.display()
+                        )
+                    })
+            })
+            .collect::<Result<_>>()?;
+        anchors.push((index, position));
+    }
+    Ok(anchors)
+}
+
+/// First two bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Largest number of distinct text labels read as classes; more suggest a
+/// column of free-form text rather than categories
+const MAX_TEXT_CLASSES: usize = 256;
+
+/// Number of rows from which a text label column with distinct values in most
+/// rows is taken for free-form text; smaller files may have a class per row
+const MIN_ROWS_FOR_UNIQUE_CHECK: usize = 20;
+
+/// Loads a numeric feature matrix from a CSV file
+///
+/// Gzip-compressed files, such as `features.csv.gz`, are recognized by their
+/// magic bytes and decompressed while reading. A header row is detected
+/// automatically: the first row holds column names if a cell read as a number
+/// is not one. Text in a label column selected by position, or in a column
+/// left out of the selection, only marks a header if the second row has a
+/// number there, as such columns may hold text in a headerless file. Every
+/// other cell must be a number; empty cells and non-numeric text are reported
+/// with their 1-based line and column. Selected label and color columns are
+/// not used as features.
+///
+/// Labels are read as integers if every one is an integer. Otherwise they are
+/// read as class names, numbered in alphabetical order from 0, so text labels
+/// such as `cat` and `dog` need no encoding beforehand. A text column with more
+/// than [`MAX_TEXT_CLASSES`] distinct values, or with distinct values in most
+/// rows, is rejected as free-form text rather than categories.
+///
+/// The shape is inferred rather than configured: the number of features comes
+/// from the first row, which every other row must match, and the values are
+/// streamed into a growable buffer that is shaped into the matrix once the
+/// number of rows is known.
+///
+/// # Arguments
+/// * `path` - CSV file with one sample per row
+/// * `label_column` - Column holding integer or text class labels, if any
+/// * `color_column` - Column holding continuous values to color the points by,
+///   such as a regression target, if any
+/// * `feature_columns` - Columns holding the features, if not every column but
+///   the label and color columns; the others are skipped
+///
+/// # Errors
+/// Returns an error if:
+/// - The file cannot be opened or is not well-formed CSV
+/// - A named label, color or feature column is requested but missing from the
+///   header, or a column number is beyond the last column
+/// - The label and color columns are the same column, or either is selected
+///   as a feature
+/// - A cell is empty or cannot be parsed
+/// - The label column holds free-form text rather than class names
+/// - The file contains no samples or no feature columns
+pub fn load_csv(
+    path: &Path,
+    label_column: Option<&LabelColumn>,
+    color_column: Option<&LabelColumn>,
+    feature_columns: Option<&FeatureColumns>,
+) -> Result<CsvData> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(open_csv(path)?);
+    let mut records = reader.records().peekable();
+    let CsvLayout {
+        header,
+        label_index,
+        color_index,
+        first_row,
+        features: is_feature,
+        n_features,
+    } = CsvLayout::read(&mut records, label_column, color_column, feature_columns)?;
+
+    let mut features = Vec::new();
+    // Labels are interned as they are read, as they may turn out to be text
+    let mut labels = Vec::new();
+    let mut label_codes: HashMap<String, i32> = HashMap::new();
+    let mut label_texts = Vec::new();
+    let mut colors = Vec::new();
+
+    for record in first_row.map(Ok).into_iter().chain(records) {
+        let record = record.with_context(|| format!("Error reading {}", path.display()))?;
+        let line = record.position().map_or(0, |p| p.line());
+
+        for (column, cell) in record.iter().enumerate() {
+            let cell = cell.trim();
+            let location = || describe_cell(line, column, header.as_ref());
+            let selected = is_feature.get(column).copied().unwrap_or(true);
+            if !selected && Some(column) != label_index && Some(column) != color_index {
+                continue;
+            }
+            ensure!(!cell.is_empty(), "Missing value at {}", location());
+
+            if Some(column) == label_index {
+                let code = match label_codes.get(cell) {
+                    Some(&code) => code,
+                    None => {
+                        let code = label_texts.len() as i32;
+                        label_codes.insert(cell.to_string(), code);
+                        label_texts.push(cell.to_string());
+                        code
+                    }
+                };
+                labels.push(code);
+            } else if Some(column) == color_index {
+                let value = cell.parse::<f32>().with_context(|| {
+                    format!("Non-numeric color value '{cell}' at {}", location())
+                })?;
+                colors.push(value);
+            } else {
+                let value = cell
+                    .parse::<f32>()
+                    .with_context(|| format!("Non-numeric value '{cell}' at {}", location()))?;
+                features.push(value);
+            }
+        }
+    }
+
+    ensure!(
+        n_features > 0,
+        "{} contains no feature columns",
+        path.display()
+    );
+    let n_samples = features.len() / n_features;
+    ensure!(n_samples > 0, "{} contains no samples", path.display());
+    // The buffer grows by doubling, so wide files could otherwise keep twice the memory they need
+    features.shrink_to_fit();
+
+    let x = Array2::from_shape_vec((n_samples, n_features), features)
+        .context("Error converting CSV values to Array2")?;
+    let (labels, class_names) = match label_index {
+        Some(column) => {
+            let column = describe_column(column, header.as_ref());
+            let (labels, class_names) = decode_labels(labels, label_texts, &column)?;
+            (Some(labels), class_names)
+        }
+        None => (None, Vec::new()),
+    };
+    let colors = color_index.map(|_| Array1::from_vec(colors));
+
+    Ok(CsvData {
+        x,
+        labels,
+        class_names,
+        colors,
+    })
+}
+
+/// Turns interned label cells into integer labels, or into classes named by
+/// their text if any cell is not an integer
+///
+/// # Arguments
+/// * `codes` - Index of each row's label in `texts`
+/// * `texts` - Distinct label cells, in the order they first appear
+/// * `column` - Description of the label column, for errors
+///
+/// # Returns
+/// A tuple containing:
+/// * Labels of shape (n_rows,)
+/// * Class names indexed by label, in alphabetical order, or none for integer labels
+///
+/// # Errors
+/// Returns an error if the labels are text with more than [`MAX_TEXT_CLASSES`]
+/// distinct values, or distinct values in most rows
+fn decode_labels(
+    codes: Vec<i32>,
+    texts: Vec<String>,
+    column: &str,
+) -> Result<(Array1<i32>, Vec<String>)> {
+    let integers: Option<Vec<i32>> = texts.iter().map(|text| text.parse().ok()).collect();
+    if let Some(integers) = integers {
+        let labels = codes.into_iter().map(|code| integers[code as usize]);
+        return Ok((labels.collect(), Vec::new()));
+    }
+
+    ensure!(
+        texts.len() <= MAX_TEXT_CLASSES
+            && (codes.len() < MIN_ROWS_FOR_UNIQUE_CHECK || texts.len() * 2 <= codes.len()),
+        "Label {column} holds {} distinct texts in {} rows, which looks like free-form text \
+         rather than class names; pick a categorical column, or merge rare classes first",
+        texts.len(),
+        codes.len()
+    );
+    let mut order: Vec<usize> = (0..texts.len()).collect();
+    order.sort_by(|&a, &b| texts[a].cmp(&texts[b]));
+    let mut ranks = vec![0; texts.len()];
+    for (rank, &code) in order.iter().enumerate() {
+        ranks[code] = rank as i32;
+    }
+    let labels = codes.into_iter().map(|code| ranks[code as usize]).collect();
+    let mut texts = texts;
+    let names = order
+        .iter()
+        .map(|&code| std::mem::take(&mut texts[code]))
+        .collect();
+    Ok((labels, names))
+}
+
+/// Checks that an input file can be read and its selected columns exist
+///
+/// Only the first rows of a CSV file are read, so this is cheap even for large
+/// inputs. NumPy and LIBSVM files are only opened, as their shape is known once
+/// loaded.
+///
+/// # Arguments
+/// * `input` - File to check
+/// * `label_column` - CSV column holding integer class labels, if any
+/// * `color_column` - CSV column holding continuous values to color by, if any
+/// * `feature_columns` - CSV columns holding the features, if not all others
+///
+/// # Returns
+/// The number of feature columns of a CSV file, or `None` for other formats
+///
+/// # Errors
+/// Returns an error if the file cannot be opened, a selected column is missing,
+/// or a label, color or feature column is requested for a NumPy or LIBSVM file
+pub fn inspect_input(
+    input: &Input,
+    label_column: Option<&LabelColumn>,
+    color_column: Option<&LabelColumn>,
+    feature_columns: Option<&FeatureColumns>,
+) -> Result<Option<usize>> {
+    match input {
+        Input::Csv(path) => {
+            let mut reader = ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(open_csv(path)?);
+            let mut records = reader.records().peekable();
+            let layout = CsvLayout::read(&mut records, label_column, color_column, feature_columns)
+                .with_context(|| format!("Error reading {}", path.display()))?;
+            ensure!(
+                layout.n_features > 0,
+                "{} contains no feature columns",
+                path.display()
+            );
+            Ok(Some(layout.n_features))
+        }
+        Input::Npy(path) => {
+            ensure!(
+                label_column.is_none() && color_column.is_none() && feature_columns.is_none(),
+                "{} is a NumPy array without named columns, so --label-column, --color-column \
+                 and --features cannot be used with it",
+                path.display()
+            );
+            File::open(path)
+                .with_context(|| format!("Error opening NumPy file {}", path.display()))?;
+            Ok(None)
+        }
+        Input::Libsvm(path) => {
+            ensure!(
+                label_column.is_none() && color_column.is_none() && feature_columns.is_none(),
+                "{} is a LIBSVM file, whose labels come first on each line, so --label-column, \
+                 --color-column and --features cannot be used with it",
+                path.display()
+            );
+            open_text(path, "LIBSVM")?;
+            Ok(None)
+        }
+    }
+}
+
+/// Header and selected columns of a CSV file, determined from its first rows
+struct CsvLayout {
+    /// Header row, if the first row holds column names
+    header: Option<StringRecord>,
+
+    /// First data row, read while checking for a header
+    first_row: Option<StringRecord>,
+
+    /// Index of the label column, if one was selected
+    label_index: Option<usize>,
+
+    /// Index of the color column, if one was selected
+    color_index: Option<usize>,
+
+    /// Whether each column is a feature
+    features: Vec<bool>,
+
+    /// Number of cells in each row that are features
+    n_features: usize,
+}
+
+impl CsvLayout {
+    /// Reads the header, if any, and resolves the selected columns, leaving
+    /// the data rows to be read
+    ///
+    /// # Errors
+    /// Returns an error if the first row cannot be read, a named column is
+    /// missing from the header, a column number is beyond the last column, the
+    /// label and color columns are the same, or either is selected as a feature
+    fn read<R: Read>(
+        records: &mut Peekable<StringRecordsIter<R>>,
+        label_column: Option<&LabelColumn>,
+        color_column: Option<&LabelColumn>,
+        feature_columns: Option<&FeatureColumns>,
+    ) -> Result<Self> {
+        // The second row is peeked at to tell a header from a first row of data
+        let first = records.next().transpose()?;
+        let is_header = first.as_ref().is_some_and(|first| {
+            let second = records.peek().and_then(|second| second.as_ref().ok());
+            is_header_row(first, second, label_column, feature_columns)
+        });
+        let (header, first_row) = if is_header {
+            (first, None)
+        } else {
+            (None, first)
+        };
+
+        // Rows must all have the same width, so the first one determines the shape
+        let width = header
+            .as_ref()
+            .or(first_row.as_ref())
+            .map_or(0, StringRecord::len);
+        let label_index = resolve_column("Label", label_column, header.as_ref(), width)?;
+        let color_index = resolve_column("Color", color_column, header.as_ref(), width)?;
+        ensure!(
+            label_index.is_none() || label_index != color_index,
+            "The label and color columns must be different columns"
+        );
+        let features = match feature_columns {
+            Some(selection) => {
+                let features = selection.resolve(header.as_ref(), width)?;
+                for (role, index) in [("label", label_index), ("color", color_index)] {
+                    if let Some(index) = index.filter(|&index| features[index]) {
+                        bail!(
+                            "--features selects {}, which is the {role} column",
+                            describe_column(index, header.as_ref())
+                        );
+                    }
+                }
+                features
+            }
+            None => (0..width)
+                .map(|column| Some(column) != label_index && Some(column) != color_index)
+                .collect(),
+        };
+        let n_features = features.iter().filter(|&&feature| feature).count();
+
+        Ok(Self {
+            header,
+            first_row,
+            label_index,
+            color_index,
+            features,
+            n_features,
+        })
+    }
+}
+
+/// Returns whether the first row of a CSV file holds column names rather than data
+///
+/// Only the cells read as numbers decide: the first row is a header if one of
+/// them is not a number. The label column, when selected by position, and the
+/// columns a selection by position leaves out may hold text in a headerless
+/// file too, so text there only marks a header if the second row has a number
+/// in the same column. A selection naming a column needs a header, and a named
+/// label column implies one anyway, as its name is text. A header of numbers
+/// only, such as pandas writes for unnamed columns, is read as data.
+///
+/// # Arguments
+/// * `first` - First row of the file
+/// * `second` - Second row of the file, if any
+/// * `label_column` - Selected label column, if any
+/// * `feature_columns` - Selected feature columns, if not all others
+fn is_header_row(
+    first: &StringRecord,
+    second: Option<&StringRecord>,
+    label_column: Option<&LabelColumn>,
+    feature_columns: Option<&FeatureColumns>,
+) -> bool {
+    if feature_columns.is_some_and(FeatureColumns::names_columns) {
+        return true;
+    }
+    let numeric = |cell: &str| cell.trim().parse::<f32>().is_ok();
+    let label_index = match label_column {
+        Some(LabelColumn::Last) => first.len().checked_sub(1),
+        Some(LabelColumn::Index(index) | LabelColumn::Number { index, .. }) => Some(index - 1),
+        _ => None,
+    };
+    let selected = feature_columns.map(|selection| selection.positions(first.len()));
+    first.iter().enumerate().any(|(column, cell)| {
+        let free_text = Some(column) == label_index
+            || selected.as_ref().is_some_and(|selected| !selected[column]);
+        !numeric(cell)
+            && (!free_text || second.and_then(|row| row.get(column)).is_some_and(numeric))
+    })
+}
+
+/// Opens a CSV file for reading, decompressing it if it is gzipped
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or read
+fn open_csv(path: &Path) -> Result<Box<dyn Read>> {
+    open_text(path, "CSV")
+}
+
+/// Opens a text file for reading, decompressing it if it is gzipped
+///
+/// # Arguments
+/// * `path` - File to open
+/// * `kind` - Format of the file, named in errors
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or read
+fn open_text(path: &Path, kind: &str) -> Result<Box<dyn Read>> {
+    let file = File::open(path)
+        .with_context(|| format!("Error opening {kind} file {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let start = reader
+        .fill_buf()
+        .with_context(|| format!("Error reading {kind} file {}", path.display()))?;
+    if start.starts_with(&GZIP_MAGIC) {
+        // Gzip files may hold several concatenated members, e.g. from appending
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Checks that every value of the data is finite
+///
+/// PaCMAP panics or silently produces a meaningless embedding on NaN or
+/// infinite values, so they are rejected before embedding.
+///
+/// # Arguments
+/// * `x` - Data of shape (n_samples, n_features)
+///
+/// # Errors
+/// Returns an error naming the 0-based row and column of the first non-finite
+/// value
+pub fn validate_matrix(x: ArrayView2<f32>) -> Result<()> {
+    match x.indexed_iter().find(|(_, value)| !value.is_finite()) {
+        Some(((row, column), value)) => bail!(
+            "Input contains the non-finite value {value} at row {row}, column {column} (0-based)"
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Finds the index of a selected column within rows of `width` cells
+///
+/// # Arguments
+/// * `role` - What the column holds, used in error messages
+/// * `column` - Selected column, if any
+/// * `header` - Header row, if the CSV has one
+/// * `width` - Number of cells in each row
+///
+/// # Errors
+/// Returns an error if a named column is requested without a header, the name
+/// does not appear in the header, or a column number is beyond the last column
+fn resolve_column(
+    role: &str,
+    column: Option<&LabelColumn>,
+    header: Option<&StringRecord>,
+    width: usize,
+) -> Result<Option<usize>> {
+    if let Some(LabelColumn::Number { name, .. }) = column {
+        if let Some(index) = header.and_then(|header| header_position(header, name)) {
+            return Ok(Some(index));
+        }
+    }
+    match column {
+        None => Ok(None),
+        Some(LabelColumn::Last) => Ok(width.checked_sub(1)),
+        Some(LabelColumn::Index(index) | LabelColumn::Number { index, .. }) => {
+            ensure!(
+                *index <= width,
+                "{role} column #{index} requested but the CSV has only {width} columns"
+            );
+            Ok(Some(index - 1))
+        }
+        Some(LabelColumn::Named(name)) => {
+            let header = header.ok_or_else(|| {
+                anyhow!(
+                    "{role} column '{name}' requested but the CSV has no header row; select the \
+                     column by position instead, such as #1"
+                )
+            })?;
+            let index = header_position(header, name).ok_or_else(|| {
+                anyhow!(
+                    "{role} column '{name}' not found in CSV header; select the column by \
+                     position instead, such as #1"
+                )
+            })?;
+            Ok(Some(index))
+        }
+    }
+}
+
+/// Formats a column for error messages, including its name when a header is present
+fn describe_column(column: usize, header: Option<&StringRecord>) -> String {
+    match header.and_then(|h| h.get(column)) {
+        Some(name) => format!("column {} ('{}')", column + 1, name.trim()),
+        None => format!("column {}", column + 1),
+    }
+}
+
+/// Formats a cell location for error messages, including the column name
+/// when a header is present
+fn describe_cell(line: u64, column: usize, header: Option<&StringRecord>) -> String {
+    match header.and_then(|h| h.get(column)) {
+        Some(name) => format!("line {line}, column {} ('{}')", column + 1, name.trim()),
+        None => format!("line {line}, column {}", column + 1),
+    }
+}
@@ -0,0 +1,72 @@
+//! Graceful handling of Ctrl-C
+//!
+//! PaCMAP offers no way to cancel a running optimization, so an interrupt is
+//! recorded instead of killing the process, and checked between the phases of
+//! a run. A run stopped this way writes no further outputs, so an interrupt can
+//! never leave a half-written visualization behind. Pressing Ctrl-C a second
+//! time exits immediately.
+//!
+//! The handler is installed on Unix platforms; elsewhere Ctrl-C keeps its
+//! default behavior of ending the process at once.
+
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether Ctrl-C has been pressed
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Message written to stderr when the first interrupt arrives
+#[cfg(unix)]
+const INTERRUPT_MESSAGE: &[u8] =
+    b"\nInterrupted; stopping after the current step (press Ctrl-C again to quit now)\n";
+
+/// Records interrupts instead of letting them end the process
+///
+/// Only the first Ctrl-C is caught; the handler then restores the default
+/// behavior, so a second one ends the process as usual.
+pub fn install_handler() {
+    #[cfg(unix)]
+    // SAFETY: the handler only stores to an atomic and calls the
+    // async-signal-safe functions write and signal
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// Signal handler recording the interrupt
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // SAFETY: write and signal are async-signal-safe, and the message is a
+    // static buffer
+    unsafe {
+        libc::write(
+            libc::STDERR_FILENO,
+            INTERRUPT_MESSAGE.as_ptr().cast(),
+            INTERRUPT_MESSAGE.len(),
+        );
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Returns whether Ctrl-C has been pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Stops the run if Ctrl-C has been pressed
+///
+/// # Arguments
+/// * `next` - The step that would run next, named in the error
+///
+/// # Errors
+/// Returns an error if the run was interrupted
+pub fn check_interrupted(next: &str) -> Result<()> {
+    if interrupted() {
+        bail!("Interrupted by Ctrl-C before {next}; no further outputs were written");
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Static image rendering through the Kaleido executable
+//!
+//! Plotly renders PNG and SVG images with Kaleido, a headless browser shipped
+//! as a standalone executable. This module runs that executable directly and
+//! speaks its JSON protocol: one figure request per line on stdin, one result
+//! per line on stdout.
+
+use anyhow::{bail, ensure, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use plotly::Plot;
+use serde_json::{json, Value};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Environment variable naming the Kaleido executable to use
+pub const KALEIDO_PATH_VAR: &str = "KALEIDO_PATH";
+
+/// Explains how to make Kaleido available when it cannot be found
+const INSTALL_HINT: &str = "Static image export needs the Kaleido executable, which was not found.
+Install it with `pip install kaleido==0.2.1`, then either put its `kaleido` launcher
+(in the `kaleido/executable/` directory of the installed package) on your PATH,
+or set KALEIDO_PATH to its location. Use `--format html` to skip static export.";
+
+/// Locates the Kaleido executable
+///
+/// Uses the path in [`KALEIDO_PATH_VAR`] if set, and otherwise searches the
+/// `PATH` for a `kaleido` executable.
+///
+/// # Errors
+/// Returns an error with installation instructions if Kaleido cannot be found
+pub fn find_kaleido() -> Result<PathBuf> {
+    if let Some(path) = env::var_os(KALEIDO_PATH_VAR) {
+        let path = PathBuf::from(path);
+        ensure!(
+            path.is_file(),
+            "{KALEIDO_PATH_VAR} is set to {}, which does not exist.\n{INSTALL_HINT}",
+            path.display()
+        );
+        return Ok(path);
+    }
+
+    let names: &[&str] = if cfg!(windows) {
+        &["kaleido.cmd", "kaleido.exe"]
+    } else {
+        &["kaleido"]
+    };
+    env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+        .context(INSTALL_HINT)
+}
+
+/// Renders a plot as a static image
+///
+/// # Arguments
+/// * `plot` - Plot to render
+/// * `format` - Kaleido image format, such as `png` or `svg`
+/// * `width` - Image width in layout pixels
+/// * `height` - Image height in layout pixels
+/// * `scale` - Multiplier applied to the pixel density; 2 doubles the resolution
+///
+/// # Returns
+/// The encoded image file contents
+///
+/// # Errors
+/// Returns an error if Kaleido cannot be found or started, or reports a
+/// rendering failure
+pub fn render_image(
+    plot: &Plot,
+    format: &str,
+    width: usize,
+    height: usize,
+    scale: f64,
+) -> Result<Vec<u8>> {
+    let kaleido = find_kaleido()?;
+    let figure: Value = serde_json::from_str(&plot.to_json())?;
+    let request = json!({
+        "format": format,
+        "width": width,
+        "height": height,
+        "scale": scale,
+        "data": figure,
+    });
+
+    let mut process = Command::new(&kaleido)
+        .args([
+            "plotly",
+            "--disable-gpu",
+            "--allow-file-access-from-files",
+            "--disable-extensions",
+            "--disable-local-file-accesses",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Error starting Kaleido at {}", kaleido.display()))?;
+
+    // Closing stdin after the request tells Kaleido to exit once it is rendered
+    {
+        let mut stdin = process.stdin.take().context("Kaleido stdin unavailable")?;
+        writeln!(stdin, "{request}").context("Error sending figure to Kaleido")?;
+    }
+
+    let stdout = process
+        .stdout
+        .take()
+        .context("Kaleido stdout unavailable")?;
+    let mut image = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("Error reading Kaleido output")?;
+        let Ok(response) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if response["code"].as_i64().is_some_and(|code| code != 0) {
+            bail!(
+                "Kaleido failed to render the {format} image: {}",
+                response["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        if let Some(result) = response["result"].as_str() {
+            image = Some(match format {
+                "svg" => result.as_bytes().to_vec(),
+                _ => STANDARD
+                    .decode(result)
+                    .context("Kaleido returned an invalid image encoding")?,
+            });
+            break;
+        }
+    }
+    process
+        .wait()
+        .context("Error waiting for Kaleido to exit")?;
+
+    image.with_context(|| format!("Kaleido exited without producing a {format} image"))
+}
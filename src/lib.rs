@@ -0,0 +1,300 @@
+//! Reusable building blocks of the PaCMAP example pipeline
+//!
+//! The command-line example in `main.rs` only parses its options into a
+//! [`cli::Cli`] and dispatches to the pipeline in [`run`], [`replot`] and
+//! [`outputs`]. The library can also be used to embed and plot data from other
+//! programs and tests:
+//!
+//! ```no_run
+//! use ndarray::Array2;
+//! use pacmap::Configuration;
+//! use pacmap_rs_example::{build_scatter, embed};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let (x, labels) = pacmap_rs_example::dataset::load_dataset(
+//!     pacmap_rs_example::dataset::Dataset::Mnist,
+//!     pacmap_rs_example::dataset::CachePolicy::Reuse,
+//!     pacmap_rs_example::dataset::DEFAULT_MAX_DOWNLOAD_SIZE,
+//! )?;
+//! let embedding: Array2<f32> = embed(x.view(), Configuration::default())?;
+//! let traces = build_scatter(embedding.view(), &labels)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The core of the pipeline is [`fit`], which only runs PaCMAP: it reads no
+//! files, downloads nothing and logs nothing, so it can be wrapped by programs
+//! with their own I/O. Dataset-specific code lives in [`dataset`], with
+//! reading and writing of user data in [`input`] and [`export`], and plotting
+//! in [`plot`]. The functions of this module and
+//! dataset loading report failures as a [`PipelineError`], so callers can
+//! react to the kind of failure.
+
+pub mod anchors;
+pub mod cache;
+pub mod cli;
+pub mod compare;
+pub mod config;
+pub mod constant;
+pub mod dataset;
+pub mod distance;
+pub mod duplicates;
+pub mod error;
+pub mod export;
+pub mod graph;
+pub mod grid;
+pub mod input;
+pub mod interrupt;
+pub mod kaleido;
+pub mod logging;
+pub mod loss;
+pub mod manifest;
+pub mod memory;
+pub mod metrics;
+pub mod normalize;
+pub mod outputs;
+pub mod pca;
+pub mod plot;
+pub mod profile;
+pub mod progress;
+pub mod replot;
+pub mod run;
+pub mod sample;
+pub mod saved;
+pub mod timing;
+pub mod watch;
+
+use anyhow::{anyhow, Context};
+pub use error::PipelineError;
+use ndarray::{Array1, Array2, Array3, ArrayView2};
+use pacmap::Configuration;
+pub use plot::embedding_bounds;
+use plotly::{Plot, Scatter};
+use rayon::ThreadPoolBuilder;
+use tracing::{info, warn};
+
+/// Runs PaCMAP on the data with a fixed seed, without any I/O or logging
+///
+/// This is the minimal building block the rest of the pipeline wraps. It
+/// touches no files or network and emits no log events of its own; PaCMAP's
+/// own debug messages only appear if the caller installs a `tracing`
+/// subscriber. `seed` replaces `config.seed`, and like any seeded run of
+/// [`embed`], the fit runs on a single thread so the embedding is bit-for-bit
+/// reproducible.
+///
+/// Besides the embedding, `pacmap::fit_transform` returns the layouts captured
+/// at the iterations in `config.snapshots`. They are diagnostics of the
+/// optimization, not a fitted model that could place new points, so `fit`
+/// clears `config.snapshots` rather than capture layouts it would discard; use
+/// [`embed_with_snapshots`] to keep them.
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+/// * `config` - PaCMAP configuration
+/// * `seed` - Seed of PaCMAP's sampling and initialization
+///
+/// # Returns
+/// Embedding of shape (n_samples, `config.embedding_dimensions`)
+///
+/// # Errors
+/// Returns [`PipelineError::Embedding`] if the thread pool cannot be created
+/// or PaCMAP fails, and [`PipelineError::Shape`] if PaCMAP returns an
+/// embedding of a different shape than configured
+pub fn fit(
+    x: ArrayView2<f32>,
+    mut config: Configuration,
+    seed: u64,
+) -> Result<Array2<f32>, PipelineError> {
+    config.seed = Some(seed);
+    config.snapshots = None;
+    let (embedding, _) = run_pacmap(x, config)?;
+    Ok(embedding)
+}
+
+/// Runs PaCMAP on the data, reproducibly if the configuration is seeded
+///
+/// A seed fixes PaCMAP's sampling and initialization, but its parallel
+/// gradient reduction still sums in whatever order threads finish. Seeded runs
+/// therefore execute on a single thread so the embedding is bit-for-bit
+/// identical across runs.
+///
+/// Snapshots requested in `config.snapshots` are not captured, with a warning,
+/// as only the embedding is returned; use [`embed_with_snapshots`] to keep them.
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+/// * `config` - PaCMAP configuration
+///
+/// # Returns
+/// Embedding of shape (n_samples, `config.embedding_dimensions`)
+///
+/// # Errors
+/// Returns [`PipelineError::Embedding`] if the thread pool cannot be created
+/// or PaCMAP fails, and [`PipelineError::Shape`] if PaCMAP returns an
+/// embedding of a different shape than configured
+pub fn embed(x: ArrayView2<f32>, mut config: Configuration) -> Result<Array2<f32>, PipelineError> {
+    if config.snapshots.take().is_some() {
+        warn!(
+            "Skipping the snapshots requested from embed, which returns only the embedding; \
+             call embed_with_snapshots to keep them"
+        );
+    }
+    let (embedding, _) = embed_with_snapshots(x, config)?;
+    Ok(embedding)
+}
+
+/// Runs PaCMAP like [`embed`], also returning the layouts captured during the
+/// optimization
+///
+/// Layouts are captured at the iterations listed in `config.snapshots`; an
+/// iteration of 0 captures the initialization. They are the second value
+/// `pacmap::fit_transform` returns, which holds nothing else: PaCMAP keeps no
+/// fitted model that could embed new points later.
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+/// * `config` - PaCMAP configuration
+///
+/// # Returns
+/// A tuple containing:
+/// * Embedding of shape (n_samples, `config.embedding_dimensions`)
+/// * Captured layouts of shape (n_snapshots, n_samples, `config.embedding_dimensions`),
+///   if snapshots were requested
+///
+/// # Errors
+/// Returns [`PipelineError::Embedding`] if the thread pool cannot be created
+/// or PaCMAP fails, and [`PipelineError::Shape`] if PaCMAP returns an
+/// embedding of a different shape than configured
+pub fn embed_with_snapshots(
+    x: ArrayView2<f32>,
+    config: Configuration,
+) -> Result<(Array2<f32>, Option<Array3<f32>>), PipelineError> {
+    if let Some(seed) = config.seed {
+        info!("Using seed {seed}; running PaCMAP on a single thread for reproducibility");
+    }
+    run_pacmap(x, config)
+}
+
+/// Runs PaCMAP, on a single thread if seeded, and checks the embedding's shape
+fn run_pacmap(
+    x: ArrayView2<f32>,
+    config: Configuration,
+) -> Result<(Array2<f32>, Option<Array3<f32>>), PipelineError> {
+    let seed = config.seed;
+    let dims = config.embedding_dimensions;
+    let fit = || pacmap::fit_transform(x, config);
+    let (embedding, snapshots) = match seed {
+        Some(_) => ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .context("Error creating single-threaded pool for seeded run")
+            .map_err(PipelineError::Embedding)?
+            .install(fit),
+        None => fit(),
+    }
+    .map_err(|e| PipelineError::Embedding(e.into()))?;
+
+    // Plotting picks 2D or 3D from the column count, so a mismatch would silently misdraw
+    if embedding.dim() != (x.nrows(), dims) {
+        return Err(PipelineError::Shape(anyhow!(
+            "PaCMAP returned an embedding of shape {:?}, expected ({}, {dims}) for the configured \
+             embedding dimensions",
+            embedding.dim(),
+            x.nrows()
+        )));
+    }
+    Ok((embedding, snapshots))
+}
+
+/// Builds scatter plot traces of a 2D embedding, one per label
+///
+/// Each label gets its own color and legend entry showing the raw label value;
+/// use [`plot::create_scatter_plot`] to show human-readable class names
+/// instead.
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, 2) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,)
+///
+/// # Errors
+/// Returns [`PipelineError::Shape`] if the embedding does not have exactly 2
+/// dimensions or `labels` is of a different length
+pub fn build_scatter(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+) -> Result<Vec<Box<Scatter<f32, f32>>>, PipelineError> {
+    check_plot_shape(embedding, labels, &[2])?;
+    let labels = plot::Labels::Discrete(labels.clone());
+    plot::create_scatter_plot(
+        embedding,
+        Some(&labels),
+        &[],
+        &plot::HoverInfo::default(),
+        &plot::PlotStyle::default(),
+    )
+    .map_err(PipelineError::Plot)
+}
+
+/// Adds an embedding to a plot owned by the caller, one trace per label
+///
+/// The plot's layout is left untouched, so the embedding can be combined with
+/// other traces in a custom dashboard. Like [`build_scatter`], legend entries
+/// show the raw label values; use [`plot::add_embedding_traces`] for class
+/// names, hover details and styling.
+///
+/// # Arguments
+/// * `plot` - Plot the traces are appended to
+/// * `embedding` - Array of shape (n_samples, 2) or (n_samples, 3) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,)
+///
+/// # Errors
+/// Returns [`PipelineError::Shape`] if the embedding has neither 2 nor 3
+/// dimensions or `labels` is of a different length
+pub fn add_embedding_traces(
+    plot: &mut Plot,
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+) -> Result<(), PipelineError> {
+    check_plot_shape(embedding, labels, &[2, 3])?;
+    let labels = plot::Labels::Discrete(labels.clone());
+    plot::add_embedding_traces(
+        plot,
+        embedding,
+        Some(&labels),
+        &[],
+        &plot::HoverInfo::default(),
+        &plot::PlotStyle::default(),
+    )
+    .map_err(PipelineError::Plot)
+}
+
+/// Checks that an embedding can be plotted with the given labels
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels, expected to be of shape (n_samples,)
+/// * `dimensions` - Numbers of dimensions the plot supports
+///
+/// # Errors
+/// Returns [`PipelineError::Shape`] if the embedding has an unsupported number
+/// of dimensions or the lengths differ
+fn check_plot_shape(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    dimensions: &[usize],
+) -> Result<(), PipelineError> {
+    if !dimensions.contains(&embedding.ncols()) {
+        return Err(PipelineError::Shape(anyhow!(
+            "Expected an embedding with {dimensions:?} dimensions, got {}",
+            embedding.ncols()
+        )));
+    }
+    if labels.len() != embedding.nrows() {
+        return Err(PipelineError::Shape(anyhow!(
+            "Got {} labels for an embedding of {} points",
+            labels.len(),
+            embedding.nrows()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Log output for people and for log aggregators
+//!
+//! Logs are written for reading in a terminal by default. Runs under an
+//! orchestrator can switch to one JSON object per line instead, which carries
+//! the structured fields some events attach next to their message: `phase`
+//! and `duration_ms` for the timing breakdown, `phase`, `iteration` and
+//! `loss` for the loss of each optimization phase, and `metric`, `k` and
+//! `value` for quality scores. The human-readable format shows messages
+//! alone, as they already say everything the fields hold.
+//!
+//! Whatever level is shown, PaCMAP's debug messages are also passed to
+//! [`LossLayer`], which records the loss they report. The spans of timed
+//! phases are never shown, but go to [`ProfileLayer`] when profiling.
+
+use crate::loss::{LossLayer, PACMAP_TARGET};
+use crate::profile::{ProfileLayer, PROFILE_TARGET};
+use serde_json::{Map, Number, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::{self, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Installs the global logger
+///
+/// # Arguments
+/// * `level` - Most detailed level of messages to show
+/// * `json` - Write each event as a JSON object instead of a line of text
+/// * `profile` - Record the spans of timed phases for a profile trace
+pub fn init(level: Level, json: bool, profile: bool) {
+    let profiler = profile
+        .then(|| ProfileLayer.with_filter(Targets::new().with_target(PROFILE_TARGET, Level::INFO)));
+    let registry = tracing_subscriber::registry()
+        .with(LossLayer.with_filter(Targets::new().with_target(PACMAP_TARGET, Level::DEBUG)))
+        .with(profiler);
+    let filter = Targets::new()
+        .with_default(LevelFilter::from_level(level))
+        .with_target(PROFILE_TARGET, LevelFilter::OFF);
+    if json {
+        let layer = tracing_subscriber::fmt::layer().event_format(JsonFormat);
+        registry.with(layer.with_filter(filter)).init();
+    } else {
+        let layer = tracing_subscriber::fmt::layer().fmt_fields(format::debug_fn(
+            |writer, field, value| {
+                if field.name() == "message" {
+                    write!(writer, "{value:?}")
+                } else {
+                    Ok(())
+                }
+            },
+        ));
+        registry.with(layer.with_filter(filter)).init();
+    }
+}
+
+/// Event format writing one JSON object per line
+///
+/// Each object holds the event's `timestamp`, `level`, `target` and
+/// `message`, along with its structured fields under their own names.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+
+        let mut fields = JsonFields(Map::new());
+        fields.insert("timestamp", Value::String(timestamp));
+        fields.insert("level", Value::String(metadata.level().to_string()));
+        fields.insert("target", Value::String(metadata.target().to_string()));
+        event.record(&mut fields);
+        writeln!(writer, "{}", Value::Object(fields.0))
+    }
+}
+
+/// Collects the fields of an event into a JSON object
+struct JsonFields(Map<String, Value>);
+
+impl JsonFields {
+    fn insert(&mut self, name: &str, value: Value) {
+        self.0.insert(name.to_string(), value);
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // JSON has no NaN or infinity, so those are written as null
+        let number = Number::from_f64(value).map_or(Value::Null, Value::Number);
+        self.insert(field.name(), number);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field.name(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field.name(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field.name(), Value::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field.name(), Value::String(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field.name(), Value::String(format!("{value:?}")));
+    }
+}
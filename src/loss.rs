@@ -0,0 +1,150 @@
+//! Loss of PaCMAP's optimization phases
+//!
+//! PaCMAP has no callback or loss history, but it logs its loss at debug
+//! level on the first iteration and every tenth one after. [`LossLayer`]
+//! picks those messages out of the log, whatever level is shown, so the loss
+//! at the end of each phase can be reported to tell whether the optimization
+//! settled. Each phase weighs the pairs differently, so losses are only
+//! comparable within a phase, not across phases. A loss still falling steeply
+//! at the end of a phase suggests giving that phase more iterations.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{info, Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Target of the log messages PaCMAP reports its loss in
+pub const PACMAP_TARGET: &str = "pacmap";
+
+/// Names of PaCMAP's three optimization phases, in order
+const PHASES: [&str; 3] = ["attraction", "local", "global"];
+
+/// Losses recorded since the last call to [`start_recording`], as (iteration, loss)
+/// with iterations counted from 1
+static HISTORY: Mutex<Vec<(usize, f64)>> = Mutex::new(Vec::new());
+
+/// Loss at the end of one optimization phase
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PhaseLoss {
+    /// Name of the phase: attraction, local or global
+    pub phase: String,
+
+    /// Last iteration of the phase PaCMAP logged its loss at, counted from 1
+    pub iteration: usize,
+
+    /// Loss at that iteration
+    pub loss: f64,
+
+    /// Relative change of the loss since the first iteration of the phase PaCMAP
+    /// logged its loss at, in percent, if it logged more than one
+    pub change_percent: Option<f64>,
+}
+
+/// Logging layer recording the loss PaCMAP reports during a fit
+///
+/// Only events from PaCMAP should reach it, raised to debug level, so it is
+/// meant to be installed with a per-layer filter on [`PACMAP_TARGET`].
+pub struct LossLayer;
+
+impl<S: Subscriber> Layer<S> for LossLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        if let Some(point) = parse_loss(&message.0) {
+            if let Ok(mut history) = HISTORY.lock() {
+                history.push(point);
+            }
+        }
+    }
+}
+
+/// Collects the message of an event
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+/// Extracts the iteration and loss from one of PaCMAP's log messages
+///
+/// PaCMAP writes `Initial Loss: <loss>` on the first iteration, and
+/// `Iteration: <n>, Loss: <loss>` on every tenth one.
+fn parse_loss(message: &str) -> Option<(usize, f64)> {
+    if let Some(loss) = message.strip_prefix("Initial Loss: ") {
+        return Some((1, loss.trim().parse().ok()?));
+    }
+    let (iteration, loss) = message.strip_prefix("Iteration:")?.split_once(", Loss: ")?;
+    Some((iteration.trim().parse().ok()?, loss.trim().parse().ok()?))
+}
+
+/// Discards the losses recorded so far, before a fit starts
+pub fn start_recording() {
+    if let Ok(mut history) = HISTORY.lock() {
+        history.clear();
+    }
+}
+
+/// Returns the loss at the end of each phase of the fit recorded since
+/// [`start_recording`]
+///
+/// Phases PaCMAP logged no loss in, which can happen to phases shorter than
+/// ten iterations, are left out.
+///
+/// # Arguments
+/// * `num_iters` - Iterations of the attraction, local and global phases
+pub fn phase_losses(num_iters: (usize, usize, usize)) -> Vec<PhaseLoss> {
+    let history = HISTORY
+        .lock()
+        .map(|history| history.clone())
+        .unwrap_or_default();
+    let (attraction, local, global) = num_iters;
+    let ends = [attraction, attraction + local, attraction + local + global];
+
+    let mut start = 0;
+    let mut losses = Vec::new();
+    for (phase, end) in PHASES.into_iter().zip(ends) {
+        let mut in_phase = history.iter().filter(|&&(i, _)| i > start && i <= end);
+        let first = in_phase.next();
+        if let Some(&(iteration, loss)) = in_phase.next_back().or(first) {
+            let change_percent = first
+                .filter(|&&(i, initial)| i < iteration && initial != 0.0)
+                .map(|&(_, initial)| 100.0 * (loss - initial) / initial.abs());
+            losses.push(PhaseLoss {
+                phase: phase.to_string(),
+                iteration,
+                loss,
+                change_percent,
+            });
+        }
+        start = end;
+    }
+    losses
+}
+
+/// Logs the loss at the end of each phase, with its change over the phase
+///
+/// # Arguments
+/// * `losses` - Losses returned by [`phase_losses`]
+pub fn log_phase_losses(losses: &[PhaseLoss]) {
+    for loss in losses {
+        let change = loss
+            .change_percent
+            .map(|change| format!(", {change:+.1}% over the phase"))
+            .unwrap_or_default();
+        info!(
+            phase = loss.phase.as_str(),
+            iteration = loss.iteration,
+            loss = loss.loss,
+            "Loss after the {} phase: {:.4} at iteration {}{change}",
+            loss.phase,
+            loss.loss,
+            loss.iteration
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Machine-readable records of embedding runs
+//!
+//! Each run writes a JSON manifest holding everything needed to reproduce and
+//! compare it: the resolved settings, the data's shape, phase timings, quality
+//! scores and the versions of the code that produced it.
+
+use crate::export::create_parent_dir;
+use crate::loss::PhaseLoss;
+use crate::saved::RunConfig;
+use crate::timing::Timings;
+use anyhow::{Context, Result};
+use pacmap::Configuration;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Version of the `pacmap` crate the example was built against
+pub const PACMAP_VERSION: &str = env!("PACMAP_VERSION");
+
+/// Record of a completed embedding run
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RunManifest {
+    /// Version of this example
+    pub example_version: String,
+
+    /// Version of the `pacmap` crate that computed the embedding
+    pub pacmap_version: String,
+
+    /// Settings given on the command line
+    pub config: RunConfig,
+
+    /// PaCMAP settings after defaults were applied
+    pub pacmap: PacmapSettings,
+
+    /// Number of worker threads available to the run
+    pub threads: usize,
+
+    /// Number of embedded samples
+    pub n_samples: usize,
+
+    /// Number of input features, after dropping constant ones and before any
+    /// PCA reduction
+    pub n_features: usize,
+
+    /// Input features dropped by `--drop-constant` for having the same value
+    /// in every row, by their index among the input's features
+    pub dropped_features: Vec<usize>,
+
+    /// Wall-clock time of each phase, in the order they ran
+    pub timings: Vec<PhaseTiming>,
+
+    /// Loss at the end of each optimization phase, empty if the embedding was
+    /// reused from the cache
+    pub loss: Vec<PhaseLoss>,
+
+    /// Quality scores of the embedding
+    pub scores: Scores,
+}
+
+/// PaCMAP settings not covered by [`RunConfig`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PacmapSettings {
+    /// Learning rate of the optimizer
+    pub learning_rate: f32,
+
+    /// Iterations of each of the three optimization phases
+    pub num_iters: (usize, usize, usize),
+
+    /// Sample count above which neighbors are found approximately
+    pub approx_threshold: usize,
+
+    /// How the embedding was initialized
+    pub initialization: String,
+}
+
+impl From<&Configuration> for PacmapSettings {
+    fn from(config: &Configuration) -> Self {
+        Self {
+            learning_rate: config.learning_rate,
+            num_iters: config.num_iters,
+            approx_threshold: config.approx_threshold,
+            initialization: format!("{:?}", config.initialization),
+        }
+    }
+}
+
+/// Wall-clock time of one phase of the run
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    /// Name of the phase
+    pub phase: String,
+
+    /// Duration of the phase in milliseconds
+    pub millis: f64,
+}
+
+/// Quality scores of an embedding
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Scores {
+    /// Number of neighbors used to score trustworthiness
+    pub trustworthiness_k: usize,
+
+    /// Trustworthiness of the embedding
+    pub trustworthiness: f64,
+
+    /// Fraction of each point's input-space neighbors kept in the embedding,
+    /// over the same number of neighbors as trustworthiness
+    pub neighbor_recall: f64,
+
+    /// Number of neighbors voting when scoring k-NN accuracy
+    pub knn_accuracy_k: usize,
+
+    /// Fraction of points whose class matches their neighbors' vote, if
+    /// labels are known
+    pub knn_accuracy: Option<f64>,
+}
+
+impl RunManifest {
+    /// Writes the manifest as pretty-printed JSON, creating parent directories
+    /// as needed
+    ///
+    /// # Errors
+    /// Returns an error if the manifest cannot be serialized or written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        create_parent_dir(path)?;
+        let json = serde_json::to_string_pretty(self).context("Error serializing run manifest")?;
+        fs::write(path, json + "\n")
+            .with_context(|| format!("Error writing run manifest {}", path.display()))
+    }
+}
+
+/// Converts phase timings into their manifest form
+pub fn phase_timings(timings: &Timings) -> Vec<PhaseTiming> {
+    timings
+        .phases()
+        .iter()
+        .map(|&(phase, duration)| PhaseTiming {
+            phase: phase.to_string(),
+            millis: duration.as_secs_f64() * 1000.0,
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+//! Peak memory measurement
+//!
+//! Wraps the global allocator to track the largest number of bytes allocated
+//! at once, and reads the operating system's peak resident set size where it
+//! is available. Together they show how close a run came to the machine's
+//! limits and whether `--sample` or `--pca` would help.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::info;
+
+/// Global allocator wrapper counting the bytes currently and at most allocated
+pub struct PeakAlloc<A> {
+    inner: A,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl<A> PeakAlloc<A> {
+    /// Wraps `inner`, starting both counters at zero
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of bytes currently allocated
+    pub fn current_bytes(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Returns the largest number of bytes allocated at once so far
+    pub fn peak_bytes(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Records `size` newly allocated bytes
+    fn grow(&self, size: usize) {
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+
+    /// Records `size` freed bytes
+    fn shrink(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for PeakAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                self.grow(new_size - layout.size());
+            } else {
+                self.shrink(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Returns the peak resident set size of the process in bytes, if known
+///
+/// Read from the `VmHWM` line of `/proc/self/status`, so only available on
+/// Linux. The resident size includes memory not allocated through the global
+/// allocator, such as BLAS buffers and the program itself.
+pub fn peak_rss() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?;
+    let kilobytes: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Logs the peak heap allocation and, where known, the peak resident memory
+///
+/// # Arguments
+/// * `peak_heap` - Largest number of bytes allocated at once, as from
+///   [`PeakAlloc::peak_bytes`]
+pub fn log_peak_memory(peak_heap: usize) {
+    let megabytes = |bytes: f64| bytes / 1e6;
+    match peak_rss() {
+        Some(rss) => info!(
+            "Peak memory: {:.1} MB allocated, {:.1} MB resident",
+            megabytes(peak_heap as f64),
+            megabytes(rss as f64)
+        ),
+        None => info!(
+            "Peak memory: {:.1} MB allocated",
+            megabytes(peak_heap as f64)
+        ),
+    }
+}
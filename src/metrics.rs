@@ -0,0 +1,524 @@
+//! Quantitative measures of embedding quality
+//!
+//! Neighbor-based scores computed from the input data and its embedding, which
+//! allow parameter settings to be compared without inspecting the plot.
+//!
+//! Neighbors are found by brute force one query point at a time, so memory use
+//! stays linear in the number of points instead of materializing an n x n
+//! distance matrix. On large datasets the scores are estimated from an evenly
+//! spaced subset of at most [`MAX_QUERIES`] query points.
+//!
+//! Points at equal distance are ordered by index, so neighbors, and the scores
+//! built on them, depend only on the data and embedding: repeated runs agree
+//! exactly, whatever the platform or number of threads.
+
+use ndarray::{Array1, Array2, ArrayView2, ArrayView3, Axis};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Maximum number of query points used to estimate a score
+pub const MAX_QUERIES: usize = 1_000;
+
+/// Computes the trustworthiness of the embedding over `k` nearest neighbors
+///
+/// Trustworthiness penalizes points that are among a point's `k` nearest
+/// neighbors in the embedding but not in the input space, weighted by how far
+/// down the input-space neighbor ranking they are. A score of 1 means every
+/// embedded neighborhood is made of true neighbors.
+///
+/// The score is averaged over at most [`MAX_QUERIES`] evenly spaced query
+/// points. Each query computes distances to all n points in both spaces, so
+/// the cost is O(m * n * (d + k)) time for m queries and d input features, and
+/// O(n) memory per thread.
+///
+/// # Arguments
+/// * `high` - Input data of shape (n_samples, n_features)
+/// * `low` - Embedding of shape (n_samples, n_dimensions)
+/// * `k` - Number of nearest neighbors to consider
+///
+/// # Panics
+/// Panics if `high` and `low` have different numbers of rows, `k` is zero, or
+/// `k` is too large for the standard normalization (`3k + 1 >= 2n`)
+pub fn trustworthiness(high: ArrayView2<f32>, low: ArrayView2<f32>, k: usize) -> f64 {
+    let n = high.nrows();
+    assert_eq!(
+        n,
+        low.nrows(),
+        "input and embedding must have the same number of rows"
+    );
+    assert!(k > 0, "trustworthiness needs at least 1 neighbor");
+    assert!(3 * k + 1 < 2 * n, "k = {k} is too large for {n} points");
+
+    let queries = query_indices(n);
+
+    // Penalties are integers, so summing them is exact in any order
+    let penalty: u64 = queries
+        .par_iter()
+        .map(|&i| {
+            let high_distances = squared_distances(high, i);
+            let low_distances = squared_distances(low, i);
+
+            nearest_neighbors(&low_distances, i, k)
+                .into_iter()
+                .map(|j| {
+                    let rank = neighbor_rank(&high_distances, i, j);
+                    rank.saturating_sub(k) as u64
+                })
+                .sum::<u64>()
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .sum();
+
+    let (m, n, k) = (queries.len() as f64, n as f64, k as f64);
+    1.0 - 2.0 * penalty as f64 / (m * k * (2.0 * n - 3.0 * k - 1.0))
+}
+
+/// Computes the neighbor recall of the embedding over `k` nearest neighbors
+///
+/// Recall is the average fraction of each point's `k` nearest neighbors in
+/// the input space that are also among its `k` nearest neighbors in the
+/// embedding. Where [`trustworthiness`] penalizes false neighbors by how far
+/// they are, recall counts the true neighbors that were lost, so together they
+/// describe how well local structure is preserved. A score of 1 means every
+/// neighborhood is kept intact.
+///
+/// Like [`trustworthiness`], the score is averaged over at most
+/// [`MAX_QUERIES`] evenly spaced query points, costing O(m * n * (d + k))
+/// time.
+///
+/// # Arguments
+/// * `high` - Input data of shape (n_samples, n_features)
+/// * `low` - Embedding of shape (n_samples, n_dimensions)
+/// * `k` - Number of nearest neighbors to compare
+///
+/// # Panics
+/// Panics if `high` and `low` have different numbers of rows, `k` is zero, or
+/// there are fewer than 2 points
+pub fn neighbor_recall(high: ArrayView2<f32>, low: ArrayView2<f32>, k: usize) -> f64 {
+    let n = high.nrows();
+    assert_eq!(
+        n,
+        low.nrows(),
+        "input and embedding must have the same number of rows"
+    );
+    assert!(k > 0, "neighbor recall needs at least 1 neighbor");
+    assert!(n > 1, "neighbor recall needs at least 2 points");
+
+    let queries = query_indices(n);
+    let k = k.min(n - 1);
+
+    // Counts are integers, so summing them is exact in any order
+    let kept: usize = queries
+        .par_iter()
+        .map(|&i| {
+            let mut high_neighbors = nearest_neighbors(&squared_distances(high, i), i, k);
+            high_neighbors.sort_unstable();
+            nearest_neighbors(&squared_distances(low, i), i, k)
+                .into_iter()
+                .filter(|j| high_neighbors.binary_search(j).is_ok())
+                .count()
+        })
+        .sum();
+
+    kept as f64 / (queries.len() * k) as f64
+}
+
+/// Computes the k-NN classification accuracy of the labels in the embedding
+///
+/// Each query point is classified by a majority vote among its `k` nearest
+/// embedded neighbors, and the score is the fraction of queries whose vote
+/// matches their own label. Tied votes go to the tied label of the nearest
+/// neighbor.
+///
+/// Like [`trustworthiness`], the score is averaged over at most
+/// [`MAX_QUERIES`] evenly spaced query points, costing O(m * n * (d + k)) time.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `k` - Number of neighbors voting on each point's class
+///
+/// # Panics
+/// Panics if `embedding` and `labels` have different lengths, `k` is zero, or
+/// there are fewer than 2 points
+pub fn knn_accuracy(embedding: ArrayView2<f32>, labels: &Array1<i32>, k: usize) -> f64 {
+    let n = embedding.nrows();
+    assert_eq!(
+        n,
+        labels.len(),
+        "embedding and labels must have the same length"
+    );
+    assert!(k > 0, "k-NN accuracy needs at least 1 neighbor");
+    assert!(n > 1, "k-NN accuracy needs at least 2 points");
+
+    let queries = query_indices(n);
+    let correct = queries
+        .par_iter()
+        .filter(|&&i| knn_vote(embedding, labels, i, k) == labels[i])
+        .count();
+
+    correct as f64 / queries.len() as f64
+}
+
+/// Predicts each point's class by a vote of its nearest embedded neighbors
+///
+/// Unlike [`knn_accuracy`], every point is voted on, which costs
+/// O(n^2 * (d + k)) time; comparing the votes with the labels shows which
+/// points sit among another class.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `k` - Number of neighbors voting on each point's class
+///
+/// # Returns
+/// The majority label of each point's `k` nearest neighbors, of shape (n_samples,)
+///
+/// # Panics
+/// Panics if `embedding` and `labels` have different lengths, `k` is zero, or
+/// there are fewer than 2 points
+pub fn knn_predictions(embedding: ArrayView2<f32>, labels: &Array1<i32>, k: usize) -> Array1<i32> {
+    let n = embedding.nrows();
+    assert_eq!(
+        n,
+        labels.len(),
+        "embedding and labels must have the same length"
+    );
+    assert!(k > 0, "k-NN predictions need at least 1 neighbor");
+    assert!(n > 1, "k-NN predictions need at least 2 points");
+
+    let predictions: Vec<i32> = (0..n)
+        .into_par_iter()
+        .map(|i| knn_vote(embedding, labels, i, k))
+        .collect();
+    Array1::from(predictions)
+}
+
+/// Returns the majority label of the `k` nearest embedded neighbors of point `i`
+fn knn_vote(embedding: ArrayView2<f32>, labels: &Array1<i32>, i: usize, k: usize) -> i32 {
+    let distances = squared_distances(embedding, i);
+    let neighbors = nearest_neighbors(&distances, i, k);
+    majority_label(neighbors.iter().map(|&j| labels[j]))
+}
+
+/// Counts the classes of each class's nearest embedded neighbors
+///
+/// Entry (i, j) of the result counts how often one of the `k` nearest
+/// embedded neighbors of a point of class i belongs to class j. Rows and
+/// columns follow the ascending label order of [`class_labels`]. A perfectly
+/// separated embedding has counts only on the diagonal, and large off-diagonal
+/// entries point out classes that overlap.
+///
+/// Like [`knn_accuracy`], the counts are taken over at most [`MAX_QUERIES`]
+/// evenly spaced query points, each contributing `k` neighbors.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `k` - Number of neighbors counted for each query point
+///
+/// # Panics
+/// Panics if `embedding` and `labels` have different lengths, `k` is zero, or
+/// there are fewer than 2 points
+pub fn neighbor_confusion(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    k: usize,
+) -> Array2<u32> {
+    let n = embedding.nrows();
+    assert_eq!(
+        n,
+        labels.len(),
+        "embedding and labels must have the same length"
+    );
+    assert!(k > 0, "neighbor confusion needs at least 1 neighbor");
+    assert!(n > 1, "neighbor confusion needs at least 2 points");
+
+    let classes = class_labels(labels);
+    let index: HashMap<i32, usize> = classes.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let neighbors: Vec<(usize, Vec<usize>)> = query_indices(n)
+        .par_iter()
+        .map(|&i| {
+            let distances = squared_distances(embedding, i);
+            (i, nearest_neighbors(&distances, i, k))
+        })
+        .collect();
+
+    let mut confusion = Array2::zeros((classes.len(), classes.len()));
+    for (i, neighbors) in neighbors {
+        for j in neighbors {
+            confusion[[index[&labels[i]], index[&labels[j]]]] += 1;
+        }
+    }
+    confusion
+}
+
+/// Computes the mean silhouette score of the classes in the embedding
+///
+/// A point's silhouette compares its mean distance `a` to the other points of
+/// its class with its mean distance `b` to the points of the nearest other
+/// class, as `(b - a) / max(a, b)`. Scores range from -1 to 1: near 1 for
+/// compact, well separated classes, near 0 for touching ones, and negative for
+/// points closer to another class than to their own. Points that are the only
+/// one of their class score 0.
+///
+/// Each point's score needs its distance to every point, so like the other
+/// scores it is averaged over at most [`MAX_QUERIES`] evenly spaced points.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+///
+/// # Panics
+/// Panics if `embedding` and `labels` have different lengths or there are
+/// fewer than 2 classes
+pub fn silhouette(embedding: ArrayView2<f32>, labels: &Array1<i32>) -> f64 {
+    let n = embedding.nrows();
+    assert_eq!(
+        n,
+        labels.len(),
+        "embedding and labels must have the same length"
+    );
+    let classes = class_labels(labels);
+    assert!(classes.len() > 1, "silhouette needs at least 2 classes");
+
+    let index: HashMap<i32, usize> = classes.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let mut sizes = vec![0_usize; classes.len()];
+    for label in labels {
+        sizes[index[label]] += 1;
+    }
+
+    let queries = query_indices(n);
+    let total: f64 = queries
+        .par_iter()
+        .map(|&i| {
+            let own = index[&labels[i]];
+            if sizes[own] == 1 {
+                return 0.0;
+            }
+            let mut sums = vec![0.0_f64; classes.len()];
+            for (j, distance) in squared_distances(embedding, i).into_iter().enumerate() {
+                sums[index[&labels[j]]] += f64::from(distance).sqrt();
+            }
+            let a = sums[own] / (sizes[own] - 1) as f64;
+            let b = (0..classes.len())
+                .filter(|&c| c != own)
+                .map(|c| sums[c] / sizes[c] as f64)
+                .fold(f64::INFINITY, f64::min);
+            // Coinciding points are as close to other classes as to their own
+            let max = a.max(b);
+            if max > 0.0 {
+                (b - a) / max
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    total / queries.len() as f64
+}
+
+/// Size, position and spread of one class in an embedding
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassSummary {
+    /// Label of the class
+    pub label: i32,
+
+    /// Number of points of the class
+    pub count: usize,
+
+    /// Mean position of the class's points, one coordinate per dimension
+    pub centroid: Vec<f64>,
+
+    /// Mean distance between two points of the class, or 0 for a single point
+    pub mean_distance: f64,
+}
+
+/// Summarizes each class of the embedding by its size, centroid and spread
+///
+/// The mean pairwise distance of a class with more than [`MAX_QUERIES`] points
+/// is estimated from an evenly spaced subset of that many of its points.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+///
+/// # Returns
+/// One summary per class, in the ascending label order of [`class_labels`]
+///
+/// # Panics
+/// Panics if `embedding` and `labels` have different lengths
+pub fn class_summaries(embedding: ArrayView2<f32>, labels: &Array1<i32>) -> Vec<ClassSummary> {
+    assert_eq!(
+        embedding.nrows(),
+        labels.len(),
+        "embedding and labels must have the same length"
+    );
+    class_labels(labels)
+        .into_par_iter()
+        .map(|label| {
+            let rows: Vec<usize> = (0..labels.len()).filter(|&i| labels[i] == label).collect();
+            let points = embedding.select(Axis(0), &rows);
+            let centroid = (0..points.ncols())
+                .map(|d| {
+                    points.column(d).iter().map(|&v| f64::from(v)).sum::<f64>() / rows.len() as f64
+                })
+                .collect();
+
+            let sample = points.select(Axis(0), &query_indices(rows.len()));
+            let m = sample.nrows();
+            let sum: f64 = (0..m)
+                .map(|i| {
+                    let distances = squared_distances(sample.view(), i);
+                    distances[i + 1..]
+                        .iter()
+                        .map(|&d| f64::from(d).sqrt())
+                        .sum::<f64>()
+                })
+                .sum();
+            let pairs = m * (m - 1) / 2;
+            ClassSummary {
+                label,
+                count: rows.len(),
+                centroid,
+                mean_distance: if pairs == 0 { 0.0 } else { sum / pairs as f64 },
+            }
+        })
+        .collect()
+}
+
+/// Returns how far points moved between the last two captured layouts,
+/// relative to the spread of the last one
+///
+/// A value near 0 means the layout had settled by the end of the
+/// optimization; a larger one suggests more iterations would still change it.
+///
+/// # Arguments
+/// * `snapshots` - Layouts of shape (n_snapshots, n_samples, n_dims), in the
+///   order they were captured
+///
+/// # Returns
+/// Mean distance a point moved divided by the root mean square distance of the
+/// last layout's points from their centroid, or `None` with fewer than two
+/// layouts, no points, or a last layout collapsed onto a single point
+pub fn snapshot_movement(snapshots: ArrayView3<f32>) -> Option<f64> {
+    let frames = snapshots.len_of(Axis(0));
+    if frames < 2 || snapshots.len_of(Axis(1)) == 0 {
+        return None;
+    }
+    let before = snapshots.index_axis(Axis(0), frames - 2);
+    let last = snapshots.index_axis(Axis(0), frames - 1);
+    let n = last.nrows() as f64;
+
+    let centroid = last.mapv(f64::from).mean_axis(Axis(0))?;
+    let squared_spread = last
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let deviations = row.iter().zip(&centroid).map(|(&v, &c)| f64::from(v) - c);
+            deviations.map(|d| d * d).sum::<f64>()
+        })
+        .sum::<f64>()
+        / n;
+    if squared_spread <= 0.0 {
+        return None;
+    }
+    let moved = before
+        .rows()
+        .into_iter()
+        .zip(last.rows())
+        .map(|(a, b)| {
+            let steps = a
+                .iter()
+                .zip(b.iter())
+                .map(|(&p, &q)| f64::from(q) - f64::from(p));
+            steps.map(|d| d * d).sum::<f64>().sqrt()
+        })
+        .sum::<f64>()
+        / n;
+    Some(moved / squared_spread.sqrt())
+}
+
+/// Returns the distinct labels in ascending order
+pub fn class_labels(labels: &Array1<i32>) -> Vec<i32> {
+    let mut classes = labels.to_vec();
+    classes.sort_unstable();
+    classes.dedup();
+    classes
+}
+
+/// Returns the most common label, preferring the earliest on ties
+///
+/// # Arguments
+/// * `labels` - Labels of the neighbors, nearest first
+fn majority_label(labels: impl Iterator<Item = i32>) -> i32 {
+    let mut counts: HashMap<i32, (usize, usize)> = HashMap::new();
+    for (position, label) in labels.enumerate() {
+        counts.entry(label).or_insert((0, position)).0 += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(_, (count_a, first_a)), (_, (count_b, first_b))| {
+            count_a.cmp(count_b).then(first_b.cmp(first_a))
+        })
+        .map(|(label, _)| label)
+        .expect("at least one neighbor votes")
+}
+
+/// Returns the indices of the query points used to estimate a score
+///
+/// Uses every point when there are at most [`MAX_QUERIES`] of them, and an
+/// evenly spaced subset otherwise.
+fn query_indices(n: usize) -> Vec<usize> {
+    let m = n.min(MAX_QUERIES);
+    (0..m).map(|q| q * n / m).collect()
+}
+
+/// Computes squared Euclidean distances from row `i` to every row of `data`
+fn squared_distances(data: ArrayView2<f32>, i: usize) -> Vec<f32> {
+    let query = data.row(i);
+    data.outer_iter()
+        .map(|row| {
+            row.iter()
+                .zip(query.iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum()
+        })
+        .collect()
+}
+
+/// Orders points by distance, breaking ties by index
+fn by_distance(distances: &[f32], a: usize, b: usize) -> Ordering {
+    distances[a].total_cmp(&distances[b]).then(a.cmp(&b))
+}
+
+/// Returns the `k` points closest to point `i`, excluding `i` itself
+///
+/// # Arguments
+/// * `distances` - Distance from point `i` to every point
+/// * `i` - Index of the query point
+/// * `k` - Number of neighbors to return
+fn nearest_neighbors(distances: &[f32], i: usize, k: usize) -> Vec<usize> {
+    let mut candidates: Vec<usize> = (0..distances.len()).filter(|&j| j != i).collect();
+    let k = k.min(candidates.len());
+    if k < candidates.len() {
+        candidates.select_nth_unstable_by(k, |&a, &b| by_distance(distances, a, b));
+        candidates.truncate(k);
+    }
+    candidates.sort_unstable_by(|&a, &b| by_distance(distances, a, b));
+    candidates
+}
+
+/// Returns the 1-based rank of point `j` among the neighbors of point `i`
+///
+/// # Arguments
+/// * `distances` - Distance from point `i` to every point
+/// * `i` - Index of the query point
+/// * `j` - Index of the point being ranked
+fn neighbor_rank(distances: &[f32], i: usize, j: usize) -> usize {
+    let closer = (0..distances.len())
+        .filter(|&l| l != i && by_distance(distances, l, j) == Ordering::Less)
+        .count();
+    closer + 1
+}
@@ -0,0 +1,132 @@
+//! Input normalization strategies
+//!
+//! Features on very different scales distort the distances PaCMAP's neighbor
+//! search relies on, so the input can be rescaled before embedding.
+
+use clap::ValueEnum;
+use ndarray::{Array2, Axis};
+use serde::Deserialize;
+
+/// Ways to rescale the input before embedding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalize {
+    /// Leave the values unchanged
+    #[default]
+    None,
+
+    /// Scale each feature to the range [0,1]
+    Minmax,
+
+    /// Center each feature on zero with unit variance
+    Zscore,
+
+    /// Scale each sample to unit Euclidean length
+    UnitL2,
+}
+
+/// Normalizes the data in place
+///
+/// Constant features, which have no range or variance to scale by, are mapped
+/// to zero by the per-feature methods. All-zero samples are left unchanged by
+/// [`Normalize::UnitL2`].
+///
+/// # Arguments
+/// * `x` - Data of shape (n_samples, n_features)
+/// * `method` - Normalization to apply
+pub fn normalize(x: &mut Array2<f32>, method: Normalize) {
+    match method {
+        Normalize::None => {}
+        Normalize::Minmax => {
+            for mut column in x.columns_mut() {
+                let min = column.fold(f32::INFINITY, |a, &b| a.min(b));
+                let max = column.fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+                let range = max - min;
+                if range > 0.0 {
+                    column.mapv_inplace(|v| (v - min) / range);
+                } else {
+                    column.fill(0.0);
+                }
+            }
+        }
+        Normalize::Zscore => {
+            for mut column in x.columns_mut() {
+                // Accumulate in f64 so large columns keep their precision
+                let n = column.len() as f64;
+                let mean = column.iter().map(|&v| v as f64).sum::<f64>() / n;
+                let variance = column
+                    .iter()
+                    .map(|&v| (v as f64 - mean).powi(2))
+                    .sum::<f64>()
+                    / n;
+                let std = variance.sqrt();
+                if std > 0.0 {
+                    column.mapv_inplace(|v| ((v as f64 - mean) / std) as f32);
+                } else {
+                    column.fill(0.0);
+                }
+            }
+        }
+        Normalize::UnitL2 => {
+            for mut row in x.axis_iter_mut(Axis(0)) {
+                let norm = row.iter().map(|&v| v * v).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    row.mapv_inplace(|v| v / norm);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    /// Three samples of a varying feature next to a constant one
+    fn fixture() -> Array2<f32> {
+        array![[1.0, 5.0], [2.0, 5.0], [4.0, 5.0]]
+    }
+
+    #[test]
+    fn none_leaves_values_unchanged() {
+        let mut x = fixture();
+        normalize(&mut x, Normalize::None);
+        assert_eq!(x, fixture());
+    }
+
+    #[test]
+    fn minmax_scales_each_feature_to_unit_range() {
+        let mut x = array![[1.0, -2.0], [2.0, 0.0], [5.0, 2.0]];
+        normalize(&mut x, Normalize::Minmax);
+        assert_eq!(x, array![[0.0, 0.0], [0.25, 0.5], [1.0, 1.0]]);
+    }
+
+    #[test]
+    fn zscore_centers_each_feature_with_unit_variance() {
+        // Mean 5 and population standard deviation 2
+        let mut x = array![[2.0], [4.0], [4.0], [4.0], [5.0], [5.0], [7.0], [9.0]];
+        normalize(&mut x, Normalize::Zscore);
+        assert_eq!(
+            x,
+            array![[-1.5], [-0.5], [-0.5], [-0.5], [0.0], [0.0], [1.0], [2.0]]
+        );
+    }
+
+    #[test]
+    fn unit_l2_scales_each_sample_to_unit_length() {
+        let mut x = array![[3.0, 4.0], [0.0, -2.0], [0.0, 0.0]];
+        normalize(&mut x, Normalize::UnitL2);
+        assert_eq!(x, array![[0.6, 0.8], [0.0, -1.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn constant_features_map_to_zero_without_nan() {
+        for method in [Normalize::Minmax, Normalize::Zscore] {
+            let mut x = fixture();
+            normalize(&mut x, method);
+            assert!(x.iter().all(|v| v.is_finite()), "{method:?} produced NaN");
+            assert_eq!(x.column(1), array![0.0, 0.0, 0.0], "{method:?}");
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Writing the plots, exports and summaries of an embedding
+//!
+//! Full runs and replots of saved embeddings share these, so both honor the
+//! same output options.
+
+use crate::cli::Cli;
+use crate::export::{labels_npy_path, write_embedding_csv, write_embedding_npy};
+use crate::input::load_annotations;
+use crate::interrupt::check_interrupted;
+use crate::metrics::{
+    class_labels, class_summaries, knn_predictions, neighbor_confusion, silhouette, MAX_QUERIES,
+};
+use crate::plot::{
+    class_name, create_comparison_plot, create_confusion_plot, create_density_plot,
+    create_gallery_plot, create_plot, write_animation_html, write_plot_html, write_plot_image,
+    Animation, HoverInfo, Labels, OutputFormat, Panel, PlotStyle, PlotType, PointAnnotation,
+};
+use crate::progress::Spinner;
+use crate::timing::Timings;
+use anyhow::{ensure, Context, Result};
+use ndarray::{Array1, ArrayView2, Axis};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Number of drawn points above which a scatter plot's HTML grows large enough
+/// to open slowly in browsers
+pub(crate) const LARGE_PLOT_POINTS: usize = 200_000;
+
+/// Approximate size of one drawn point in a scatter plot's HTML, in bytes
+pub(crate) const HTML_BYTES_PER_POINT: usize = 45;
+
+/// A finished embedding and everything shown alongside it
+pub(crate) struct Outputs<'a> {
+    /// Array of shape (n_samples, n_dims) containing embedded coordinates
+    pub(crate) embedding: ArrayView2<'a, f32>,
+
+    /// Class labels of shape (n_samples,), if known
+    pub(crate) labels: Option<&'a Array1<i32>>,
+
+    /// Values the points are colored by in the plot, if any
+    pub(crate) coloring: Option<&'a Labels>,
+
+    /// Human-readable class names, indexed by label
+    pub(crate) class_names: &'a [&'a str],
+
+    /// Extra details shown when hovering over a point
+    pub(crate) hover: HoverInfo<'a>,
+
+    /// PaCMAP's and a baseline's panels, plotted side by side instead of the
+    /// embedding alone
+    pub(crate) comparison: Option<(Panel<'a>, Panel<'a>)>,
+
+    /// Layouts captured during the optimization, animated in the HTML plot
+    pub(crate) animation: Option<Animation<'a>>,
+
+    /// Name of the embedded dataset used in the plot title
+    pub(crate) dataset: &'a str,
+
+    /// Description of the run embedded in an HTML plot, if any
+    pub(crate) metadata: Option<&'a Value>,
+
+    /// Whether each point comes from the training data, of shape (n_samples,),
+    /// if the splits are also plotted on their own
+    pub(crate) training: Option<&'a [bool]>,
+}
+
+/// Exports the embedding and writes the visualization unless `--no-plot` is set
+///
+/// # Arguments
+/// * `cli` - Command-line options selecting the outputs
+/// * `outputs` - Embedding to export and plot
+/// * `timings` - Phase timings the exports and plotting are added to
+///
+/// # Returns
+/// The absolute path of the written combined visualization, if one was written
+///
+/// # Errors
+/// Returns an error if plot creation fails or any output cannot be written
+pub(crate) fn write_outputs(
+    cli: &Cli,
+    outputs: &Outputs,
+    timings: &mut Timings,
+) -> Result<Option<PathBuf>> {
+    let Outputs {
+        embedding,
+        labels,
+        coloring,
+        class_names,
+        ref hover,
+        comparison,
+        ref animation,
+        dataset,
+        metadata,
+        training,
+    } = *outputs;
+    if let Some(path) = &cli.embedding_out {
+        timings.time("export", || write_embedding_csv(embedding, labels, path))?;
+        info!("Embedding coordinates saved to {}", path.display());
+    }
+
+    if let Some(path) = &cli.npy_out {
+        timings.time("export", || write_embedding_npy(embedding, labels, path))?;
+        info!("Embedding array saved to {}", path.display());
+        if labels.is_some() {
+            info!("Label array saved to {}", labels_npy_path(path).display());
+        }
+    }
+
+    if cli.no_plot {
+        info!("Done! Skipped the visualization as requested by --no-plot");
+        return Ok(None);
+    }
+
+    check_interrupted("plotting")?;
+    // Create and save interactive visualization
+    let mut title = match &cli.title {
+        Some(title) => title.clone(),
+        None => format!(
+            "PaCMAP Embedding of {dataset} ({} points)",
+            embedding.nrows()
+        ),
+    };
+    if cli.preview {
+        title += " (preview)";
+    }
+    let mut style = cli.plot_style();
+    if let (true, Some(labels)) = (cli.knn_agreement, labels) {
+        let k = cli.knn_accuracy_k;
+        let predictions = timings.time("metrics", || knn_predictions(embedding, labels, k));
+        let disagreeing = predictions
+            .iter()
+            .zip(labels)
+            .filter(|(predicted, label)| predicted != label)
+            .count();
+        info!(
+            "The {k} nearest embedded neighbors vote for another class for {disagreeing} of {} points",
+            labels.len()
+        );
+        style.knn_predictions = Some(predictions);
+    }
+    if let Some(path) = &cli.annotations {
+        style.annotations = resolve_annotations(path, embedding.nrows(), hover.ids)?;
+        info!(
+            "Annotating {} points from {}",
+            style.annotations.len(),
+            path.display()
+        );
+    }
+    if cli.plot_type == PlotType::Scatter {
+        match cli.max_plot_points {
+            Some(max) if embedding.nrows() > max => info!(
+                "Drawing about {max} of {} points, as set by --max-plot-points",
+                embedding.nrows()
+            ),
+            None if cli.format == OutputFormat::Html && embedding.nrows() > LARGE_PLOT_POINTS => {
+                warn!(
+                    "Drawing {} points makes an HTML file of about {} MB that browsers open slowly; \
+                     consider --max-plot-points{}",
+                    embedding.nrows(),
+                    embedding.nrows() * HTML_BYTES_PER_POINT / 1_000_000,
+                    if cli.webgl { "" } else { " and --webgl" }
+                )
+            }
+            _ => {}
+        }
+    }
+    if training.is_some() {
+        // Pinning the combined plot's ranges keeps the split plots comparable with it
+        style.x_range = style
+            .x_range
+            .or_else(|| Some(style.x_scale.padded_range(embedding.column(0))));
+        style.y_range = style
+            .y_range
+            .or_else(|| Some(style.y_scale.padded_range(embedding.column(1))));
+    }
+    let render = |style: &PlotStyle, title: &str, output: &Path| -> Result<PathBuf> {
+        let plot = match (comparison, cli.plot_type) {
+            (Some((left, right)), _) => {
+                create_comparison_plot(left, right, coloring, class_names, hover, style, title)?
+            }
+            (None, PlotType::Density) => create_density_plot(embedding, style, title)?,
+            (None, PlotType::Scatter) => {
+                create_plot(embedding, coloring, class_names, hover, style, title)?
+            }
+        };
+
+        info!("Saving visualization...");
+        match (cli.format, animation) {
+            (OutputFormat::Html, Some(animation)) => {
+                write_animation_html(&plot, output, animation, coloring, style, metadata)
+            }
+            (OutputFormat::Html, None) => {
+                write_plot_html(&plot, output, hover.thumbnails, metadata)
+            }
+            (format, _) => write_plot_image(
+                &plot,
+                output,
+                format,
+                cli.image_width,
+                cli.image_height,
+                cli.image_scale,
+            ),
+        }
+    };
+    let output = cli.output_path();
+    let saved = timings.time("plot", || render(&style, &title, &output))?;
+    info!("Done! Visualization saved to {}", saved.display());
+
+    if let Some(training) = training {
+        for (split, name, in_split) in [("train", "training", true), ("test", "test", false)] {
+            let mut style = style.clone();
+            style.shown_rows = Some(training.iter().map(|&t| t == in_split).collect());
+            style
+                .annotations
+                .retain(|annotation| training[annotation.row] == in_split);
+            let n = training.iter().filter(|&&t| t == in_split).count();
+            let title = format!("{title}: {name} split ({n} points)");
+            let path = split_output_path(&output, split);
+            let saved = timings.time("plot", || render(&style, &title, &path))?;
+            info!("Plot of the {name} split saved to {}", saved.display());
+        }
+    }
+    Ok(Some(saved))
+}
+
+/// Returns the path of the plot of one split for `--split-output`, e.g.
+/// `plot_train.html` next to `plot.html`
+pub(crate) fn split_output_path(output: &Path, split: &str) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{stem}_{split}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{split}"),
+    };
+    output.with_file_name(name)
+}
+
+/// Prints a table of each class's size, centroid and spread and the
+/// embedding's silhouette score, for `--summary-only`
+///
+/// The table goes to stdout whatever the log level, so it can be read or piped
+/// without any log lines in between.
+///
+/// # Arguments
+/// * `embedding` - Embedding of shape (n_samples, n_dimensions)
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `class_names` - Human-readable class names, indexed by label
+pub(crate) fn print_summary(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    class_names: &[&str],
+) {
+    let summaries = Spinner::run("Summarizing classes", || class_summaries(embedding, labels));
+    let names: Vec<String> = summaries
+        .iter()
+        .map(|summary| class_name(summary.label, class_names))
+        .collect();
+    let width = names.iter().map(String::len).chain([5]).max().unwrap_or(5);
+
+    println!(
+        "{:<width$}  {:>8}  {:<24}  {:>13}",
+        "Class", "Points", "Centroid", "Mean distance"
+    );
+    for (summary, name) in summaries.iter().zip(&names) {
+        let centroid: Vec<_> = summary.centroid.iter().map(|v| format!("{v:.3}")).collect();
+        println!(
+            "{name:<width$}  {:>8}  {:<24}  {:>13.3}",
+            summary.count,
+            format!("({})", centroid.join(", ")),
+            summary.mean_distance
+        );
+    }
+    if summaries.len() > 1 {
+        let score = Spinner::run("Scoring silhouette", || silhouette(embedding, labels));
+        let sampled = if labels.len() > MAX_QUERIES {
+            format!(", estimated from {MAX_QUERIES} points")
+        } else {
+            String::new()
+        };
+        println!("Silhouette: {score:.4}{sampled}");
+    } else {
+        println!("Silhouette: undefined for a single class");
+    }
+}
+
+/// Plots which classes the nearest embedded neighbors of each class belong to
+///
+/// The most confused pair of classes is also logged. The heatmap is written
+/// next to the visualization as `neighbor_confusion.<FORMAT>`.
+///
+/// # Arguments
+/// * `cli` - Command-line options holding the neighbor count and output settings
+/// * `embedding` - Array of shape (n_samples, n_dims) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `class_names` - Human-readable class names, indexed by label
+/// * `timings` - Phase timings the scoring and plotting are added to
+///
+/// # Errors
+/// Returns an error if the heatmap cannot be created or written
+pub(crate) fn write_neighbor_confusion(
+    cli: &Cli,
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    class_names: &[&str],
+    timings: &mut Timings,
+) -> Result<()> {
+    let k = cli.knn_accuracy_k;
+    let confusion = timings.time("scoring", || {
+        Spinner::run("Counting neighbor classes", || {
+            neighbor_confusion(embedding, labels, k)
+        })
+    });
+    let classes = class_labels(labels);
+
+    // Report the largest share of a class's neighbors that belong to another class
+    let totals = confusion.sum_axis(Axis(1));
+    let confused = confusion
+        .indexed_iter()
+        .filter(|&((i, j), &count)| i != j && count > 0)
+        .map(|((i, j), &count)| (i, j, count as f64 / totals[i] as f64))
+        .max_by(|a, b| a.2.total_cmp(&b.2));
+    if let Some((i, j, share)) = confused {
+        cli.report(&format!(
+            "Most confused classes (k = {k}): {:.1}% of the neighbors of {} are {}",
+            share * 100.0,
+            class_name(classes[i], class_names),
+            class_name(classes[j], class_names)
+        ));
+    }
+
+    let path = cli
+        .output_path()
+        .with_file_name(format!("neighbor_confusion.{}", cli.format.extension()));
+    let saved = timings.time("plot", || -> Result<_> {
+        let plot = create_confusion_plot(
+            confusion.view(),
+            &classes,
+            class_names,
+            &cli.plot_style(),
+            &format!("Classes of the {k} nearest embedded neighbors"),
+        )?;
+        match cli.format {
+            OutputFormat::Html => write_plot_html(&plot, &path, None, None),
+            format => write_plot_image(
+                &plot,
+                &path,
+                format,
+                cli.image_width,
+                cli.image_height,
+                cli.image_scale,
+            ),
+        }
+    })?;
+    info!("Neighbor confusion heatmap saved to {}", saved.display());
+    Ok(())
+}
+
+/// Returns what the plot colors the points by, and the names of its classes
+///
+/// Points are colored by their origin file if `--color-by-source` is given,
+/// naming each source after its file, and otherwise as [`coloring`] picks.
+pub(crate) fn plot_coloring<'a>(
+    cli: &Cli,
+    labels: Option<&Array1<i32>>,
+    colors: Option<Array1<f32>>,
+    sources: Option<&'a (Array1<i32>, Vec<String>)>,
+    class_names: &'a [&'a str],
+) -> (Option<Labels>, Vec<&'a str>) {
+    match sources {
+        Some((sources, names)) if cli.color_by_source => (
+            Some(Labels::Discrete(sources.clone())),
+            names.iter().map(String::as_str).collect(),
+        ),
+        _ => (coloring(labels, colors), class_names.to_vec()),
+    }
+}
+
+/// Returns what the points are colored by: continuous values if given,
+/// otherwise their class labels
+pub(crate) fn coloring(
+    labels: Option<&Array1<i32>>,
+    colors: Option<Array1<f32>>,
+) -> Option<Labels> {
+    match colors {
+        Some(colors) => Some(Labels::Continuous(colors)),
+        None => labels.cloned().map(Labels::Discrete),
+    }
+}
+
+/// Reads `--annotations` and finds the embedded row of each annotated point
+///
+/// # Arguments
+/// * `path` - CSV file of `index,text` rows
+/// * `n_rows` - Number of embedded points
+/// * `ids` - Index of each embedded point in the original data, if the rows
+///   were subsampled from it; otherwise indices are rows
+///
+/// # Errors
+/// Returns an error if the file cannot be read or an index names no embedded point
+pub(crate) fn resolve_annotations(
+    path: &Path,
+    n_rows: usize,
+    ids: Option<&[usize]>,
+) -> Result<Vec<PointAnnotation>> {
+    let rows = embedded_rows(ids);
+    load_annotations(path)?
+        .into_iter()
+        .map(|(index, text)| {
+            let row = embedded_row(index, rows.as_ref(), n_rows, "Annotated point", path)?;
+            Ok(PointAnnotation { row, text })
+        })
+        .collect()
+}
+
+/// Maps each original point index to its embedded row, if the rows were
+/// subsampled, reordered or filtered
+pub(crate) fn embedded_rows(ids: Option<&[usize]>) -> Option<HashMap<usize, usize>> {
+    ids.map(|ids| ids.iter().enumerate().map(|(row, &id)| (id, row)).collect())
+}
+
+/// Returns the embedded row of the point shown as `index`
+///
+/// # Arguments
+/// * `index` - Index of the point, as shown when hovering over it
+/// * `rows` - Embedded row of each original index, from [`embedded_rows`]
+/// * `n_rows` - Number of embedded points
+/// * `what` - Description of the point in error messages, e.g. `Annotated point`
+/// * `path` - File the index was read from
+///
+/// # Errors
+/// Returns an error if the index names no embedded point
+pub(crate) fn embedded_row(
+    index: usize,
+    rows: Option<&HashMap<usize, usize>>,
+    n_rows: usize,
+    what: &str,
+    path: &Path,
+) -> Result<usize> {
+    match rows {
+        Some(rows) => rows.get(&index).copied().with_context(|| {
+            format!(
+                "{what} {index} in {} is not among the {n_rows} embedded points",
+                path.display()
+            )
+        }),
+        None => {
+            ensure!(
+                index < n_rows,
+                "{what} {index} in {} is out of range; only {n_rows} points are embedded",
+                path.display()
+            );
+            Ok(index)
+        }
+    }
+}
+
+/// Embeddings of an ensemble and everything shown alongside them
+pub(crate) struct Gallery<'a> {
+    /// One panel per finished run, in seed order
+    pub(crate) panels: &'a [Panel<'a>],
+
+    /// Values the points are colored by, if any
+    pub(crate) coloring: Option<&'a Labels>,
+
+    /// Human-readable name of each class, indexed by label
+    pub(crate) class_names: &'a [&'a str],
+
+    /// Extra per-point hover information
+    pub(crate) hover: HoverInfo<'a>,
+
+    /// Title shown above the panels
+    pub(crate) title: &'a str,
+}
+
+/// Writes the panels of an ensemble to the visualization path
+///
+/// # Returns
+/// The path of the written file
+///
+/// # Errors
+/// Returns an error if the plot cannot be created or written
+pub(crate) fn write_gallery(
+    cli: &Cli,
+    gallery: &Gallery,
+    timings: &mut Timings,
+) -> Result<PathBuf> {
+    let base = cli.seed.unwrap_or_default();
+    let seeds: Vec<u64> = (base..).take(gallery.panels.len()).collect();
+    let metadata = json!({ "config": cli.run_config(), "seeds": seeds });
+    timings.time("plot", || {
+        let plot = create_gallery_plot(
+            gallery.panels,
+            gallery.coloring,
+            gallery.class_names,
+            &gallery.hover,
+            &cli.plot_style(),
+            gallery.title,
+        )?;
+        info!("Saving visualization...");
+        match cli.format {
+            OutputFormat::Html => write_plot_html(&plot, &cli.output_path(), None, Some(&metadata)),
+            format => write_plot_image(
+                &plot,
+                &cli.output_path(),
+                format,
+                cli.image_width,
+                cli.image_height,
+                cli.image_scale,
+            ),
+        }
+    })
+}
@@ -0,0 +1,1884 @@
+//! Interactive visualizations of embeddings
+//!
+//! Builds plotly figures from embedding coordinates, drawing 2D embeddings as
+//! a flat scatter plot and 3D embeddings as a rotatable 3D scatter. Figures are
+//! saved as interactive HTML or rendered to static images with Kaleido.
+
+use crate::export::create_parent_dir;
+use crate::kaleido::render_image;
+use anyhow::{bail, ensure, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::ValueEnum;
+use ndarray::{Array1, ArrayView1, ArrayView2, ArrayView3, Axis as ArrayAxis};
+use plotly::common::{
+    Anchor, ColorBar, ColorScale, ColorScalePalette, HoverInfo as TraceHoverInfo, Line, Marker,
+    MarkerSymbol, Mode, Position, Title,
+};
+use plotly::layout::{
+    Annotation, AspectMode, Axis, AxisType, GridPattern, ItemClick, ItemSizing, LayoutGrid,
+    LayoutScene, Legend,
+};
+use plotly::{HeatMap, Layout, Plot, Scatter, Scatter3D};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::f32::consts::TAU;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Hover template showing only the point's hover text, without a trace box
+const HOVER_TEMPLATE: &str = "%{hovertext}<extra></extra>";
+
+/// Qualitative palette giving each class a distinct color
+///
+/// These are the ten category colors of D3 and matplotlib, chosen so that
+/// neighboring classes never share similar hues. Further classes reuse the
+/// palette from the start.
+pub const CLASS_COLORS: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+/// Qualitative palette that stays distinguishable with color vision deficiencies
+///
+/// The eight colors of Okabe and Ito's palette, followed by grey and the wine
+/// of Paul Tol's muted scheme to reach ten classes.
+pub const OKABE_ITO_COLORS: [&str; 10] = [
+    "#e69f00", "#56b4e9", "#009e73", "#f0e442", "#0072b2", "#d55e00", "#cc79a7", "#000000",
+    "#999999", "#882255",
+];
+
+/// File formats the visualization can be saved in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Interactive HTML page
+    Html,
+
+    /// Static raster image, rendered with Kaleido
+    Png,
+
+    /// Static vector image, rendered with Kaleido
+    Svg,
+}
+
+impl OutputFormat {
+    /// Returns the file extension of the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "html",
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+        }
+    }
+}
+
+/// Ways of drawing a 2D embedding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlotType {
+    /// One marker per point, colored by class
+    #[default]
+    Scatter,
+
+    /// Heatmap of the number of points in each cell of a grid, revealing
+    /// structure hidden by overlapping markers
+    Density,
+}
+
+/// Scales of the axes of a 2D scatter plot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AxisScale {
+    /// Evenly spaced values
+    #[default]
+    Linear,
+
+    /// Evenly spaced powers of ten, for positive coordinates only
+    Log,
+
+    /// `sign(v) * log10(1 + |v|)`: logarithmic far from zero and close to linear
+    /// within one unit of it, so zero and negative coordinates can be shown too
+    Symlog,
+}
+
+impl AxisScale {
+    /// Returns the position of a coordinate on the axis, in the units plotly
+    /// places ranges and annotations in
+    pub fn to_axis(self, value: f64) -> f64 {
+        match self {
+            AxisScale::Linear => value,
+            AxisScale::Log => value.log10(),
+            AxisScale::Symlog => value.signum() * value.abs().ln_1p() / std::f64::consts::LN_10,
+        }
+    }
+
+    /// Returns the coordinate at a position on the axis, undoing [`AxisScale::to_axis`]
+    pub fn from_axis(self, position: f64) -> f64 {
+        match self {
+            AxisScale::Linear => position,
+            AxisScale::Log => 10_f64.powf(position),
+            AxisScale::Symlog => {
+                position.signum() * (position.abs() * std::f64::consts::LN_10).exp_m1()
+            }
+        }
+    }
+
+    /// Returns the (min, max) range of the values widened by 5% of the axis on
+    /// each side, like [`padded_range`] but measured along this scale
+    pub fn padded_range(self, values: ArrayView1<f32>) -> (f64, f64) {
+        let (min, max) = value_range(values);
+        let (min, max) = pad_range(self.to_axis(f64::from(min)), self.to_axis(f64::from(max)));
+        (self.from_axis(min), self.from_axis(max))
+    }
+
+    /// Returns the coordinate a trace is drawn at: transformed for symlog, which
+    /// plotly has no axis type for, and left to plotly otherwise
+    fn plotted(self, value: f32) -> f32 {
+        match self {
+            AxisScale::Symlog => self.to_axis(f64::from(value)) as f32,
+            _ => value,
+        }
+    }
+}
+
+/// Tick values and texts of a symlog axis, labeling positions with the
+/// coordinates they stand for
+///
+/// Ticks are placed at 0 and at ±1, 2 and 5 times the powers of ten from 1 on
+/// within the range, or only at the powers of ten if that gives too many
+/// ticks. Ranges too narrow for three such ticks also get ticks at the powers
+/// of ten below 1.
+///
+/// # Arguments
+/// * `range` - Range of the axis in axis units
+fn symlog_ticks((min, max): (f64, f64)) -> (Vec<f64>, Vec<String>) {
+    let candidates = |lowest_power: i32, steps: &[f64]| -> Vec<f64> {
+        let magnitudes: Vec<f64> = (lowest_power..=15)
+            .map(|k| 10_f64.powi(k))
+            .flat_map(|power| steps.iter().map(move |step| step * power))
+            .collect();
+        let mut values: Vec<f64> = magnitudes
+            .iter()
+            .map(|v| -v)
+            .chain([0.0])
+            .chain(magnitudes.iter().copied())
+            .filter(|&v| (min..=max).contains(&AxisScale::Symlog.to_axis(v)))
+            .collect();
+        values.sort_by(f64::total_cmp);
+        values
+    };
+    let mut values = candidates(0, &[1.0, 2.0, 5.0]);
+    if values.len() > MAX_SYMLOG_TICKS {
+        values = candidates(0, &[1.0]);
+    } else if values.len() < 3 {
+        values = candidates(-3, &[1.0]);
+    }
+    let texts = values.iter().map(|v| format!("{v}")).collect();
+    let positions = values
+        .iter()
+        .map(|&v| AxisScale::Symlog.to_axis(v))
+        .collect();
+    (positions, texts)
+}
+
+/// Largest number of ticks of a symlog axis before skipping the 2s and 5s
+const MAX_SYMLOG_TICKS: usize = 12;
+
+/// Number of grid cells along each axis of a density plot
+pub const DENSITY_BINS: usize = 200;
+
+/// Color palettes of the plot
+///
+/// Apart from Okabe-Ito these are plotly's built-in color scales for
+/// continuous values and point densities, and classes keep their
+/// [`CLASS_COLORS`]. Okabe-Ito instead colors classes with
+/// [`OKABE_ITO_COLORS`] and pairs them with the Cividis scale, so every
+/// part of the plot is colorblind-safe. Viridis and Cividis are perceptually
+/// uniform and remain readable with color vision deficiencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[value(rename_all = "lower")]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// Black through red and yellow to white
+    Blackbody,
+
+    /// Blue to red
+    Bluered,
+
+    /// Light to dark blue
+    Blues,
+
+    /// Blue to yellow, designed for color vision deficiencies
+    Cividis,
+
+    /// Blue through green to brown
+    Earth,
+
+    /// Black through purple to yellow
+    Electric,
+
+    /// Light to dark green
+    Greens,
+
+    /// Black to white
+    Greys,
+
+    /// Black through red to yellow
+    Hot,
+
+    /// Blue through cyan and yellow to red
+    Jet,
+
+    /// Blue through white to red, brightly
+    Picnic,
+
+    /// Blue through green and yellow to red
+    #[default]
+    Portland,
+
+    /// Blue through green and yellow to red, saturated
+    Rainbow,
+
+    /// Red through white to blue
+    RdBu,
+
+    /// Light to dark red
+    Reds,
+
+    /// Purple through teal to yellow, perceptually uniform
+    Viridis,
+
+    /// Yellow through green to blue
+    YlGnBu,
+
+    /// Yellow through orange to red
+    YlOrRd,
+
+    /// Colorblind-safe class colors, with the Cividis scale for continuous values
+    #[value(name = "okabe-ito")]
+    #[serde(rename = "okabe-ito")]
+    OkabeIto,
+}
+
+impl Palette {
+    /// Returns the plotly color scale of the palette
+    fn color_scale(self) -> ColorScale {
+        ColorScale::Palette(match self {
+            Palette::Blackbody => ColorScalePalette::Blackbody,
+            Palette::Bluered => ColorScalePalette::Bluered,
+            Palette::Blues => ColorScalePalette::Blues,
+            Palette::Cividis => ColorScalePalette::Cividis,
+            Palette::Earth => ColorScalePalette::Earth,
+            Palette::Electric => ColorScalePalette::Electric,
+            Palette::Greens => ColorScalePalette::Greens,
+            Palette::Greys => ColorScalePalette::Greys,
+            Palette::Hot => ColorScalePalette::Hot,
+            Palette::Jet => ColorScalePalette::Jet,
+            Palette::Picnic => ColorScalePalette::Picnic,
+            Palette::Portland => ColorScalePalette::Portland,
+            Palette::Rainbow => ColorScalePalette::Rainbow,
+            Palette::RdBu => ColorScalePalette::RdBu,
+            Palette::Reds => ColorScalePalette::Reds,
+            Palette::Viridis => ColorScalePalette::Viridis,
+            Palette::YlGnBu => ColorScalePalette::YlGnBu,
+            Palette::YlOrRd => ColorScalePalette::YlOrRd,
+            Palette::OkabeIto => ColorScalePalette::Cividis,
+        })
+    }
+
+    /// Returns the colors cycled through for successive classes
+    fn class_colors(self) -> &'static [&'static str] {
+        match self {
+            Palette::OkabeIto => &OKABE_ITO_COLORS,
+            _ => &CLASS_COLORS,
+        }
+    }
+}
+
+/// Appearance settings shared by every kind of plot
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlotStyle {
+    /// Color scale of continuous values and density heatmaps
+    pub palette: Palette,
+
+    /// Title of the horizontal axis, if any
+    pub x_label: Option<String>,
+
+    /// Title of the vertical axis, if any
+    pub y_label: Option<String>,
+
+    /// Mark each class's centroid in 2D scatter plots with a larger labeled marker
+    pub centroids: bool,
+
+    /// Also outline one standard deviation around each centroid with an ellipse
+    pub centroid_spread: bool,
+
+    /// Labels of the classes to draw, if not every class; the others are left
+    /// out of the plot but keep their place in the color cycle
+    pub classes: Option<Vec<i32>>,
+
+    /// Whether to draw each point, of shape (n_samples,), if not every point;
+    /// classes keep the colors they have when every point is drawn
+    pub shown_rows: Option<Vec<bool>>,
+
+    /// Fixed (min, max) range of the horizontal axis, instead of fitting the data
+    pub x_range: Option<(f64, f64)>,
+
+    /// Fixed (min, max) range of the vertical axis, instead of fitting the data
+    pub y_range: Option<(f64, f64)>,
+
+    /// Scale of the horizontal axis of 2D scatter plots; ranges stay in coordinates
+    pub x_scale: AxisScale,
+
+    /// Scale of the vertical axis of 2D scatter plots; ranges stay in coordinates
+    pub y_scale: AxisScale,
+
+    /// Draw one unit of both axes of 2D plots at the same length, so clusters
+    /// are not stretched by the plot's shape
+    pub equal_aspect: bool,
+
+    /// Diameter of the point markers in pixels, or [`DEFAULT_MARKER_SIZE`] if unset
+    pub marker_size: Option<usize>,
+
+    /// Opacity of the point markers from 0 to 1, or lowered automatically for
+    /// plots of more than [`OPAQUE_POINTS`] points if unset
+    pub marker_opacity: Option<f64>,
+
+    /// Largest number of points drawn per class, thinned evenly if exceeded;
+    /// the embedding itself is unaffected
+    pub max_points_per_class: Option<usize>,
+
+    /// Largest number of points drawn in total, split between the classes in
+    /// proportion to their size; the embedding itself is unaffected
+    pub max_points: Option<usize>,
+
+    /// Class of each point predicted by a k-NN vote in the embedding, of shape
+    /// (n_samples,); points whose vote disagrees with their label are drawn as
+    /// larger crosses on top of the others
+    pub knn_predictions: Option<Array1<i32>>,
+
+    /// Draw classes from the largest to the smallest, so small classes are
+    /// drawn on top instead of being buried under large ones
+    pub small_classes_on_top: bool,
+
+    /// Outline the markers of classes smaller than [`SMALL_CLASS_FRACTION`] of
+    /// the largest class
+    pub outline_small_classes: bool,
+
+    /// Notes drawn next to individual points of 2D plots, with an arrow
+    /// pointing at each
+    pub annotations: Vec<PointAnnotation>,
+
+    /// Draw the points of 2D scatter plots with WebGL instead of SVG, which
+    /// keeps browsers responsive with hundreds of thousands of points
+    pub webgl: bool,
+}
+
+/// A note attached to one point of a 2D plot
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointAnnotation {
+    /// Row of the point in the embedding
+    pub row: usize,
+
+    /// Text shown next to the point
+    pub text: String,
+}
+
+/// Share of the largest class's size below which a class counts as small
+pub const SMALL_CLASS_FRACTION: f64 = 0.25;
+
+/// Diameter in pixels of the point markers unless configured otherwise
+pub const DEFAULT_MARKER_SIZE: usize = 2;
+
+/// Width and height in pixels of each panel of a gallery plot
+const GALLERY_PANEL_SIZE: usize = 400;
+
+/// Number of plotted points up to which markers are drawn fully opaque by default
+pub const OPAQUE_POINTS: usize = 10_000;
+
+/// Lowest opacity chosen automatically for very large plots
+const MIN_AUTO_OPACITY: f64 = 0.2;
+
+impl PlotStyle {
+    /// Returns the horizontal axis, titled with the x label if set
+    fn x_axis(&self) -> Axis {
+        self.titled_x_axis(self.x_label.as_deref())
+    }
+
+    /// Returns the horizontal axis with the given title instead of the x label
+    fn titled_x_axis(&self, title: Option<&str>) -> Axis {
+        with_range(titled_axis(title), self.x_range)
+    }
+
+    /// Returns the vertical axis, titled with the y label if set
+    fn y_axis(&self) -> Axis {
+        with_range(titled_axis(self.y_label.as_deref()), self.y_range)
+    }
+
+    /// Returns the opacity of the markers of a plot of `n_points` points
+    ///
+    /// Without a configured opacity, plots of more than [`OPAQUE_POINTS`]
+    /// points fade with the square root of the point count, so dense regions
+    /// still show their structure.
+    fn marker_opacity(&self, n_points: usize) -> f64 {
+        self.marker_opacity.unwrap_or_else(|| {
+            (OPAQUE_POINTS as f64 / n_points.max(1) as f64)
+                .sqrt()
+                .clamp(MIN_AUTO_OPACITY, 1.0)
+        })
+    }
+
+    /// Returns the layout annotations pointing at the annotated points of a 2D embedding
+    ///
+    /// # Errors
+    /// Returns an error if an annotated row is outside the embedding
+    fn point_annotations(&self, embedding: ArrayView2<f32>) -> Result<Vec<Annotation>> {
+        self.annotations
+            .iter()
+            .map(|annotation| {
+                ensure!(
+                    annotation.row < embedding.nrows(),
+                    "Annotated row {} is outside the embedding of {} points",
+                    annotation.row,
+                    embedding.nrows()
+                );
+                let point = embedding.row(annotation.row);
+                Ok(Annotation::new()
+                    .x(self.x_scale.to_axis(f64::from(point[0])))
+                    .y(self.y_scale.to_axis(f64::from(point[1])))
+                    .text(&annotation.text)
+                    .show_arrow(true)
+                    .arrow_head(2))
+            })
+            .collect()
+    }
+
+    /// Returns the vertical axis of a 2D plot, scaled like the horizontal axis
+    /// `x_axis` (e.g. `"x"`) if the aspect ratio is fixed
+    fn y_axis_2d(&self, x_axis: &str) -> Axis {
+        match self.equal_aspect {
+            true => self.y_axis().scale_anchor(x_axis),
+            false => self.y_axis(),
+        }
+    }
+}
+
+/// Sets the type, or for symlog the ticks, of an axis of the given scale
+///
+/// # Arguments
+/// * `axis` - Axis to scale
+/// * `scale` - Scale of the axis
+/// * `range` - Range of the axis in axis units, which symlog ticks are placed in
+fn with_scale(axis: Axis, scale: AxisScale, range: Option<(f64, f64)>) -> Axis {
+    match (scale, range) {
+        (AxisScale::Linear, _) => axis,
+        (AxisScale::Log, _) => axis.type_(AxisType::Log),
+        (AxisScale::Symlog, Some(range)) => {
+            let (values, texts) = symlog_ticks(range);
+            axis.tick_values(values).tick_text(texts)
+        }
+        (AxisScale::Symlog, None) => axis,
+    }
+}
+
+/// Checks that every coordinate can be drawn on an axis of the given scale
+///
+/// # Errors
+/// Returns an error if the scale is logarithmic and a coordinate is not positive
+fn check_scale(values: ArrayView1<f32>, scale: AxisScale, axis: &str) -> Result<()> {
+    if scale == AxisScale::Log {
+        let (min, _) = value_range(values);
+        ensure!(
+            min > 0.0,
+            "A log scale on the {axis} axis needs positive coordinates, but the smallest is \
+             {min}; a symlog scale shows zero and negative coordinates too"
+        );
+    }
+    Ok(())
+}
+
+/// Fixes the range of an axis, if a range is given
+fn with_range(axis: Axis, range: Option<(f64, f64)>) -> Axis {
+    match range {
+        Some((min, max)) => axis.range(vec![min, max]),
+        None => axis,
+    }
+}
+
+/// Returns an axis with the given title, or an untitled one
+fn titled_axis(title: Option<&str>) -> Axis {
+    match title {
+        Some(title) => Axis::new().title(Title::with_text(title)),
+        None => Axis::new(),
+    }
+}
+
+/// Values the points are colored by
+#[derive(Clone, Debug, PartialEq)]
+pub enum Labels {
+    /// Integer class labels, drawn as one colored trace per class with a legend
+    Discrete(Array1<i32>),
+
+    /// Continuous values such as a regression target, drawn on a color scale
+    /// with a colorbar
+    Continuous(Array1<f32>),
+}
+
+/// Extra per-point information shown when hovering over the plot
+///
+/// Every point's hover text shows its index and class. The fields here refine
+/// what is shown.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HoverInfo<'a> {
+    /// Index of each point in the original dataset, if the embedded rows were
+    /// subsampled from it; otherwise points are numbered by row
+    pub ids: Option<&'a [usize]>,
+
+    /// Source image of each point as rows of 28x28 pixel intensities in
+    /// [0,1], shown as a thumbnail next to the cursor in HTML output
+    pub thumbnails: Option<ArrayView2<'a, f32>>,
+}
+
+/// Side length in pixels of the square thumbnail images
+const THUMBNAIL_SIZE: usize = 28;
+
+/// Creates a complete plot of the embedding with a titled layout
+///
+/// Two-dimensional embeddings are drawn with [`create_scatter_plot`] and
+/// three-dimensional ones with [`create_scatter_3d_plot`]. Unless the style
+/// pins them, the axes of a 2D plot span the [`embedding_bounds`] widened by
+/// 5% on each side.
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, 2) or (n_samples, 3) containing embedded coordinates
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings
+/// * `title` - Title shown above the plot
+///
+/// # Errors
+/// Returns an error if the embedding has neither 2 nor 3 dimensions, or
+/// points of a 3D embedding are annotated
+pub fn create_plot(
+    embedding: ArrayView2<f32>,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+    title: &str,
+) -> Result<Plot> {
+    let layout = Layout::new()
+        .title(Title::with_text(title))
+        .legend(class_legend())
+        .width(800)
+        .height(800);
+
+    let scaled = (embedding.ncols() == 2
+        && (style.x_scale == AxisScale::Symlog || style.y_scale == AxisScale::Symlog))
+        .then(|| {
+            let mut scaled = embedding.to_owned();
+            scaled
+                .column_mut(0)
+                .mapv_inplace(|v| style.x_scale.plotted(v));
+            scaled
+                .column_mut(1)
+                .mapv_inplace(|v| style.y_scale.plotted(v));
+            scaled
+        });
+    let plotted = match &scaled {
+        Some(scaled) => scaled.view(),
+        None => embedding.view(),
+    };
+
+    let mut plot = Plot::new();
+    add_embedding_traces(&mut plot, plotted, labels, class_names, hover, style)?;
+    if embedding.ncols() == 3 {
+        ensure!(
+            style.annotations.is_empty(),
+            "Point annotations can only be drawn on 2D embeddings"
+        );
+        ensure!(
+            style.x_scale == AxisScale::Linear && style.y_scale == AxisScale::Linear,
+            "Log and symlog axis scales can only be drawn on 2D embeddings"
+        );
+        plot.set_layout(
+            layout.scene(
+                LayoutScene::new()
+                    .aspect_mode(AspectMode::Data)
+                    .x_axis(style.x_axis())
+                    .y_axis(style.y_axis()),
+            ),
+        );
+    } else {
+        check_scale(embedding.column(0), style.x_scale, "horizontal")?;
+        check_scale(embedding.column(1), style.y_scale, "vertical")?;
+        // Fitting every point rather than the classes shown, as plotly would
+        let mut style = style.clone();
+        if embedding.nrows() > 0 {
+            style.x_range = style
+                .x_range
+                .or_else(|| Some(style.x_scale.padded_range(embedding.column(0))));
+            style.y_range = style
+                .y_range
+                .or_else(|| Some(style.y_scale.padded_range(embedding.column(1))));
+        }
+        // Ranges are given in coordinates, and plotly takes them in axis units
+        let to_axis =
+            |scale: AxisScale, (min, max): (f64, f64)| (scale.to_axis(min), scale.to_axis(max));
+        style.x_range = style.x_range.map(|range| to_axis(style.x_scale, range));
+        style.y_range = style.y_range.map(|range| to_axis(style.y_scale, range));
+        plot.set_layout(
+            layout
+                .x_axis(with_scale(style.x_axis(), style.x_scale, style.x_range))
+                .y_axis(with_scale(
+                    style.y_axis_2d("x"),
+                    style.y_scale,
+                    style.y_range,
+                ))
+                .annotations(style.point_annotations(embedding)?),
+        );
+    }
+
+    Ok(plot)
+}
+
+/// Adds the traces of an embedding to an existing plot, leaving its layout alone
+///
+/// Two-dimensional embeddings are drawn with [`create_scatter_plot`] and
+/// three-dimensional ones with [`create_scatter_3d_plot`]. The caller keeps
+/// control of the title, axes and legend, so several embeddings or other
+/// traces can share one plot.
+///
+/// # Arguments
+/// * `plot` - Plot the traces are appended to
+/// * `embedding` - Array of shape (n_samples, 2) or (n_samples, 3) containing embedded coordinates
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings of the traces
+///
+/// # Errors
+/// Returns an error if the embedding has neither 2 nor 3 dimensions
+pub fn add_embedding_traces(
+    plot: &mut Plot,
+    embedding: ArrayView2<f32>,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+) -> Result<()> {
+    match embedding.ncols() {
+        2 => {
+            for trace in create_scatter_plot(embedding, labels, class_names, hover, style)? {
+                plot.add_trace(trace);
+            }
+        }
+        3 => {
+            for trace in create_scatter_3d_plot(embedding, labels, class_names, hover, style)? {
+                plot.add_trace(trace);
+            }
+        }
+        n => bail!(
+            "Cannot visualize a {n}-dimensional embedding; only 2 or 3 dimensions can be plotted"
+        ),
+    }
+    Ok(())
+}
+
+/// Returns the legend listing one entry per class
+///
+/// Clicking an entry hides or shows its class, and double-clicking isolates
+/// it by hiding every other class, so a few classes can be picked out of a
+/// crowded embedding. Entries are drawn at a readable size rather than the
+/// tiny point size.
+fn class_legend() -> Legend {
+    Legend::new()
+        .item_sizing(ItemSizing::Constant)
+        .item_click(ItemClick::Toggle)
+        .item_double_click(ItemClick::ToggleOthers)
+}
+
+/// A titled 2D embedding shown as one panel of a comparison plot
+#[derive(Clone, Copy, Debug)]
+pub struct Panel<'a> {
+    /// Caption shown below the panel, e.g. the algorithm and its scores
+    pub caption: &'a str,
+
+    /// Array of shape (n_samples, 2) containing embedded coordinates
+    pub embedding: ArrayView2<'a, f32>,
+}
+
+/// Creates a plot showing two embeddings of the same points side by side
+///
+/// Both panels share the class colors and a single legend, so toggling a
+/// class hides it in both. Each panel has its own axes because the
+/// embeddings' coordinates are not on a common scale. The panels' captions
+/// are shown below any x label.
+///
+/// # Arguments
+/// * `left` - Panel drawn on the left, usually PaCMAP's embedding
+/// * `right` - Panel drawn on the right
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings
+/// * `title` - Title shown above the plot
+///
+/// # Errors
+/// Returns an error if either embedding does not have exactly 2 dimensions
+pub fn create_comparison_plot(
+    left: Panel,
+    right: Panel,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+    title: &str,
+) -> Result<Plot> {
+    let layout = Layout::new()
+        .title(Title::with_text(title))
+        .legend(class_legend())
+        .grid(
+            LayoutGrid::new()
+                .rows(1)
+                .columns(2)
+                .pattern(GridPattern::Independent),
+        )
+        .x_axis(style.titled_x_axis(Some(&panel_caption(left, style))))
+        .x_axis2(style.titled_x_axis(Some(&panel_caption(right, style))))
+        .y_axis(style.y_axis_2d("x"))
+        .y_axis2(style.y_axis_2d("x2"))
+        .width(1600)
+        .height(800);
+
+    let mut plot = Plot::new();
+    let panels = [
+        create_scatter_plot(left.embedding, labels, class_names, hover, style)?,
+        create_scatter_plot(right.embedding, labels, class_names, hover, style)?,
+    ];
+    for (i, traces) in panels.into_iter().enumerate() {
+        for trace in traces {
+            let trace = trace
+                .x_axis(format!("x{}", i + 1))
+                .y_axis(format!("y{}", i + 1));
+            // List each class in the legend once, for the left panel
+            plot.add_trace(if i == 0 {
+                trace
+            } else {
+                trace.show_legend(false)
+            });
+        }
+    }
+    plot.set_layout(layout);
+
+    Ok(plot)
+}
+
+/// Creates a plot showing several embeddings of the same points as small multiples
+///
+/// The panels fill a grid row by row, with as many columns as rows or one
+/// more, so a run-to-run change in the layout stands out when scanning across
+/// them. Like [`create_comparison_plot`], the panels share the class colors
+/// and a single legend, but each has its own axes, as embeddings from
+/// different seeds can be rotated or reflected against each other. Each
+/// panel's caption is shown above it.
+///
+/// # Arguments
+/// * `panels` - Panels to draw, in reading order
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings
+/// * `title` - Title shown above the plot
+///
+/// # Errors
+/// Returns an error if there are no panels or any embedding does not have
+/// exactly 2 dimensions
+pub fn create_gallery_plot(
+    panels: &[Panel],
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+    title: &str,
+) -> Result<Plot> {
+    ensure!(!panels.is_empty(), "A gallery needs at least one embedding");
+    let columns = (panels.len() as f64).sqrt().ceil() as usize;
+    let rows = panels.len().div_ceil(columns);
+
+    // Plotly creates the axes the traces refer to and the grid places them, so
+    // axes are not limited to those `Layout` has fields for. The first pair is
+    // unnumbered, and the others are numbered from 2.
+    let suffix = |i: usize| {
+        if i == 0 {
+            String::new()
+        } else {
+            (i + 1).to_string()
+        }
+    };
+    let captions = panels
+        .iter()
+        .enumerate()
+        .map(|(i, panel)| {
+            Annotation::new()
+                .x_ref(format!("x{} domain", suffix(i)))
+                .y_ref(format!("y{} domain", suffix(i)))
+                .x(0.5)
+                .y(1.0)
+                .y_anchor(Anchor::Bottom)
+                .text(panel.caption)
+                .show_arrow(false)
+        })
+        .collect();
+    let layout = Layout::new()
+        .title(Title::with_text(title))
+        .legend(class_legend())
+        .grid(
+            LayoutGrid::new()
+                .rows(rows)
+                .columns(columns)
+                .pattern(GridPattern::Independent)
+                .y_gap(0.2),
+        )
+        .annotations(captions)
+        .width(GALLERY_PANEL_SIZE * columns)
+        .height(GALLERY_PANEL_SIZE * rows + 100);
+
+    let mut plot = Plot::new();
+    for (i, panel) in panels.iter().enumerate() {
+        for trace in create_scatter_plot(panel.embedding, labels, class_names, hover, style)? {
+            let trace = trace
+                .x_axis(format!("x{}", suffix(i)))
+                .y_axis(format!("y{}", suffix(i)));
+            // List each class in the legend once, for the first panel
+            plot.add_trace(if i == 0 {
+                trace
+            } else {
+                trace.show_legend(false)
+            });
+        }
+    }
+    plot.set_layout(layout);
+
+    Ok(plot)
+}
+
+/// Returns the x axis title of a comparison panel: its caption, below the x
+/// label if set
+fn panel_caption(panel: Panel, style: &PlotStyle) -> String {
+    match &style.x_label {
+        Some(label) => format!("{label}<br>{}", panel.caption),
+        None => panel.caption.to_string(),
+    }
+}
+
+/// Writes the plot as a standalone HTML file
+///
+/// Creates any missing parent directories of `path` before writing, so runs
+/// can be directed into per-experiment output folders. When thumbnails are
+/// given, a script is added to the page that shows the hovered point's image.
+///
+/// # Arguments
+/// * `plot` - Plot to render, created with the same thumbnails
+/// * `path` - Destination of the HTML file
+/// * `thumbnails` - Source image of each point, as in [`HoverInfo::thumbnails`]
+/// * `metadata` - Description of the run to embed in the page, if any, as
+///   described for [`METADATA_ELEMENT_ID`]
+///
+/// # Returns
+/// The absolute path of the written file
+///
+/// # Errors
+/// Returns an error if the parent directory cannot be created or the file
+/// cannot be written
+pub fn write_plot_html(
+    plot: &Plot,
+    path: &Path,
+    thumbnails: Option<ArrayView2<f32>>,
+    metadata: Option<&Value>,
+) -> Result<PathBuf> {
+    let mut scripts = match thumbnails {
+        Some(thumbnails) => vec![thumbnail_script(thumbnails)?],
+        None => Vec::new(),
+    };
+    scripts.extend(metadata.map(metadata_script));
+    write_html(plot, path, &scripts)
+}
+
+/// Largest number of frames an animation may have, keeping the HTML file a
+/// manageable size
+pub const MAX_ANIMATION_FRAMES: usize = 100;
+
+/// Intermediate layouts captured while PaCMAP optimized an embedding
+#[derive(Clone, Copy, Debug)]
+pub struct Animation<'a> {
+    /// Layouts of shape (n_frames, n_samples, 2), in the order they were captured
+    pub snapshots: ArrayView3<'a, f32>,
+
+    /// Optimization iteration at which each layout was captured
+    pub iterations: &'a [usize],
+}
+
+/// Writes a 2D scatter plot as a standalone HTML file that animates the embedding
+///
+/// Each snapshot becomes a frame of the figure, played with a button and
+/// scrubbed with a slider below the plot. Frames move the class traces of
+/// [`create_scatter_plot`] and rescale the axes to fit each layout, as the
+/// embedding grows considerably from its initialization.
+///
+/// # Arguments
+/// * `plot` - Plot of the final embedding, created with the same labels and style
+/// * `path` - Destination of the HTML file
+/// * `animation` - Snapshots to animate
+/// * `labels` - Class labels or continuous values the plot is colored by, if known
+/// * `style` - Appearance settings the plot was created with
+/// * `metadata` - Description of the run to embed in the page, if any, as
+///   described for [`METADATA_ELEMENT_ID`]
+///
+/// # Returns
+/// The absolute path of the written file
+///
+/// # Errors
+/// Returns an error if the snapshots are not 2-dimensional, there are too
+/// many or too few of them, or the file cannot be written
+pub fn write_animation_html(
+    plot: &Plot,
+    path: &Path,
+    animation: &Animation,
+    labels: Option<&Labels>,
+    style: &PlotStyle,
+    metadata: Option<&Value>,
+) -> Result<PathBuf> {
+    let (n_frames, n_rows, dims) = animation.snapshots.dim();
+    if dims != 2 {
+        bail!("Animations need 2-dimensional snapshots, got {dims}");
+    }
+    if n_frames != animation.iterations.len() || !(2..=MAX_ANIMATION_FRAMES).contains(&n_frames) {
+        bail!(
+            "Animations need 2 to {MAX_ANIMATION_FRAMES} snapshots with one iteration each, got \
+             {n_frames} snapshots and {} iterations",
+            animation.iterations.len()
+        );
+    }
+
+    let groups = class_groups(n_rows, labels, &[], style);
+    let frames: Vec<_> = animation
+        .snapshots
+        .axis_iter(ArrayAxis(0))
+        .zip(animation.iterations)
+        .map(|(layout, iteration)| {
+            let data: Vec<_> = groups
+                .iter()
+                .map(|group| json!({ "x": group.column(layout, 0), "y": group.column(layout, 1) }))
+                .collect();
+            json!({
+                "name": iteration.to_string(),
+                "data": data,
+                "traces": (0..groups.len()).collect::<Vec<_>>(),
+                "layout": {
+                    "xaxis": { "range": frame_range(layout.column(0), style.x_range) },
+                    "yaxis": { "range": frame_range(layout.column(1), style.y_range) },
+                },
+            })
+        })
+        .collect();
+    let mut scripts = vec![animation_script(frames, animation.iterations)];
+    scripts.extend(metadata.map(metadata_script));
+    write_html(plot, path, &scripts)
+}
+
+/// Returns the axis range of an animation frame: the pinned range if given,
+/// otherwise the range of the values as from [`padded_range`]
+fn frame_range(values: ArrayView1<f32>, pinned: Option<(f64, f64)>) -> [f64; 2] {
+    let (min, max) = pinned.unwrap_or_else(|| padded_range(values));
+    [min, max]
+}
+
+/// Returns the (min, max) range of the values widened by 5% on each side, so
+/// points at the edges are not cut off
+pub fn padded_range(values: ArrayView1<f32>) -> (f64, f64) {
+    let (min, max) = value_range(values);
+    pad_range(f64::from(min), f64::from(max))
+}
+
+/// Returns the extents of the first two dimensions of an embedding
+///
+/// NaN coordinates are ignored. An embedding without points has infinite
+/// bounds, with each minimum above its maximum.
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, n_dims) with at least 2 dimensions
+///
+/// # Returns
+/// The bounds as (x_min, x_max, y_min, y_max)
+///
+/// # Panics
+/// Panics if the embedding has fewer than 2 dimensions
+pub fn embedding_bounds(embedding: ArrayView2<f32>) -> (f32, f32, f32, f32) {
+    let (x_min, x_max) = value_range(embedding.column(0));
+    let (y_min, y_max) = value_range(embedding.column(1));
+    (x_min, x_max, y_min, y_max)
+}
+
+/// Returns the (min, max) of the values, ignoring NaN
+fn value_range(values: ArrayView1<f32>) -> (f32, f32) {
+    values
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        })
+}
+
+/// Widens a (min, max) range by 5% on each side
+fn pad_range(min: f64, max: f64) -> (f64, f64) {
+    let pad = ((max - min) * 0.05).max(f64::from(f32::EPSILON));
+    (min - pad, max + pad)
+}
+
+/// Returns a script adding the frames, a play button and an iteration slider
+/// to the plot once it exists
+fn animation_script(frames: Vec<Value>, iterations: &[usize]) -> String {
+    // Frames are short so the whole optimization plays in a few seconds
+    let play = json!({ "frame": { "duration": 150, "redraw": false }, "transition": { "duration": 0 }, "fromcurrent": true });
+    let pause = json!({ "frame": { "duration": 0, "redraw": false }, "mode": "immediate" });
+    let controls = json!({
+        "updatemenus": [{
+            "type": "buttons",
+            "direction": "left",
+            "x": 0.0,
+            "y": -0.05,
+            "xanchor": "left",
+            "yanchor": "top",
+            "buttons": [
+                { "label": "Play", "method": "animate", "args": [null, play] },
+                { "label": "Pause", "method": "animate", "args": [[null], pause] },
+            ],
+        }],
+        "sliders": [{
+            "x": 0.15,
+            "y": -0.05,
+            "len": 0.85,
+            "currentvalue": { "prefix": "Iteration " },
+            "steps": iterations.iter().map(|iteration| json!({
+                "label": iteration.to_string(),
+                "method": "animate",
+                "args": [[iteration.to_string()], pause],
+            })).collect::<Vec<_>>(),
+        }],
+    });
+    format!(
+        r#"
+    <script>
+        const animationFrames = {frames};
+        function attachAnimation() {{
+            const graph = document.getElementById("plotly-html-element");
+            if (!graph || !graph.on) {{
+                // The plot is created asynchronously; retry until it exists
+                setTimeout(attachAnimation, 100);
+                return;
+            }}
+            Plotly.addFrames(graph, animationFrames);
+            Plotly.relayout(graph, {controls});
+        }}
+        attachAnimation();
+    </script>
+"#,
+        frames = Value::Array(frames),
+    )
+}
+
+/// Id of the element holding the run metadata embedded in an HTML plot
+///
+/// The element is a `<script type="application/json">` block, which browsers
+/// neither run nor display, so the JSON can be read back from the file by
+/// anyone who receives it, for example with
+/// `document.getElementById("pacmap-run").textContent` in the page.
+pub const METADATA_ELEMENT_ID: &str = "pacmap-run";
+
+/// Returns a script element embedding run metadata as JSON
+fn metadata_script(metadata: &Value) -> String {
+    // `<` only occurs inside JSON strings, where its escape reads back as the same
+    // character, so no value can close the element early
+    let json = metadata.to_string().replace('<', "\\u003c");
+    format!(
+        "\n    <script type=\"application/json\" id=\"{METADATA_ELEMENT_ID}\">{json}</script>\n"
+    )
+}
+
+/// Writes the plot as a standalone HTML file with extra scripts before the end
+/// of its body
+///
+/// # Returns
+/// The absolute path of the written file
+///
+/// # Errors
+/// Returns an error if the parent directory cannot be created or the file
+/// cannot be written
+fn write_html(plot: &Plot, path: &Path, scripts: &[String]) -> Result<PathBuf> {
+    create_parent_dir(path)?;
+
+    let mut html = plot.to_html();
+    for script in scripts {
+        match html.rfind("</body>") {
+            Some(end) => html.insert_str(end, script),
+            None => html.push_str(script),
+        }
+    }
+
+    fs::write(path, html)
+        .with_context(|| format!("Error writing visualization to {}", path.display()))?;
+
+    path.canonicalize()
+        .with_context(|| format!("Error resolving output path {}", path.display()))
+}
+
+/// Renders the plot as a static PNG or SVG image with Kaleido
+///
+/// Creates any missing parent directories of `path` before writing.
+///
+/// # Arguments
+/// * `plot` - Plot to render
+/// * `path` - Destination of the image file
+/// * `format` - Image format; must not be [`OutputFormat::Html`]
+/// * `width` - Image width in layout pixels
+/// * `height` - Image height in layout pixels
+/// * `scale` - Multiplier applied to the pixel density; 2 doubles the resolution
+///
+/// # Returns
+/// The absolute path of the written file
+///
+/// # Errors
+/// Returns an error if Kaleido is unavailable or fails, or the file cannot be
+/// written
+pub fn write_plot_image(
+    plot: &Plot,
+    path: &Path,
+    format: OutputFormat,
+    width: usize,
+    height: usize,
+    scale: f64,
+) -> Result<PathBuf> {
+    if format == OutputFormat::Html {
+        bail!("HTML is not a static image format; use write_plot_html instead");
+    }
+
+    let image = render_image(plot, format.extension(), width, height, scale)?;
+    create_parent_dir(path)?;
+    fs::write(path, image)
+        .with_context(|| format!("Error writing visualization to {}", path.display()))?;
+
+    path.canonicalize()
+        .with_context(|| format!("Error resolving output path {}", path.display()))
+}
+
+/// Creates an interactive scatter plot of the embedding coordinates
+///
+/// Emits one trace per class, each drawn in a distinct qualitative color and
+/// listed in the legend under its class name, so that classes can be told
+/// apart and toggled individually. Unlabeled data, and data colored by
+/// continuous values, is drawn as a single trace without a legend entry; the
+/// latter gets a colorbar instead.
+///
+/// Labels need not be contiguous or start at 0: each distinct label takes the
+/// next palette color in ascending label order, and labels without a name are
+/// shown as their raw value.
+///
+/// # Arguments
+/// * `embedding` - 2D array of shape (n_samples, 2) containing embedded coordinates
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label; may be
+///   empty to show the raw label values
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings
+///
+/// # Returns
+/// One trace per class, in ascending label order. With
+/// [`PlotStyle::centroids`] and class labels, each class's centroid marker
+/// and spread ellipse follow as further traces in the same legend group.
+///
+/// # Errors
+/// Returns an error if the embedding does not have exactly 2 dimensions
+pub fn create_scatter_plot(
+    embedding: ArrayView2<f32>,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+) -> Result<Vec<Box<Scatter<f32, f32>>>> {
+    if embedding.ncols() != 2 {
+        bail!(
+            "Expected a 2-dimensional embedding, got {}",
+            embedding.ncols()
+        );
+    }
+
+    let groups = class_groups(embedding.nrows(), labels, class_names, style);
+    let opacity = style.marker_opacity(plotted_points(&groups));
+    let mut traces: Vec<_> = groups
+        .iter()
+        .map(|group| {
+            // Only the class's own rows are gathered, so no embedding column is copied whole
+            let x = group.column(embedding, 0);
+            let y = group.column(embedding, 1);
+            // Grouping by class lets one legend entry toggle every panel's trace
+            let scatter = Scatter::new(x, y)
+                .mode(Mode::Markers)
+                .name(&group.name)
+                .legend_group(&group.name)
+                .show_legend(group.in_legend())
+                .marker(group.marker(labels, style).opacity(opacity))
+                .hover_text_array(group.hover_text(labels, hover))
+                .hover_template(HOVER_TEMPLATE)
+                .web_gl_mode(style.webgl);
+            match hover.thumbnails {
+                Some(_) => scatter.custom_data(group.rows.clone()),
+                None => scatter,
+            }
+        })
+        .collect();
+
+    if let (true, Some(Labels::Discrete(labels))) = (style.centroids, labels) {
+        let centroids = class_centroids(embedding, labels);
+        let spreads = style
+            .centroid_spread
+            .then(|| class_spreads(embedding, labels, &centroids));
+        for group in groups.iter().filter(|group| group.votes.is_none()) {
+            let (Some(label), Some(color)) = (group.label, group.color) else {
+                continue;
+            };
+            let centroid = centroids[&label];
+            traces.push(centroid_trace(&group.name, color, centroid));
+            if let Some(spreads) = &spreads {
+                traces.push(spread_trace(&group.name, color, centroid, spreads[&label]));
+            }
+        }
+    }
+
+    Ok(traces)
+}
+
+/// Returns the mean position of each class in a 2D embedding
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, 2) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,)
+///
+/// # Returns
+/// The (x, y) centroid of every label present
+pub fn class_centroids(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+) -> HashMap<i32, (f32, f32)> {
+    let mut sums: HashMap<i32, (f64, f64, usize)> = HashMap::new();
+    for (row, &label) in embedding.rows().into_iter().zip(labels) {
+        let sum = sums.entry(label).or_default();
+        sum.0 += row[0] as f64;
+        sum.1 += row[1] as f64;
+        sum.2 += 1;
+    }
+    sums.into_iter()
+        .map(|(label, (x, y, n))| (label, ((x / n as f64) as f32, (y / n as f64) as f32)))
+        .collect()
+}
+
+/// Returns the standard deviation of each class along both axes of a 2D embedding
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, 2) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `centroids` - Centroid of every label, as from [`class_centroids`]
+///
+/// # Returns
+/// The (x, y) population standard deviation of every label present
+pub fn class_spreads(
+    embedding: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    centroids: &HashMap<i32, (f32, f32)>,
+) -> HashMap<i32, (f32, f32)> {
+    let mut sums: HashMap<i32, (f64, f64, usize)> = HashMap::new();
+    for (row, &label) in embedding.rows().into_iter().zip(labels) {
+        let (cx, cy) = centroids[&label];
+        let sum = sums.entry(label).or_default();
+        sum.0 += ((row[0] - cx) as f64).powi(2);
+        sum.1 += ((row[1] - cy) as f64).powi(2);
+        sum.2 += 1;
+    }
+    sums.into_iter()
+        .map(|(label, (x, y, n))| {
+            let std = |sum: f64| (sum / n as f64).sqrt() as f32;
+            (label, (std(x), std(y)))
+        })
+        .collect()
+}
+
+/// Number of line segments approximating each spread ellipse
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// Returns a large labeled marker at a class's centroid
+fn centroid_trace(name: &str, color: &'static str, (x, y): (f32, f32)) -> Box<Scatter<f32, f32>> {
+    Scatter::new(vec![x], vec![y])
+        .mode(Mode::MarkersText)
+        .name(name)
+        .legend_group(name)
+        .show_legend(false)
+        .text(name)
+        .text_position(Position::TopCenter)
+        .marker(
+            Marker::new()
+                .size(14)
+                .color(color)
+                .line(Line::new().color("black").width(1.5)),
+        )
+        .hover_text_array(vec![format!("{name} centroid")])
+        .hover_template(HOVER_TEMPLATE)
+}
+
+/// Returns an axis-aligned ellipse with one standard deviation radii around a
+/// class's centroid
+fn spread_trace(
+    name: &str,
+    color: &'static str,
+    (cx, cy): (f32, f32),
+    (sx, sy): (f32, f32),
+) -> Box<Scatter<f32, f32>> {
+    let angles = (0..=ELLIPSE_SEGMENTS).map(|i| i as f32 / ELLIPSE_SEGMENTS as f32 * TAU);
+    let x = angles.clone().map(|a| cx + sx * a.cos()).collect();
+    let y = angles.map(|a| cy + sy * a.sin()).collect();
+    Scatter::new(x, y)
+        .mode(Mode::Lines)
+        .name(name)
+        .legend_group(name)
+        .show_legend(false)
+        .line(Line::new().color(color).width(2.0))
+        .hover_info(TraceHoverInfo::Skip)
+}
+
+/// Creates an interactive 3D scatter plot of the embedding coordinates
+///
+/// Uses the same per-class traces and legend as [`create_scatter_plot`].
+///
+/// # Arguments
+/// * `embedding` - 2D array of shape (n_samples, 3) containing embedded coordinates
+/// * `labels` - Class labels or continuous values of shape (n_samples,) to color by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label; may be
+///   empty to show the raw label values
+/// * `hover` - Extra per-point hover information
+/// * `style` - Appearance settings
+///
+/// # Returns
+/// One trace per class, in ascending label order
+///
+/// # Errors
+/// Returns an error if the embedding does not have exactly 3 dimensions
+pub fn create_scatter_3d_plot(
+    embedding: ArrayView2<f32>,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    hover: &HoverInfo,
+    style: &PlotStyle,
+) -> Result<Vec<Box<Scatter3D<f32, f32, f32>>>> {
+    if embedding.ncols() != 3 {
+        bail!(
+            "Expected a 3-dimensional embedding, got {}",
+            embedding.ncols()
+        );
+    }
+
+    let groups = class_groups(embedding.nrows(), labels, class_names, style);
+    let opacity = style.marker_opacity(plotted_points(&groups));
+    let traces = groups
+        .into_iter()
+        .map(|group| {
+            let x = group.column(embedding, 0);
+            let y = group.column(embedding, 1);
+            let z = group.column(embedding, 2);
+            let scatter = Scatter3D::new(x, y, z)
+                .mode(Mode::Markers)
+                .name(&group.name)
+                .legend_group(&group.name)
+                .show_legend(group.in_legend())
+                .marker(group.marker(labels, style).opacity(opacity))
+                .hover_text_array(group.hover_text(labels, hover))
+                .hover_template(HOVER_TEMPLATE);
+            match hover.thumbnails {
+                Some(_) => scatter.custom_data(group.rows),
+                None => scatter,
+            }
+        })
+        .collect();
+
+    Ok(traces)
+}
+
+/// Returns the name of a class, falling back to the raw label if unnamed
+pub fn class_name(label: i32, class_names: &[&str]) -> String {
+    match usize::try_from(label).ok().and_then(|l| class_names.get(l)) {
+        Some(name) => name.to_string(),
+        None => label.to_string(),
+    }
+}
+
+/// Creates a heatmap of which classes the nearest neighbors of each class belong to
+///
+/// Each row shows the classes of one class's nearest embedded neighbors as
+/// shares of that row's total, so classes of different sizes are comparable.
+/// The first class is drawn at the top, like a confusion matrix.
+///
+/// # Arguments
+/// * `confusion` - Neighbor counts of shape (n_classes, n_classes), as from
+///   [`crate::metrics::neighbor_confusion`]
+/// * `classes` - Label of each row and column, in order
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `style` - Appearance settings
+/// * `title` - Title shown above the plot
+///
+/// # Errors
+/// Returns an error if the matrix is not square with one row per class
+pub fn create_confusion_plot(
+    confusion: ArrayView2<u32>,
+    classes: &[i32],
+    class_names: &[&str],
+    style: &PlotStyle,
+    title: &str,
+) -> Result<Plot> {
+    if confusion.dim() != (classes.len(), classes.len()) {
+        bail!(
+            "Expected a {0}x{0} neighbor confusion matrix, got {1:?}",
+            classes.len(),
+            confusion.dim()
+        );
+    }
+
+    let names: Vec<_> = classes
+        .iter()
+        .map(|&label| class_name(label, class_names))
+        .collect();
+    // Heatmap rows are drawn bottom-up, so reverse them to put the first class on top
+    let z: Vec<Vec<f64>> = confusion
+        .rows()
+        .into_iter()
+        .rev()
+        .map(|row| {
+            let total = row.sum().max(1) as f64;
+            row.iter().map(|&count| count as f64 / total).collect()
+        })
+        .collect();
+    let y: Vec<_> = names.iter().rev().cloned().collect();
+
+    let heatmap = HeatMap::new(names, y, z)
+        .color_scale(style.palette.color_scale())
+        .color_bar(ColorBar::new().title(Title::with_text("Share of neighbors")))
+        .zmin(0.0)
+        .zmax(1.0)
+        .hover_template("%{y} points: %{z:.1%} of neighbors are %{x}<extra></extra>");
+
+    let mut plot = Plot::new();
+    plot.add_trace(heatmap);
+    plot.set_layout(
+        Layout::new()
+            .title(Title::with_text(title))
+            .x_axis(titled_axis(Some("Class of neighbor")))
+            .y_axis(titled_axis(Some("Class of point")))
+            .width(800)
+            .height(800),
+    );
+    Ok(plot)
+}
+
+/// Creates a density heatmap of a 2D embedding with a titled layout
+///
+/// The embedding's bounding box is divided into a grid of
+/// [`DENSITY_BINS`] x [`DENSITY_BINS`] cells, each colored by the number of
+/// points falling into it. Empty cells are left blank, so the background
+/// shows where the embedding has no points at all.
+///
+/// # Arguments
+/// * `embedding` - Array of shape (n_samples, 2) containing embedded coordinates
+/// * `style` - Appearance settings
+/// * `title` - Title shown above the plot
+///
+/// # Errors
+/// Returns an error if the embedding does not have exactly 2 dimensions
+pub fn create_density_plot(
+    embedding: ArrayView2<f32>,
+    style: &PlotStyle,
+    title: &str,
+) -> Result<Plot> {
+    if embedding.ncols() != 2 {
+        bail!(
+            "A density plot needs a 2-dimensional embedding, got {}",
+            embedding.ncols()
+        );
+    }
+
+    let (x_centers, x_bins) = bin_column(embedding.column(0).iter().copied());
+    let (y_centers, y_bins) = bin_column(embedding.column(1).iter().copied());
+    let mut counts = vec![vec![0u32; DENSITY_BINS]; DENSITY_BINS];
+    for (x, y) in x_bins.into_iter().zip(y_bins) {
+        counts[y][x] += 1;
+    }
+    let z: Vec<Vec<Option<u32>>> = counts
+        .into_iter()
+        .map(|row| row.into_iter().map(|n| (n > 0).then_some(n)).collect())
+        .collect();
+
+    let heatmap = HeatMap::new(x_centers, y_centers, z)
+        .color_scale(style.palette.color_scale())
+        .color_bar(ColorBar::new().title(Title::with_text("Points")))
+        .hover_template("x: %{x:.2f}<br>y: %{y:.2f}<br>points: %{z}<extra></extra>");
+
+    let mut plot = Plot::new();
+    plot.add_trace(heatmap);
+    plot.set_layout(
+        Layout::new()
+            .title(Title::with_text(title))
+            .x_axis(style.x_axis())
+            .y_axis(style.y_axis_2d("x"))
+            .annotations(style.point_annotations(embedding)?)
+            .width(800)
+            .height(800),
+    );
+    Ok(plot)
+}
+
+/// Assigns each value to one of [`DENSITY_BINS`] equal-width bins spanning
+/// the values' range
+///
+/// # Returns
+/// A tuple containing:
+/// * Center of each bin
+/// * Bin index of each value
+fn bin_column(values: impl Iterator<Item = f32> + Clone) -> (Vec<f32>, Vec<usize>) {
+    let (mut min, mut max) = values
+        .clone()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+    // Give identical or missing values a nonzero range to divide
+    if min >= max {
+        let center = if min.is_finite() { min } else { 0.0 };
+        (min, max) = (center - 0.5, center + 0.5);
+    }
+
+    let width = (max - min) / DENSITY_BINS as f32;
+    let centers = (0..DENSITY_BINS)
+        .map(|i| min + (i as f32 + 0.5) * width)
+        .collect();
+    let bins = values
+        .map(|v| (((v - min) / width) as usize).min(DENSITY_BINS - 1))
+        .collect();
+    (centers, bins)
+}
+
+/// Points of one class, drawn together as a single trace
+struct ClassGroup {
+    /// Legend entry of the class
+    name: String,
+
+    /// Label of the class, or `None` for the single group of unlabeled or
+    /// continuously colored data
+    label: Option<i32>,
+
+    /// Marker color of a class, or `None` for the single group of unlabeled
+    /// or continuously colored data
+    color: Option<&'static str>,
+
+    /// Rows of the embedding belonging to the class
+    rows: Vec<usize>,
+
+    /// Names of the classes the rows' nearest neighbors voted for, for the
+    /// group of a class's points whose vote disagrees with their label
+    votes: Option<Vec<String>>,
+
+    /// Whether the markers are outlined to make a small class stand out
+    outlined: bool,
+}
+
+impl ClassGroup {
+    /// Returns whether the group has its own legend entry
+    ///
+    /// Only classes do; points whose k-NN vote disagrees with their label share
+    /// the entry of their class.
+    fn in_legend(&self) -> bool {
+        self.color.is_some() && self.votes.is_none()
+    }
+
+    /// Returns the coordinates of the group's points along one dimension
+    fn column(&self, embedding: ArrayView2<f32>, dim: usize) -> Vec<f32> {
+        self.rows.iter().map(|&i| embedding[[i, dim]]).collect()
+    }
+
+    /// Returns the hover text of each of the group's points
+    ///
+    /// Points show their class name, or their value when colored by
+    /// continuous values.
+    ///
+    /// # Arguments
+    /// * `labels` - Values the points are colored by, if any
+    /// * `hover` - Extra per-point hover information
+    fn hover_text(&self, labels: Option<&Labels>, hover: &HoverInfo) -> Vec<String> {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let id = hover.ids.map_or(i, |ids| ids[i]);
+                match (labels, &self.votes) {
+                    (Some(Labels::Discrete(_)), Some(votes)) => {
+                        format!("#{id}<br>{}<br>k-NN vote: {}", self.name, votes[position])
+                    }
+                    (Some(Labels::Discrete(_)), None) => format!("#{id}<br>{}", self.name),
+                    (Some(Labels::Continuous(values)), _) => format!("#{id}<br>{}", values[i]),
+                    (None, _) => format!("#{id}"),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the marker styling of the group's points
+    ///
+    /// # Arguments
+    /// * `labels` - Values the points are colored by, if any
+    /// * `style` - Appearance settings
+    fn marker(&self, labels: Option<&Labels>, style: &PlotStyle) -> Marker {
+        let size = style.marker_size.unwrap_or(DEFAULT_MARKER_SIZE);
+        let marker = match self.votes {
+            Some(_) => Marker::new().size(2 * size + 2).symbol(MarkerSymbol::X),
+            None => Marker::new().size(size),
+        };
+        let marker = if self.outlined {
+            marker.line(Line::new().color("black").width(1.0))
+        } else {
+            marker
+        };
+        match (self.color, labels) {
+            (Some(color), _) => marker.color(color),
+            (None, Some(Labels::Continuous(values))) => marker
+                .color_array(self.rows.iter().map(|&i| values[i]).collect())
+                .color_scale(style.palette.color_scale())
+                .show_scale(true),
+            (None, _) => marker,
+        }
+    }
+}
+
+/// Builds the script showing the hovered point's thumbnail in HTML output
+///
+/// The pixels of all images are embedded once as base64-encoded bytes, and
+/// each trace stores the row of every point in its custom data. On hover the
+/// script draws that row's image onto a canvas next to the cursor.
+///
+/// # Errors
+/// Returns an error if the images are not rows of 28x28 pixels
+fn thumbnail_script(thumbnails: ArrayView2<f32>) -> Result<String> {
+    let pixels = THUMBNAIL_SIZE * THUMBNAIL_SIZE;
+    if thumbnails.ncols() != pixels {
+        bail!(
+            "Hover thumbnails need {THUMBNAIL_SIZE}x{THUMBNAIL_SIZE} images, got rows of {} values",
+            thumbnails.ncols()
+        );
+    }
+
+    let bytes: Vec<u8> = thumbnails
+        .iter()
+        .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    let data = STANDARD.encode(bytes);
+
+    Ok(format!(
+        r#"
+    <canvas id="thumbnail" width="{THUMBNAIL_SIZE}" height="{THUMBNAIL_SIZE}"
+        style="position:fixed; display:none; width:84px; height:84px; image-rendering:pixelated;
+        border:1px solid #444; background:#fff; pointer-events:none; z-index:1000"></canvas>
+    <script>
+        const thumbnailPixels = Uint8Array.from(atob("{data}"), c => c.charCodeAt(0));
+        const thumbnail = document.getElementById("thumbnail");
+        const thumbnailContext = thumbnail.getContext("2d");
+        function showThumbnail(event) {{
+            const point = event.points[0];
+            if (point.customdata === undefined) return;
+            const image = thumbnailContext.createImageData({THUMBNAIL_SIZE}, {THUMBNAIL_SIZE});
+            const offset = point.customdata * {pixels};
+            for (let i = 0; i < {pixels}; i++) {{
+                // Draw dark strokes on a light background
+                const value = 255 - thumbnailPixels[offset + i];
+                image.data.set([value, value, value, 255], i * 4);
+            }}
+            thumbnailContext.putImageData(image, 0, 0);
+            const mouse = event.event || {{ clientX: 0, clientY: 0 }};
+            thumbnail.style.left = (mouse.clientX + 16) + "px";
+            thumbnail.style.top = (mouse.clientY + 16) + "px";
+            thumbnail.style.display = "block";
+        }}
+        function attachThumbnails() {{
+            const graph = document.getElementById("plotly-html-element");
+            if (!graph || !graph.on) {{
+                // The plot is created asynchronously; retry until it exists
+                setTimeout(attachThumbnails, 100);
+                return;
+            }}
+            graph.on("plotly_hover", showThumbnail);
+            graph.on("plotly_unhover", () => thumbnail.style.display = "none");
+        }}
+        attachThumbnails();
+    </script>
+"#
+    ))
+}
+
+/// Groups the rows of the embedding by class
+///
+/// Classes are ordered by label, or from the largest to the smallest with
+/// [`PlotStyle::small_classes_on_top`], and colored by cycling through the
+/// palette's class colors. Unlabeled data, and data colored by continuous
+/// values, forms a single group without a class color. Only the rows in
+/// [`PlotStyle::shown_rows`] are grouped, dropping classes left without any.
+///
+/// # Arguments
+/// * `n_rows` - Number of points in the embedding
+/// * `labels` - Values of shape (n_rows,) the points are colored by, if known
+/// * `class_names` - Human-readable name of each class, indexed by label
+/// * `style` - Appearance settings providing the class colors and the classes to draw
+fn class_groups(
+    n_rows: usize,
+    labels: Option<&Labels>,
+    class_names: &[&str],
+    style: &PlotStyle,
+) -> Vec<ClassGroup> {
+    let shown = |i: usize| style.shown_rows.as_ref().is_none_or(|shown| shown[i]);
+    let thin = |rows: Vec<usize>| {
+        let rows = rows.into_iter().filter(|&i| shown(i)).collect();
+        thin_rows(rows, style.max_points_per_class)
+    };
+    let Some(Labels::Discrete(labels)) = labels else {
+        let mut groups = vec![ClassGroup {
+            name: "points".to_string(),
+            label: None,
+            color: None,
+            rows: thin((0..n_rows).collect()),
+            votes: None,
+            outlined: false,
+        }];
+        cap_points(&mut groups, style.max_points);
+        return groups;
+    };
+
+    let mut rows_by_label: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+    for (i, &label) in labels.iter().enumerate() {
+        rows_by_label.entry(label).or_default().push(i);
+    }
+
+    // Colors follow each label's rank rather than its value, so any integers work, and
+    // are assigned before filtering, so a class looks the same in every plot
+    let colors = style.palette.class_colors();
+    let largest = rows_by_label.values().map(Vec::len).max().unwrap_or(0);
+    let mut classes: Vec<_> = rows_by_label
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (label, rows))| {
+            style
+                .classes
+                .as_ref()
+                .is_none_or(|classes| classes.contains(label))
+                && rows.iter().any(|&i| shown(i))
+        })
+        .collect();
+    // Later traces are drawn on top; the sort is stable, so equal sizes keep label order
+    if style.small_classes_on_top {
+        classes.sort_by_key(|(_, (_, rows))| std::cmp::Reverse(rows.len()));
+    }
+    let mut groups: Vec<_> = classes
+        .into_iter()
+        .map(|(position, (label, rows))| ClassGroup {
+            name: class_name(label, class_names),
+            label: Some(label),
+            color: Some(colors[position % colors.len()]),
+            outlined: style.outline_small_classes
+                && (rows.len() as f64) < SMALL_CLASS_FRACTION * largest as f64,
+            rows: thin(rows),
+            votes: None,
+        })
+        .collect();
+    cap_points(&mut groups, style.max_points);
+    match &style.knn_predictions {
+        Some(predictions) => split_disagreeing(groups, labels, predictions, class_names),
+        None => groups,
+    }
+}
+
+/// Moves each class's points whose k-NN vote disagrees with their label into a group of its own
+///
+/// The groups of disagreeing points follow all classes, so they are drawn on
+/// top where classes overlap.
+///
+/// # Arguments
+/// * `groups` - Groups of the classes
+/// * `labels` - Class labels of shape (n_rows,)
+/// * `predictions` - Classes predicted by the k-NN vote, of shape (n_rows,)
+/// * `class_names` - Human-readable name of each class, indexed by label
+fn split_disagreeing(
+    groups: Vec<ClassGroup>,
+    labels: &Array1<i32>,
+    predictions: &Array1<i32>,
+    class_names: &[&str],
+) -> Vec<ClassGroup> {
+    let mut disagreeing = Vec::new();
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .map(|mut group| {
+            let (agreeing, rows): (Vec<_>, Vec<_>) = group
+                .rows
+                .iter()
+                .partition(|&&i| predictions[i] == labels[i]);
+            group.rows = agreeing;
+            if !rows.is_empty() {
+                let votes = rows
+                    .iter()
+                    .map(|&i| class_name(predictions[i], class_names))
+                    .collect();
+                disagreeing.push(ClassGroup {
+                    name: group.name.clone(),
+                    label: group.label,
+                    color: group.color,
+                    rows,
+                    votes: Some(votes),
+                    outlined: group.outlined,
+                });
+            }
+            group
+        })
+        .collect();
+    groups.append(&mut disagreeing);
+    groups
+}
+
+/// Keeps at most `max` evenly spaced rows, if a maximum is given
+fn thin_rows(rows: Vec<usize>, max: Option<usize>) -> Vec<usize> {
+    match max {
+        Some(max) if rows.len() > max => (0..max).map(|i| rows[i * rows.len() / max]).collect(),
+        _ => rows,
+    }
+}
+
+/// Thins the groups to about `max` points in total, if a maximum is given
+///
+/// Each group keeps a share of the points in proportion to its size, so the
+/// plot shows the classes in their true proportions, but at least one point,
+/// so no class vanishes from the plot and its legend.
+fn cap_points(groups: &mut [ClassGroup], max: Option<usize>) {
+    let total = plotted_points(groups);
+    let Some(max) = max.filter(|&max| total > max) else {
+        return;
+    };
+    for group in groups {
+        let share = (group.rows.len() * max / total).max(1);
+        group.rows = thin_rows(std::mem::take(&mut group.rows), Some(share));
+    }
+}
+
+/// Returns the number of points drawn for the groups
+fn plotted_points(groups: &[ClassGroup]) -> usize {
+    groups.iter().map(|group| group.rows.len()).sum()
+}
@@ -0,0 +1,204 @@
+//! Chrome trace of the phases of a run
+//!
+//! Every phase timed through [`Timings`](crate::timing::Timings) runs inside a
+//! span named after it. When profiling is enabled, [`ProfileLayer`] records
+//! each of those spans as a complete event of the Chrome trace event format,
+//! and [`TraceGuard`] writes them to a JSON file once the run ends. The file
+//! opens in `chrome://tracing`, Perfetto or speedscope, which show the phases
+//! on a timeline and as a flame graph.
+//!
+//! This is the format the `tracing-chrome` crate writes, produced here with
+//! `serde_json`.
+
+use crate::export::create_parent_dir;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{info, warn, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target of the spans wrapping each timed phase
+///
+/// It names no module, so hiding it from the log output hides no events.
+pub const PROFILE_TARGET: &str = "pacmap_rs_example::phase";
+
+/// Instant the timestamps of the trace are measured from
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+/// Phases finished since profiling started, in the order they finished
+static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+
+/// Source of the small thread numbers shown in the trace
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Number of the current thread in the trace, assigned on first use
+    static THREAD: Cell<u64> = const { Cell::new(0) };
+}
+
+/// One phase of the run, as a complete event of the Chrome trace event format
+#[derive(Clone, Debug, Serialize)]
+struct TraceEvent {
+    /// Name of the phase, as in the timing breakdown
+    name: String,
+
+    /// Category of the event, which trace viewers can filter by
+    cat: &'static str,
+
+    /// Event type; `X` marks a complete event with a start and a duration
+    ph: &'static str,
+
+    /// Start of the phase in microseconds since profiling started
+    ts: f64,
+
+    /// Duration of the phase in microseconds
+    dur: f64,
+
+    /// Process the phase ran in
+    pid: u32,
+
+    /// Thread the phase ran on
+    tid: u64,
+}
+
+/// Logging layer recording the spans of timed phases
+///
+/// Only spans on [`PROFILE_TARGET`] should reach it, so it is meant to be
+/// installed with a per-layer filter on that target.
+pub struct ProfileLayer;
+
+/// Start of a phase, kept with its span until the span closes
+struct PhaseStart {
+    phase: String,
+    start: Instant,
+    thread: u64,
+}
+
+impl<S> Layer<S> for ProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let mut phase = PhaseName(None);
+        attrs.record(&mut phase);
+        if let (Some(phase), Some(span)) = (phase.0, ctx.span(id)) {
+            span.extensions_mut().insert(PhaseStart {
+                phase,
+                start: Instant::now(),
+                thread: thread_number(),
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(start) = extensions.get::<PhaseStart>() else {
+            return;
+        };
+        let origin = *ORIGIN.get_or_init(Instant::now);
+        let event = TraceEvent {
+            name: start.phase.clone(),
+            cat: "phase",
+            ph: "X",
+            ts: start.start.saturating_duration_since(origin).as_secs_f64() * 1e6,
+            dur: start.start.elapsed().as_secs_f64() * 1e6,
+            pid: std::process::id(),
+            tid: start.thread,
+        };
+        if let Ok(mut events) = EVENTS.lock() {
+            events.push(event);
+        }
+    }
+}
+
+/// Collects the `phase` field of a span
+struct PhaseName(Option<String>);
+
+impl Visit for PhaseName {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "phase" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "phase" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Returns the number of the current thread, numbering threads from 1 in the
+/// order they first run a phase
+fn thread_number() -> u64 {
+    THREAD.with(|thread| {
+        if thread.get() == 0 {
+            thread.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+        }
+        thread.get()
+    })
+}
+
+/// Writes the trace of the run when dropped
+///
+/// Keeping the guard alive for the whole run writes the trace however `main`
+/// returns, including on errors. Exits that skip destructors write no trace:
+/// a second Ctrl-C ends the process at once, as writing a file is not safe
+/// from a signal handler. A trace that cannot be written is reported as a
+/// warning, as the run's own outputs are already in place by then.
+pub struct TraceGuard {
+    path: PathBuf,
+}
+
+impl TraceGuard {
+    /// Starts profiling, measuring timestamps from now
+    ///
+    /// # Arguments
+    /// * `path` - Destination of the Chrome trace JSON file
+    pub fn new(path: &Path) -> Self {
+        ORIGIN.get_or_init(Instant::now);
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        match write_trace(&self.path) {
+            Ok(()) => info!("Profile trace saved to {}", self.path.display()),
+            Err(e) => warn!("Could not save the profile trace: {e:#}"),
+        }
+    }
+}
+
+/// Writes the phases recorded so far as a Chrome trace JSON file
+///
+/// # Arguments
+/// * `path` - Destination of the file, whose parent directories are created as needed
+///
+/// # Errors
+/// Returns an error if the directory or file cannot be written
+pub fn write_trace(path: &Path) -> Result<()> {
+    let events = EVENTS
+        .lock()
+        .map(|events| events.clone())
+        .unwrap_or_default();
+    let trace = serde_json::json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+    create_parent_dir(path)?;
+    let json = serde_json::to_string(&trace).context("Error serializing profile trace")?;
+    fs::write(path, json + "\n")
+        .with_context(|| format!("Error writing profile trace {}", path.display()))
+}
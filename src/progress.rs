@@ -0,0 +1,82 @@
+//! Terminal progress feedback for long-running steps
+//!
+//! PaCMAP runs its optimization without reporting progress, so long steps are
+//! wrapped in a spinner showing the elapsed time. The spinner is only drawn
+//! when stderr is a terminal and spinners have not been
+//! [disabled](set_spinners_enabled), keeping redirected output and logs clean.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Animation frames of the spinner
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Time between redraws of the spinner
+const TICK: Duration = Duration::from_millis(100);
+
+/// Whether spinners are drawn at all
+static SPINNERS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables drawing spinners, e.g. for quiet output
+pub fn set_spinners_enabled(enabled: bool) {
+    SPINNERS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Spinner with elapsed time, drawn on stderr until dropped
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    /// Starts a spinner showing `message` and the time since it started
+    ///
+    /// Does nothing if stderr is not a terminal or spinners are disabled.
+    pub fn start(message: impl Into<String>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        if !SPINNERS_ENABLED.load(Ordering::Relaxed) || !io::stderr().is_terminal() {
+            return Spinner { stop, handle: None };
+        }
+
+        let message = message.into();
+        let stopped = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            for frame in FRAMES.iter().cycle() {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                let elapsed = start.elapsed().as_secs_f32();
+                eprint!("\r{frame} {message} {elapsed:.1}s");
+                let _ = io::stderr().flush();
+                thread::sleep(TICK);
+            }
+            // Erase the spinner line
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        });
+
+        Spinner {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Runs `f` while showing a spinner with `message`
+    pub fn run<T>(message: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        let _spinner = Spinner::start(message);
+        f()
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
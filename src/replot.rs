@@ -0,0 +1,255 @@
+//! Commands working from embeddings saved with `--save-embedding`
+//!
+//! They skip loading the data and running PaCMAP, so plotting, scoring and
+//! comparing saved embeddings takes seconds even where fitting took minutes.
+
+use crate::cli::Cli;
+use crate::metrics::knn_accuracy;
+use crate::outputs::{coloring, print_summary, write_outputs, Outputs};
+use crate::plot::{HoverInfo, Panel};
+use crate::progress::Spinner;
+use crate::run::check_classes;
+use crate::saved::{RunConfig, SavedEmbedding};
+use crate::timing::Timings;
+use crate::watch::{watch_file, POLL_INTERVAL};
+use anyhow::{ensure, Context, Result};
+use serde_json::json;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Plots an embedding saved by an earlier run with `--save-embedding`
+///
+/// Loading the data and running PaCMAP are skipped entirely. A warning lists
+/// any settings that differ between the saved run and the current flags.
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `path` - Saved embedding file
+/// * `timings` - Phase timings of the run so far
+///
+/// # Errors
+/// Returns an error if the saved embedding cannot be read or the outputs
+/// cannot be written
+pub fn replot(cli: &Cli, path: &Path, mut timings: Timings) -> Result<()> {
+    info!("Loading saved embedding {}...", path.display());
+    let saved = timings.time("load", || SavedEmbedding::load(path))?;
+    let embedding = saved.embedding()?;
+    let labels = saved.labels();
+    let coloring = coloring(labels.as_ref(), saved.colors());
+    check_classes(&cli.classes, labels.as_ref())?;
+    info!(
+        "Loaded embedding of {} with shape {:?}",
+        saved.config.dataset,
+        embedding.shape()
+    );
+
+    // The saved file determines which data is plotted, so only the parameters are compared.
+    // `plot` takes no PaCMAP parameters, leaving nothing to compare.
+    let current = RunConfig {
+        dataset: saved.config.dataset.clone(),
+        ..cli.run_config()
+    };
+    let differences = saved.config.differences(&current);
+    if !differences.is_empty() && cli.command.is_none() {
+        warn!(
+            "Saved embedding was computed with different settings than the current flags: {}",
+            differences.join(", ")
+        );
+    }
+
+    let class_names: Vec<&str> = saved.class_names.iter().map(String::as_str).collect();
+    if cli.summary_only {
+        let labels = labels.with_context(|| {
+            format!(
+                "Saved embedding {} has no labels to summarize classes by",
+                path.display()
+            )
+        })?;
+        timings.time("metrics", || {
+            print_summary(embedding.view(), &labels, &class_names)
+        });
+        timings.log_summary();
+        return Ok(());
+    }
+    let hover = HoverInfo {
+        ids: saved.ids.as_deref(),
+        thumbnails: None,
+    };
+    let metadata = json!({ "config": saved.config });
+    let outputs = Outputs {
+        embedding: embedding.view(),
+        labels: labels.as_ref(),
+        coloring: coloring.as_ref(),
+        class_names: &class_names,
+        hover,
+        comparison: None,
+        animation: None,
+        dataset: &saved.config.dataset,
+        metadata: Some(&metadata),
+        training: None,
+    };
+    write_outputs(cli, &outputs, &mut timings)?;
+    timings.log_summary();
+    Ok(())
+}
+
+/// Replots a saved embedding whenever its file changes, for `plot --watch`
+///
+/// The file may not exist yet, as it can be written by a `fit` started at the
+/// same time. A replot that fails, for example on a file written by hand and
+/// not yet complete, is reported as a warning before waiting for the next
+/// change.
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `path` - Saved embedding file
+pub fn watch_replot(cli: &Cli, path: &Path) {
+    info!(
+        "Watching {} and replotting it whenever it changes; press Ctrl-C to stop",
+        path.display()
+    );
+    watch_file(path, POLL_INTERVAL, || {
+        if let Err(e) = replot(cli, path, Timings::new()) {
+            warn!("Could not replot {}: {e:#}", path.display());
+        }
+        info!("Waiting for {} to change...", path.display());
+    });
+    info!("Stopped watching {}", path.display());
+}
+
+/// Prints the k-NN accuracy of an embedding saved by an earlier run
+///
+/// Trustworthiness compares the embedding with the input data, which is not
+/// saved, so only the label-based score is available.
+///
+/// # Arguments
+/// * `cli` - Command-line options holding the neighbor count
+/// * `path` - Saved embedding file
+///
+/// # Errors
+/// Returns an error if the saved embedding cannot be read or has no labels
+pub fn print_saved_metrics(cli: &Cli, path: &Path) -> Result<()> {
+    let saved = SavedEmbedding::load(path)?;
+    let embedding = saved.embedding()?;
+    let labels = saved.labels().with_context(|| {
+        format!(
+            "Saved embedding {} has no labels to score k-NN accuracy with",
+            path.display()
+        )
+    })?;
+    ensure!(
+        embedding.nrows() >= 2,
+        "Saved embedding {} has too few points to score",
+        path.display()
+    );
+    cli.report(&format!(
+        "Embedding of {} with shape {:?}",
+        saved.config.dataset,
+        embedding.shape()
+    ));
+    let k = cli.knn_accuracy_k;
+    let accuracy = Spinner::run("Scoring k-NN accuracy", || {
+        knn_accuracy(embedding.view(), &labels, k)
+    });
+    cli.report_metric(
+        None,
+        "knn_accuracy",
+        k,
+        accuracy,
+        &format!("k-NN accuracy (k = {k}): {:.2}%", accuracy * 100.0),
+    );
+    Ok(())
+}
+
+/// Plots two embeddings saved by earlier runs side by side
+///
+/// The points are colored by the left embedding's labels, and the settings
+/// that differ between the two runs are logged.
+///
+/// # Arguments
+/// * `cli` - Command-line options holding the output settings
+/// * `left` - Saved embedding drawn on the left
+/// * `right` - Saved embedding drawn on the right
+/// * `timings` - Phase timings of the run so far
+///
+/// # Errors
+/// Returns an error if either file cannot be read, the embeddings are not 2D
+/// embeddings of the same number of points, or the plot cannot be written
+pub fn compare_saved(cli: &Cli, left: &Path, right: &Path, mut timings: Timings) -> Result<()> {
+    let (left_saved, right_saved) = timings.time("load", || -> Result<_> {
+        Ok((SavedEmbedding::load(left)?, SavedEmbedding::load(right)?))
+    })?;
+    let (left_embedding, right_embedding) = (left_saved.embedding()?, right_saved.embedding()?);
+    ensure!(
+        left_embedding.ncols() == 2 && right_embedding.ncols() == 2,
+        "compare plots embeddings side by side and needs 2D embeddings, got {} and {} dimensions",
+        left_embedding.ncols(),
+        right_embedding.ncols()
+    );
+    ensure!(
+        left_embedding.nrows() == right_embedding.nrows(),
+        "compare needs embeddings of the same points, got {} and {} points",
+        left_embedding.nrows(),
+        right_embedding.nrows()
+    );
+    let labels = left_saved.labels();
+    if right_saved.labels() != labels {
+        warn!("The embeddings have different labels; coloring both by the left one's");
+    }
+    let differences = left_saved.config.differences(&right_saved.config);
+    if differences.is_empty() {
+        info!("Both embeddings were computed with the same settings");
+    } else {
+        info!(
+            "The embeddings' settings differ in: {}",
+            differences.join(", ")
+        );
+    }
+
+    let captions: Vec<String> = [(left, &left_embedding), (right, &right_embedding)]
+        .into_iter()
+        .map(|(path, embedding)| {
+            let name = path
+                .file_stem()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy();
+            match &labels {
+                Some(labels) => format!(
+                    "{name}: k-NN accuracy {:.1}%",
+                    knn_accuracy(embedding.view(), labels, cli.knn_accuracy_k) * 100.0
+                ),
+                None => name.into_owned(),
+            }
+        })
+        .collect();
+    let coloring = coloring(labels.as_ref(), left_saved.colors());
+    let class_names: Vec<&str> = left_saved.class_names.iter().map(String::as_str).collect();
+    let metadata = json!({ "panels": [left_saved.config, right_saved.config] });
+    let outputs = Outputs {
+        embedding: left_embedding.view(),
+        labels: labels.as_ref(),
+        coloring: coloring.as_ref(),
+        class_names: &class_names,
+        hover: HoverInfo {
+            ids: left_saved.ids.as_deref(),
+            thumbnails: None,
+        },
+        comparison: Some((
+            Panel {
+                caption: &captions[0],
+                embedding: left_embedding.view(),
+            },
+            Panel {
+                caption: &captions[1],
+                embedding: right_embedding.view(),
+            },
+        )),
+        animation: None,
+        dataset: &left_saved.config.dataset,
+        metadata: Some(&metadata),
+        training: None,
+    };
+    write_outputs(cli, &outputs, &mut timings)?;
+    timings.log_summary();
+    Ok(())
+}
@@ -0,0 +1,1224 @@
+//! The full run of the example, from loading the data to writing the plot
+//!
+//! [`run`] loads and preprocesses the data, then fits PaCMAP once, or several
+//! times for `--grid`, `--ensemble` and `--verify-reproducible`. [`preflight`]
+//! and [`print_plan`] check a run before anything slow starts.
+
+use crate::anchors::{anchored_initialization, Anchor};
+use crate::cache;
+use crate::cli::{Cli, Command};
+use crate::constant::{constant_columns, varying_columns};
+use crate::dataset::{class_names, load_dataset_subset, CachePolicy};
+use crate::distance::{manhattan_neighbors, Metric};
+use crate::duplicates::{find_duplicates, unique_rows, Duplicate};
+use crate::graph::{
+    graph_distances_path, graph_from_pairs, pacmap_neighbor_graph, write_graph_npy,
+    PACMAP_PCA_COMPONENTS,
+};
+use crate::grid::{write_grid_csv, GridResult};
+use crate::input::{
+    inspect_input, load_anchors, load_inputs, load_neighbors, validate_matrix, InputData,
+};
+use crate::interrupt::{check_interrupted, interrupted};
+use crate::kaleido::find_kaleido;
+use crate::loss;
+use crate::manifest::{phase_timings, PacmapSettings, RunManifest, Scores, PACMAP_VERSION};
+use crate::metrics::{knn_accuracy, neighbor_recall, snapshot_movement, trustworthiness};
+use crate::normalize::{normalize, Normalize};
+use crate::outputs::{
+    embedded_row, embedded_rows, plot_coloring, print_summary, write_gallery,
+    write_neighbor_confusion, write_outputs, Gallery, Outputs,
+};
+use crate::pca::pca_reduce;
+use crate::plot::{Animation, HoverInfo, OutputFormat, Panel};
+use crate::progress::Spinner;
+use crate::sample::{sample_indices, stratified_sample_indices, SampleStrategy};
+use crate::saved::SavedEmbedding;
+use crate::timing::Timings;
+use crate::{embed, embed_with_snapshots, fit};
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use ndarray::{Array1, Array2, Array3, ArrayView2, Axis};
+use pacmap::{Configuration, Initialization, PairConfiguration};
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Largest difference between the coordinates of two seeded runs that
+/// `--verify-reproducible` accepts; seeded runs are expected to match exactly
+pub(crate) const REPRODUCIBILITY_TOLERANCE: f32 = 1e-6;
+
+/// Number of duplicate rows listed as examples when warning about them
+pub(crate) const DUPLICATE_EXAMPLES: usize = 3;
+
+/// Number of constant features listed as examples when reporting them
+pub(crate) const CONSTANT_EXAMPLES: usize = 5;
+
+/// Number of points above which hover thumbnails noticeably slow down the plot
+pub(crate) const THUMBNAIL_WARNING_POINTS: usize = 20_000;
+
+/// Checks what can be checked before any slow work starts, and warns when
+/// `--preview` makes the results unrepresentative
+///
+/// # Arguments
+/// * `cli` - Command-line options
+///
+/// # Errors
+/// Returns an error if the run writes images but the image renderer is missing
+pub fn preflight(cli: &Cli) -> Result<()> {
+    // Fail before the slow embedding if the image renderer is missing
+    let plots = !cli.grid
+        && !cli.no_plot
+        && !cli.summary_only
+        && !matches!(cli.command, Some(Command::Metrics { .. }));
+    if cli.format != OutputFormat::Html && plots {
+        find_kaleido()?;
+    }
+
+    if cli.preview {
+        let (attraction, local, global) = cli.pacmap_config().num_iters;
+        warn!(
+            "PREVIEW MODE: embedding at most {} rows with {} iterations{}; the layout and \
+             scores are not representative of a full run",
+            cli.sample_size().unwrap_or_default(),
+            attraction + local + global,
+            if cli.pca.is_some() {
+                " and ignoring --pca"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Runs the full pipeline: loads the data, embeds it with PaCMAP, scores the
+/// embedding and writes the plot, manifest and other requested outputs
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `timings` - Phase timings of the run so far
+///
+/// # Errors
+/// Returns an error if the data cannot be loaded, PaCMAP fails, the run is
+/// interrupted or an output cannot be written
+pub fn run(cli: &Cli, mut timings: Timings) -> Result<()> {
+    // Load the user-supplied inputs, or download and combine the dataset splits
+    let dataset = cli.dataset_name();
+    let (x, labels, colors, sources, class_names) = timings.time("load", || -> Result<_> {
+        if cli.input.is_empty() {
+            info!("Loading {dataset} dataset...");
+            let (x, labels) = load_dataset_subset(
+                cli.dataset,
+                cli.split_sizes(),
+                cli.cache_policy(),
+                cli.max_download_size,
+            )?;
+            let names = class_names(cli.dataset).iter().map(|name| name.to_string());
+            return Ok((x, Some(labels), None, None, names.collect()));
+        }
+
+        for input in &cli.input {
+            info!("Loading input {}...", input.path().display());
+        }
+        let InputData {
+            x,
+            labels,
+            class_names,
+            colors,
+            sources,
+            source_names,
+        } = load_inputs(
+            &cli.input,
+            cli.label_column.as_ref(),
+            cli.color_column.as_ref(),
+            cli.features.as_ref(),
+        )?;
+        if cli.input.len() > 1 {
+            info!(
+                "Combined {} inputs into shape {:?}",
+                cli.input.len(),
+                x.shape()
+            );
+        }
+        if !class_names.is_empty() {
+            // --classes selects text classes by these numbers
+            let numbered: Vec<_> = class_names
+                .iter()
+                .enumerate()
+                .map(|(label, name)| format!("{name} ({label})"))
+                .collect();
+            info!(
+                "Read {} classes from text labels, numbered {}",
+                class_names.len(),
+                numbered.join(", ")
+            );
+        }
+        Ok((
+            x,
+            labels,
+            colors,
+            Some((sources, source_names)),
+            class_names,
+        ))
+    })?;
+    let class_names: Vec<&str> = class_names.iter().map(String::as_str).collect();
+    let class_names = &class_names[..];
+
+    check_interrupted("preprocessing")?;
+    validate_matrix(x.view())?;
+    info!(
+        "Input matrix of shape {:?} takes {:.1} MB",
+        x.shape(),
+        (x.len() * size_of::<f32>()) as f64 / 1e6
+    );
+
+    check_classes(&cli.classes, labels.as_ref())?;
+    // Optionally keep only the selected classes, which changes the neighbors PaCMAP sees
+    let (x, labels, colors, sources, selected) = match &labels {
+        Some(class_labels) if cli.classes_before_fit => {
+            let selected: Vec<_> = (0..x.nrows())
+                .filter(|&i| cli.classes.contains(&class_labels[i]))
+                .collect();
+            ensure!(
+                selected.len() >= 2,
+                "--classes keeps only {} rows",
+                selected.len()
+            );
+            let x = x.select(Axis(0), &selected);
+            let labels = labels.map(|labels| labels.select(Axis(0), &selected));
+            let colors = colors.map(|colors| colors.select(Axis(0), &selected));
+            let sources =
+                sources.map(|(sources, names)| (sources.select(Axis(0), &selected), names));
+            info!("Kept {} rows of the selected classes", x.nrows());
+            (x, labels, colors, sources, Some(selected))
+        }
+        _ => (x, labels, colors, sources, None),
+    };
+
+    // Report exact duplicate rows, which distort neighbor-based embeddings, and optionally drop them
+    let duplicates = timings.time("duplicates", || find_duplicates(x.view()));
+    if !duplicates.is_empty() {
+        warn!(
+            "{} of {} rows exactly repeat an earlier row, e.g. {}{}",
+            duplicates.len(),
+            x.nrows(),
+            duplicate_examples(&duplicates),
+            if cli.dedup {
+                ""
+            } else {
+                "; pass --dedup to drop them"
+            }
+        );
+    }
+    let (x, labels, colors, sources, kept) = if cli.dedup && !duplicates.is_empty() {
+        let kept = unique_rows(x.nrows(), &duplicates);
+        let x = x.select(Axis(0), &kept);
+        let labels = labels.map(|labels| labels.select(Axis(0), &kept));
+        let colors = colors.map(|colors| colors.select(Axis(0), &kept));
+        let sources = sources.map(|(sources, names)| (sources.select(Axis(0), &kept), names));
+        info!(
+            "Dropped {} duplicate rows, keeping {}",
+            duplicates.len(),
+            x.nrows()
+        );
+        (x, labels, colors, sources, Some(kept))
+    } else {
+        (x, labels, colors, sources, None)
+    };
+
+    // Optionally embed a reproducible random subset, which grid searches always use
+    // Default sample sizes quietly keep every row of smaller data
+    let sample = cli
+        .sample_size()
+        .filter(|&n| cli.sample.is_some() || n < x.nrows());
+    let (mut x, labels, colors, sources, ids) = match sample {
+        Some(n) => timings.time("sample", || -> Result<_> {
+            let seed = cli.seed.unwrap_or_default();
+            let ids = match cli.sample_strategy {
+                SampleStrategy::Random => sample_indices(x.nrows(), n, seed),
+                SampleStrategy::Stratified => {
+                    let labels = labels
+                        .as_ref()
+                        .context("--sample-strategy stratified needs class labels")?;
+                    stratified_sample_indices(labels, n, seed)
+                }
+            };
+            let x = x.select(Axis(0), &ids);
+            let labels = labels.map(|labels| labels.select(Axis(0), &ids));
+            let colors = colors.map(|colors| colors.select(Axis(0), &ids));
+            let sources = sources.map(|(sources, names)| (sources.select(Axis(0), &ids), names));
+            Ok((x, labels, colors, sources, Some(ids)))
+        })?,
+        None => (x, labels, colors, sources, None),
+    };
+    // Number points by their row in the input, before any rows were dropped
+    let ids = compose_rows(compose_rows(selected, kept), ids);
+
+    // Report features that are constant across the embedded rows, and optionally drop them
+    let constant = timings.time("constant", || constant_columns(x.view()));
+    if !constant.is_empty() {
+        info!(
+            "{} of {} features are constant, e.g. features {}{}",
+            constant.len(),
+            x.ncols(),
+            constant
+                .iter()
+                .take(CONSTANT_EXAMPLES)
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            if cli.drop_constant {
+                ""
+            } else {
+                "; pass --drop-constant to drop them"
+            }
+        );
+    }
+    let dropped_features = if cli.drop_constant && !constant.is_empty() {
+        let kept = varying_columns(x.ncols(), &constant);
+        ensure!(
+            !kept.is_empty(),
+            "Every feature is constant, leaving nothing to embed"
+        );
+        // Selecting columns yields a column-major array, but PaCMAP needs contiguous rows
+        x = timings.time("constant", || {
+            x.select(Axis(1), &kept).as_standard_layout().into_owned()
+        });
+        info!(
+            "Dropped {} constant features, keeping {}",
+            constant.len(),
+            x.ncols()
+        );
+        constant
+    } else {
+        Vec::new()
+    };
+
+    if cli.normalize != Normalize::None {
+        if let Some(method) = cli.normalize.to_possible_value() {
+            info!("Normalizing input with {}...", method.get_name());
+        }
+        timings.time("normalize", || normalize(&mut x, cli.normalize));
+        validate_matrix(x.view()).context("Normalization produced non-finite values")?;
+    }
+    if cli.metric == Metric::Cosine {
+        // Euclidean distances between unit-length samples rank pairs as cosine distance does
+        info!("Scaling samples to unit length for cosine distance...");
+        timings.time("metric", || normalize(&mut x, Normalize::UnitL2));
+    }
+
+    let k = cli.trustworthiness_k;
+    ensure!(
+        3 * k + 1 < 2 * x.nrows(),
+        "--trustworthiness-k {k} is too large for {} samples",
+        x.nrows()
+    );
+
+    // Optionally pre-reduce the input with PCA, keeping the original for scoring
+    let reduced = match cli.pca_components() {
+        Some(k) => {
+            info!(
+                "Reducing input with shape {:?} to {k} principal components...",
+                x.shape()
+            );
+            Some(timings.time("pca", || pca_reduce(x.view(), k))?)
+        }
+        None => None,
+    };
+    let x_fit = reduced.as_ref().unwrap_or(&x);
+
+    if cli.grid {
+        run_grid(cli, x.view(), x_fit.view(), labels.as_ref(), &mut timings)?;
+        timings.log_summary();
+        return Ok(());
+    }
+
+    if cli.verify_reproducible {
+        verify_reproducible(cli, x_fit.view(), &mut timings)?;
+        timings.log_summary();
+        return Ok(());
+    }
+
+    check_interrupted("running PaCMAP")?;
+    // Configure PaCMAP from the command-line parameters
+    let mut config = cli.pacmap_config();
+    if let Some(path) = &cli.neighbors_file {
+        info!("Loading neighbor graph {}...", path.display());
+        let pair_neighbors = timings.time("load", || load_neighbors(path, x_fit.nrows()))?;
+        let k = pair_neighbors.nrows() / x_fit.nrows();
+        ensure!(
+            k == cli.neighbors,
+            "Neighbor graph {} lists {k} neighbors per sample, but --neighbors is {}; pass \
+             --neighbors {k} to use it",
+            path.display(),
+            cli.neighbors
+        );
+        config.pair_configuration = PairConfiguration::NeighborsProvided { pair_neighbors };
+    }
+    if cli.metric == Metric::Manhattan {
+        ensure!(
+            cli.neighbors < x.nrows(),
+            "--neighbors {} needs more than {} samples",
+            cli.neighbors,
+            x.nrows()
+        );
+        // Searched in the input space, as Manhattan distances between principal components differ
+        info!("Searching nearest neighbors under Manhattan distance...");
+        let pair_neighbors =
+            timings.time("metric", || manhattan_neighbors(x.view(), cli.neighbors));
+        config.pair_configuration = PairConfiguration::NeighborsProvided { pair_neighbors };
+    }
+    if let Some(path) = &cli.anchors {
+        let anchors = resolve_anchors(
+            path,
+            x_fit.nrows(),
+            ids.as_deref(),
+            cli.embedding_dimensions,
+        )?;
+        info!(
+            "Starting {} points at their coordinates in {}...",
+            anchors.len(),
+            path.display()
+        );
+        let layout = timings.time("anchors", || {
+            anchored_initialization(x_fit.view(), &anchors, cli.embedding_dimensions)
+        })?;
+        config.initialization = Initialization::Value(layout);
+    }
+    if let Some(path) = &cli.graph_out {
+        let graph = match &config.pair_configuration {
+            PairConfiguration::NeighborsProvided { pair_neighbors } => {
+                graph_from_pairs(x.view(), pair_neighbors.view(), cli.metric)
+            }
+            _ => {
+                info!("Searching nearest neighbors as PaCMAP does, to save the graph...");
+                let graph = timings.time("neighbors", || {
+                    if x_fit.ncols() > PACMAP_PCA_COMPONENTS
+                        && matches!(config.initialization, Initialization::Pca)
+                    {
+                        // PaCMAP searches wide data among its leading principal components
+                        let k = PACMAP_PCA_COMPONENTS.min(x_fit.nrows());
+                        let components = pca_reduce(x_fit.view(), k)?;
+                        pacmap_neighbor_graph(
+                            components.view(),
+                            cli.neighbors,
+                            config.approx_threshold,
+                        )
+                    } else {
+                        pacmap_neighbor_graph(x_fit.view(), cli.neighbors, config.approx_threshold)
+                    }
+                })?;
+                config.pair_configuration = PairConfiguration::NeighborsProvided {
+                    pair_neighbors: graph.pair_neighbors(),
+                };
+                graph
+            }
+        };
+        timings.time("export", || write_graph_npy(&graph, path))?;
+        info!(
+            "Neighbor graph saved to {}, with distances in {}",
+            path.display(),
+            graph_distances_path(path).display()
+        );
+    }
+    let pacmap_settings = PacmapSettings::from(&config);
+
+    if let Some(runs) = cli.ensemble {
+        let members = fit_ensemble(
+            cli,
+            runs,
+            x.view(),
+            x_fit.view(),
+            config,
+            labels.as_ref(),
+            &mut timings,
+        )?;
+        let (coloring, plot_class_names) =
+            plot_coloring(cli, labels.as_ref(), colors, sources.as_ref(), class_names);
+        let panels: Vec<Panel> = members
+            .iter()
+            .map(|(embedding, caption)| Panel {
+                caption,
+                embedding: embedding.view(),
+            })
+            .collect();
+        let hover = HoverInfo {
+            ids: ids.as_deref(),
+            thumbnails: None,
+        };
+        let title = cli.title.clone().unwrap_or_else(|| {
+            format!(
+                "{runs} PaCMAP Embeddings of {dataset} ({} points)",
+                x.nrows()
+            )
+        });
+        let gallery = Gallery {
+            panels: &panels,
+            coloring: coloring.as_ref(),
+            class_names: &plot_class_names,
+            hover,
+            title: &title,
+        };
+        let saved = write_gallery(cli, &gallery, &mut timings)?;
+        info!("Done! Ensemble gallery saved to {}", saved.display());
+        timings.log_summary();
+        return check_interrupted("the remaining ensemble runs");
+    }
+
+    // Run PaCMAP reduction and time it
+    info!(
+        "Running PaCMAP on {dataset} with shape {:?}...",
+        x_fit.shape()
+    );
+    let snapshot_iterations = config.snapshots.clone().unwrap_or_default();
+    let (embedding, snapshots) = fit_cached(cli, x_fit.view(), config, &mut timings)?;
+    let losses = loss::phase_losses(pacmap_settings.num_iters);
+    loss::log_phase_losses(&losses);
+    if let (Some(movement), [.., before, last]) = (
+        snapshots
+            .as_ref()
+            .and_then(|snapshots| snapshot_movement(snapshots.view())),
+        &snapshot_iterations[..],
+    ) {
+        info!(
+            "Points moved {:.1}% of the layout's spread on average between iterations {before} \
+             and {last}",
+            movement * 100.0
+        );
+    }
+
+    check_interrupted("scoring")?;
+    let scores = timings.time("scoring", || {
+        score_embedding(cli, "PaCMAP", x.view(), embedding.view(), labels.as_ref())
+    });
+    if cli.summary_only {
+        let labels = labels
+            .as_ref()
+            .context("--summary-only needs class labels")?;
+        timings.time("metrics", || {
+            print_summary(embedding.view(), labels, class_names)
+        });
+        timings.log_summary();
+        return Ok(());
+    }
+
+    // Optionally embed the same data with a baseline algorithm for comparison
+    let baseline = match cli.compare_with {
+        Some(comparison) => {
+            let name = comparison.display_name();
+            info!("Running {name} for comparison...");
+            let (baseline, elapsed) = timings.measure("comparison", || {
+                Spinner::run(format!("Running {name}"), || {
+                    comparison.embed(x.view(), cli.embedding_dimensions)
+                })
+            });
+            let baseline = baseline?;
+            info!("{name} completed in {} ms", elapsed.as_millis());
+
+            let baseline_scores = timings.time("scoring", || {
+                score_embedding(cli, name, x.view(), baseline.view(), labels.as_ref())
+            });
+            let caption = caption(name, elapsed, &baseline_scores);
+            Some((baseline, caption))
+        }
+        None => None,
+    };
+    let pacmap_caption = timings
+        .get("fit")
+        .map(|fit| caption("PaCMAP", fit, &scores))
+        .unwrap_or_default();
+    let comparison = baseline.as_ref().map(|(baseline, caption)| {
+        (
+            Panel {
+                caption: &pacmap_caption,
+                embedding: embedding.view(),
+            },
+            Panel {
+                caption,
+                embedding: baseline.view(),
+            },
+        )
+    });
+
+    check_interrupted("writing outputs")?;
+    if let Some(path) = &cli.save_embedding {
+        let saved = SavedEmbedding::new(
+            cli.run_config(),
+            embedding.view(),
+            labels.as_ref(),
+            colors.as_ref(),
+            ids.as_deref(),
+            class_names,
+        );
+        timings.time("export", || saved.save(path))?;
+        info!("Embedding saved to {}", path.display());
+    }
+
+    if cli.hover_thumbnails && x.nrows() > THUMBNAIL_WARNING_POINTS {
+        warn!(
+            "Embedding thumbnails of {} images adds about {} MB to the HTML; consider --sample",
+            x.nrows(),
+            x.len() * 4 / 3 / 1_000_000
+        );
+    }
+    let hover = HoverInfo {
+        ids: ids.as_deref(),
+        thumbnails: cli.hover_thumbnails.then(|| x.view()),
+    };
+    let (coloring, plot_class_names) =
+        plot_coloring(cli, labels.as_ref(), colors, sources.as_ref(), class_names);
+    let mut manifest = RunManifest {
+        example_version: env!("CARGO_PKG_VERSION").to_string(),
+        pacmap_version: PACMAP_VERSION.to_string(),
+        config: cli.run_config(),
+        pacmap: pacmap_settings,
+        threads: rayon::current_num_threads(),
+        n_samples: x.nrows(),
+        n_features: x.ncols(),
+        dropped_features,
+        timings: phase_timings(&timings),
+        loss: losses,
+        scores,
+    };
+    // The plot embeds the manifest as it stands, with timings up to plotting
+    let metadata = serde_json::to_value(&manifest)?;
+    let training = cli.split_output.then(|| {
+        let sources = sources.as_ref().map(|(sources, _)| sources);
+        training_rows(cli, embedding.nrows(), ids.as_deref(), sources)
+    });
+    let outputs = Outputs {
+        embedding: embedding.view(),
+        labels: labels.as_ref(),
+        coloring: coloring.as_ref(),
+        class_names: &plot_class_names,
+        hover,
+        comparison,
+        animation: snapshots.as_ref().map(|snapshots| Animation {
+            snapshots: snapshots.view(),
+            iterations: &snapshot_iterations,
+        }),
+        dataset: &dataset,
+        metadata: Some(&metadata),
+        training: training.as_deref(),
+    };
+    let plotted = write_outputs(cli, &outputs, &mut timings)?;
+    if let (true, Some(labels)) = (cli.neighbor_confusion, &labels) {
+        write_neighbor_confusion(cli, embedding.view(), labels, class_names, &mut timings)?;
+    }
+
+    manifest.timings = phase_timings(&timings);
+    let manifest_path = plotted.map_or_else(
+        || cli.manifest_path(),
+        |path| path.with_file_name("manifest.json"),
+    );
+    manifest.write(&manifest_path)?;
+    info!("Run manifest saved to {}", manifest_path.display());
+
+    timings.log_summary();
+    Ok(())
+}
+
+/// Runs PaCMAP on the data, reusing an identical seeded run's cached embedding
+///
+/// Unseeded runs differ each time, so only seeded runs are cached; `--no-cache`
+/// fits those again too, still refreshing the cache entry.
+///
+/// # Arguments
+/// * `cli` - Command-line options controlling the cache
+/// * `x_fit` - Input data of shape (n_samples, n_features) as passed to PaCMAP
+/// * `config` - PaCMAP configuration
+/// * `timings` - Phase timings the fit is added to
+///
+/// # Errors
+/// Returns an error if PaCMAP fails or the cache directory cannot be determined
+pub(crate) fn fit_cached(
+    cli: &Cli,
+    x_fit: ArrayView2<f32>,
+    config: Configuration,
+    timings: &mut Timings,
+) -> Result<(Array2<f32>, Option<Array3<f32>>)> {
+    // Cleared before the cache lookup, so a reused embedding reports no loss
+    loss::start_recording();
+    let entry = match config.seed {
+        Some(_) => {
+            let dir = cache::cache_dir()?;
+            let key = timings.time("cache", || cache::cache_key(x_fit, &config));
+            Some((dir, key))
+        }
+        None => None,
+    };
+    if let (Some((dir, key)), false) = (&entry, cli.no_cache) {
+        if let Some(cached) = cache::load(dir, key, config.snapshots.is_some()) {
+            info!(
+                "Reusing the embedding of an identical earlier run from {}; pass --no-cache to \
+                 fit again",
+                dir.display()
+            );
+            return Ok(cached);
+        }
+    }
+
+    let (embedding, snapshots) = timings.time("fit", || {
+        Spinner::run("Running PaCMAP", || embed_with_snapshots(x_fit, config))
+    })?;
+    if let Some(duration) = timings.get("fit") {
+        info!("PaCMAP completed in {} ms", duration.as_millis());
+    }
+    // A failure to cache only costs the next run a fit, so it does not fail this one
+    if let Some((dir, key)) = &entry {
+        match cache::store(dir, key, &embedding, snapshots.as_ref()) {
+            Ok(()) => debug!("Cached the embedding as {key}"),
+            Err(e) => warn!("Could not cache the embedding: {e:#}"),
+        }
+    }
+    Ok((embedding, snapshots))
+}
+
+/// Prints what a run with these options would do, for `--dry-run`
+///
+/// Input files are opened and their selected columns resolved, so mistyped
+/// paths and column names are caught without downloading or embedding anything.
+///
+/// # Errors
+/// Returns an error if an input file or saved embedding cannot be read, or a
+/// selected column does not exist
+pub fn print_plan(cli: &Cli) -> Result<()> {
+    let mut plan = vec!["Dry run: nothing will be downloaded, embedded or written".to_string()];
+    if let Some(path) = &cli.config {
+        plan.push(format!("Config file: {}", path.display()));
+    }
+
+    if let Some(path) = &cli.load_embedding {
+        if cli.watches() {
+            plan.push(format!(
+                "Watch: replot saved embedding {} whenever it changes, until Ctrl-C",
+                path.display()
+            ));
+        } else {
+            ensure!(
+                path.is_file(),
+                "Saved embedding {} does not exist",
+                path.display()
+            );
+            plan.push(format!("Replot saved embedding: {}", path.display()));
+        }
+    } else if cli.input.is_empty() {
+        let sizes = cli.split_sizes();
+        plan.push(format!(
+            "Dataset: {} ({} training and {} test images), cached in {}{}",
+            cli.dataset.display_name(),
+            sizes.train,
+            sizes.test,
+            cli.dataset.cache_dir()?.display(),
+            match cli.cache_policy() {
+                CachePolicy::Reuse => "",
+                CachePolicy::Refresh => ", downloaded again",
+                CachePolicy::Offline => ", never downloaded",
+            }
+        ));
+    } else {
+        for input in &cli.input {
+            let columns = inspect_input(
+                input,
+                cli.label_column.as_ref(),
+                cli.color_column.as_ref(),
+                cli.features.as_ref(),
+            )?;
+            plan.push(format!(
+                "Input: {}{}",
+                input.path().display(),
+                columns.map_or(String::new(), |n| format!(" ({n} feature columns)"))
+            ));
+        }
+    }
+
+    if !cli.classes.is_empty() {
+        let classes: Vec<_> = cli.classes.iter().map(i32::to_string).collect();
+        plan.push(format!(
+            "Classes: {}, {}",
+            classes.join(", "),
+            if cli.classes_before_fit {
+                "dropping the others before embedding"
+            } else {
+                "hiding the others in the plot"
+            }
+        ));
+    }
+    if cli.load_embedding.is_none() {
+        plan.push(format!(
+            "Duplicate rows: {}",
+            if cli.dedup { "dropped" } else { "reported" }
+        ));
+        if let Some(n) = cli.sample_size() {
+            plan.push(format!(
+                "Sample: {n} rows{} with seed {}",
+                match cli.sample_strategy {
+                    SampleStrategy::Random => "",
+                    SampleStrategy::Stratified => " in proportion to each class",
+                },
+                cli.seed.unwrap_or_default()
+            ));
+        }
+        if let Some(method) = cli.normalize.to_possible_value() {
+            plan.push(format!("Normalize: {}", method.get_name()));
+        }
+        if let Some(metric) = cli.metric.to_possible_value() {
+            plan.push(format!("Metric: {}", metric.get_name()));
+        }
+        if let Some(k) = cli.pca_components() {
+            plan.push(format!("PCA: {k} components"));
+        }
+        if let Some(path) = &cli.anchors {
+            plan.push(format!("Anchors: {}", path.display()));
+        }
+
+        let config = cli.pacmap_config();
+        let (attraction, local, global) = config.num_iters;
+        let pairs = if cli.grid {
+            format!("{} grid combinations", cli.grid_points().len())
+        } else {
+            format!(
+                "{} neighbors, mid-near ratio {}, far pair ratio {}",
+                cli.neighbors, cli.mid_near_ratio, cli.far_pair_ratio
+            )
+        };
+        plan.push(format!(
+            "PaCMAP: {}D, {pairs}, learning rate {}, iterations {attraction},{local},{global}, {}",
+            cli.embedding_dimensions,
+            cli.learning_rate,
+            cli.seed
+                .map_or("unseeded".to_string(), |seed| format!("seed {seed}"))
+        ));
+        plan.push(format!("Threads: {}", rayon::current_num_threads()));
+        if let Some(comparison) = cli.compare_with {
+            plan.push(format!("Compare with: {}", comparison.display_name()));
+        }
+    }
+
+    if cli.grid {
+        plan.push(format!("Grid summary: {}", cli.grid_out.display()));
+    } else if cli.verify_reproducible {
+        plan.push(format!(
+            "Reproducibility check: embed twice with seed {} and compare, writing no outputs",
+            cli.seed.unwrap_or_default()
+        ));
+    } else if let Some(runs) = cli.ensemble {
+        let base = cli.seed.unwrap_or_default();
+        plan.push(format!(
+            "Ensemble: {runs} runs with seeds {base} to {}, plotted together in {}",
+            base + runs as u64 - 1,
+            cli.output_path().display()
+        ));
+    } else if cli.summary_only {
+        plan.push("Summary: class statistics printed as text, writing no outputs".to_string());
+    } else {
+        if !cli.no_plot {
+            plan.push(format!("Visualization: {}", cli.output_path().display()));
+        }
+        if let Some(iterations) = cli.pacmap_config().snapshots {
+            plan.push(format!(
+                "Animation: {} frames captured at iterations {}",
+                iterations.len(),
+                iterations
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        for (name, path) in [
+            ("Embedding CSV", &cli.embedding_out),
+            ("Embedding array", &cli.npy_out),
+            ("Neighbor graph", &cli.graph_out),
+            ("Saved embedding", &cli.save_embedding),
+        ] {
+            if let Some(path) = path {
+                plan.push(format!("{name}: {}", path.display()));
+            }
+        }
+        if cli.load_embedding.is_none() {
+            plan.push(format!("Run manifest: {}", cli.manifest_path().display()));
+        }
+    }
+
+    if let Some(path) = &cli.profile {
+        plan.push(format!("Profile trace: {}", path.display()));
+    }
+
+    println!("{}", plan.join("\n"));
+    Ok(())
+}
+
+/// Scores an embedding's trustworthiness, neighbor recall and, if labels are
+/// known, k-NN accuracy
+///
+/// # Arguments
+/// * `cli` - Command-line options holding the neighbor counts
+/// * `name` - Name of the algorithm that produced the embedding, used in logs
+/// * `x` - Input data the embedding was computed from
+/// * `embedding` - Array of shape (n_samples, n_dims) containing embedded coordinates
+/// * `labels` - Class labels of shape (n_samples,), if known
+///
+/// # Returns
+/// The scores, with a k-NN accuracy only if labels are known
+pub(crate) fn score_embedding(
+    cli: &Cli,
+    name: &str,
+    x: ArrayView2<f32>,
+    embedding: ArrayView2<f32>,
+    labels: Option<&Array1<i32>>,
+) -> Scores {
+    let k = cli.trustworthiness_k;
+    let score = Spinner::run("Scoring trustworthiness", || {
+        trustworthiness(x, embedding, k)
+    });
+    cli.report_metric(
+        Some(name),
+        "trustworthiness",
+        k,
+        score,
+        &format!("{name} trustworthiness (k = {k}): {score:.4}"),
+    );
+    let recall = Spinner::run("Scoring neighbor recall", || {
+        neighbor_recall(x, embedding, k)
+    });
+    cli.report_metric(
+        Some(name),
+        "neighbor_recall",
+        k,
+        recall,
+        &format!("{name} neighbor recall (k = {k}): {:.2}%", recall * 100.0),
+    );
+
+    let accuracy = labels.map(|labels| {
+        let k = cli.knn_accuracy_k;
+        let accuracy = Spinner::run("Scoring k-NN accuracy", || {
+            knn_accuracy(embedding, labels, k)
+        });
+        cli.report_metric(
+            Some(name),
+            "knn_accuracy",
+            k,
+            accuracy,
+            &format!("{name} k-NN accuracy (k = {k}): {:.2}%", accuracy * 100.0),
+        );
+        accuracy
+    });
+    Scores {
+        trustworthiness_k: k,
+        trustworthiness: score,
+        neighbor_recall: recall,
+        knn_accuracy_k: cli.knn_accuracy_k,
+        knn_accuracy: accuracy,
+    }
+}
+
+/// Checks that `--classes` can select points by label, warning about classes
+/// missing from the data
+///
+/// # Errors
+/// Returns an error if classes are selected but the data has no labels
+pub(crate) fn check_classes(classes: &[i32], labels: Option<&Array1<i32>>) -> Result<()> {
+    if classes.is_empty() {
+        return Ok(());
+    }
+    let labels = labels.context("--classes needs class labels, but the data has none")?;
+    let missing: Vec<_> = classes
+        .iter()
+        .filter(|class| !labels.iter().any(|label| label == *class))
+        .map(i32::to_string)
+        .collect();
+    if !missing.is_empty() {
+        warn!(
+            "--classes selects labels with no points: {}",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Maps row indices into a selection of rows back to the rows they were selected from
+///
+/// # Arguments
+/// * `outer` - Rows kept by an earlier selection, if any
+/// * `inner` - Rows kept from the earlier selection, if any
+///
+/// # Returns
+/// The original row behind each row kept by both selections, or `None` if
+/// neither selected rows
+pub(crate) fn compose_rows(
+    outer: Option<Vec<usize>>,
+    inner: Option<Vec<usize>>,
+) -> Option<Vec<usize>> {
+    match (outer, inner) {
+        (Some(outer), Some(inner)) => Some(inner.into_iter().map(|i| outer[i]).collect()),
+        (outer, inner) => inner.or(outer),
+    }
+}
+
+/// Lists the first few duplicates as examples for a warning
+pub(crate) fn duplicate_examples(duplicates: &[Duplicate]) -> String {
+    let examples: Vec<_> = duplicates
+        .iter()
+        .take(DUPLICATE_EXAMPLES)
+        .map(|duplicate| format!("row {} repeats row {}", duplicate.row, duplicate.first))
+        .collect();
+    examples.join(", ")
+}
+
+/// Returns the caption summarizing an algorithm's run in a comparison plot
+///
+/// # Arguments
+/// * `name` - Name of the algorithm
+/// * `elapsed` - Time taken to compute the embedding
+/// * `scores` - Scores of the embedding, as from [`score_embedding`]
+pub(crate) fn caption(name: &str, elapsed: Duration, scores: &Scores) -> String {
+    let mut caption = format!(
+        "{name}: {} ms, trustworthiness {:.3}",
+        elapsed.as_millis(),
+        scores.trustworthiness
+    );
+    if let Some(accuracy) = scores.knn_accuracy {
+        caption += &format!(", k-NN accuracy {:.1}%", accuracy * 100.0);
+    }
+    caption
+}
+
+/// Returns whether each embedded point comes from the training data, for
+/// `--split-output`
+///
+/// Built-in datasets are loaded with the training images before the test
+/// images, so a point is a training point if its row in the loaded data falls
+/// within the training split. With `--input`, the first input holds the
+/// training data.
+///
+/// # Arguments
+/// * `cli` - Command-line options naming the data
+/// * `n_rows` - Number of embedded points
+/// * `ids` - Row of the loaded data behind each embedded point, if rows were
+///   dropped or sampled; otherwise points are rows
+/// * `sources` - Index of the input each embedded point came from, with `--input`
+pub(crate) fn training_rows(
+    cli: &Cli,
+    n_rows: usize,
+    ids: Option<&[usize]>,
+    sources: Option<&Array1<i32>>,
+) -> Vec<bool> {
+    match sources {
+        Some(sources) => sources.iter().map(|&source| source == 0).collect(),
+        None => {
+            let train = cli.split_sizes().train;
+            (0..n_rows)
+                .map(|i| ids.map_or(i, |ids| ids[i]) < train)
+                .collect()
+        }
+    }
+}
+
+/// Reads `--anchors` and finds the embedded row of each anchored point
+///
+/// # Arguments
+/// * `path` - CSV file of `index,x,y` rows, or `index,x,y,z` for 3D
+/// * `n_rows` - Number of embedded points
+/// * `ids` - Index of each embedded point in the original data, if the rows
+///   were subsampled from it; otherwise indices are rows
+/// * `dims` - Number of embedding dimensions
+///
+/// # Errors
+/// Returns an error if the file cannot be read, a row does not give `dims`
+/// coordinates, or an index names no embedded point
+pub(crate) fn resolve_anchors(
+    path: &Path,
+    n_rows: usize,
+    ids: Option<&[usize]>,
+    dims: usize,
+) -> Result<Vec<Anchor>> {
+    let rows = embedded_rows(ids);
+    load_anchors(path, dims)?
+        .into_iter()
+        .map(|(index, position)| {
+            let row = embedded_row(index, rows.as_ref(), n_rows, "Anchored point", path)?;
+            Ok(Anchor { row, position })
+        })
+        .collect()
+}
+
+/// Embeds the data twice with the same seed and checks that both embeddings match
+///
+/// The largest per-coordinate difference is reported either way, so a near
+/// miss can be told apart from a completely different layout.
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `x_fit` - Data passed to PaCMAP, possibly PCA-reduced
+/// * `timings` - Phase timings the runs are added to
+///
+/// # Errors
+/// Returns an error if either run fails or the embeddings differ by more than
+/// [`REPRODUCIBILITY_TOLERANCE`]
+pub(crate) fn verify_reproducible(
+    cli: &Cli,
+    x_fit: ArrayView2<f32>,
+    timings: &mut Timings,
+) -> Result<()> {
+    let seed = cli.seed.unwrap_or_default();
+    let mut config = cli.pacmap_config();
+    config.seed = Some(seed);
+    info!(
+        "Embedding {} rows twice with seed {seed} to check reproducibility...",
+        x_fit.nrows()
+    );
+
+    let mut runs = Vec::with_capacity(2);
+    for run in 1..=2 {
+        check_interrupted("the reproducibility check finished")?;
+        let embedding = timings.time("fit", || {
+            Spinner::run(format!("Running PaCMAP ({run} of 2)"), || {
+                embed(x_fit, config.clone())
+            })
+        })?;
+        runs.push(embedding);
+    }
+
+    let difference = runs[0]
+        .iter()
+        .zip(runs[1].iter())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max);
+    cli.report(&format!(
+        "Largest coordinate difference between the two seeded runs: {difference:e}"
+    ));
+    ensure!(
+        difference <= REPRODUCIBILITY_TOLERANCE,
+        "Seeded runs are not reproducible: coordinates differ by up to {difference:e}, more \
+         than the tolerance of {REPRODUCIBILITY_TOLERANCE:e}"
+    );
+    cli.report("Reproducibility check passed: both seeded runs produced the same embedding");
+    Ok(())
+}
+
+/// Embeds the data once per seed of an ensemble
+///
+/// Run `i` is seeded with `--seed` (or 0 if unset) plus `i`, so the whole
+/// ensemble is reproducible while every run starts from a different
+/// initialization and pair sample. The runs skip the embedding cache.
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `runs` - Number of embeddings to compute
+/// * `x` - Input data used to score the embeddings
+/// * `x_fit` - Data passed to PaCMAP, possibly PCA-reduced
+/// * `config` - PaCMAP configuration whose seed each run replaces
+/// * `labels` - Class labels used to score k-NN accuracy, if known
+/// * `timings` - Phase timings the runs are added to
+///
+/// # Returns
+/// Each finished run's embedding with its caption, naming the seed and
+/// scores. An interrupt stops before the next run, returning those finished.
+///
+/// # Errors
+/// Returns an error if any run fails
+pub(crate) fn fit_ensemble(
+    cli: &Cli,
+    runs: usize,
+    x: ArrayView2<f32>,
+    x_fit: ArrayView2<f32>,
+    config: Configuration,
+    labels: Option<&Array1<i32>>,
+    timings: &mut Timings,
+) -> Result<Vec<(Array2<f32>, String)>> {
+    let base = cli.seed.unwrap_or_default();
+    let mut members = Vec::with_capacity(runs);
+    for (i, seed) in (base..).take(runs).enumerate() {
+        if interrupted() {
+            warn!("Plotting the {} finished runs", members.len());
+            break;
+        }
+        info!(
+            "Ensemble run {}/{runs} with seed {seed} on shape {:?}...",
+            i + 1,
+            x_fit.shape()
+        );
+        let (embedding, elapsed) = timings.measure("fit", || {
+            Spinner::run(format!("Running PaCMAP ({} of {runs})", i + 1), || {
+                fit(x_fit, config.clone(), seed)
+            })
+        });
+        let embedding = embedding?;
+
+        let name = format!("Seed {seed}");
+        let scores = timings.time("scoring", || {
+            score_embedding(cli, &name, x, embedding.view(), labels)
+        });
+        members.push((embedding, caption(&name, elapsed, &scores)));
+    }
+    Ok(members)
+}
+
+/// Embeds the data under every grid search combination and writes a summary
+///
+/// Every run uses the same data and seed (`--seed`, or 0 if unset), so
+/// differences in the scores come from the parameters alone.
+///
+/// # Arguments
+/// * `cli` - Command-line options
+/// * `x` - Input data used to score trustworthiness
+/// * `x_fit` - Data passed to PaCMAP, possibly PCA-reduced
+/// * `labels` - Class labels used to score k-NN accuracy, if known
+/// * `timings` - Phase timings the runs are added to
+///
+/// # Errors
+/// Returns an error if any run fails or the summary cannot be written
+pub(crate) fn run_grid(
+    cli: &Cli,
+    x: ArrayView2<f32>,
+    x_fit: ArrayView2<f32>,
+    labels: Option<&Array1<i32>>,
+    timings: &mut Timings,
+) -> Result<()> {
+    let mut base = cli.pacmap_config();
+    base.seed = Some(cli.seed.unwrap_or_default());
+
+    let points = cli.grid_points();
+    let mut results = Vec::with_capacity(points.len());
+    for (i, point) in points.into_iter().enumerate() {
+        if interrupted() {
+            warn!("Writing the summary of the {} finished runs", results.len());
+            break;
+        }
+        info!(
+            "Grid run {}/{}: {point} on shape {:?}...",
+            i + 1,
+            results.capacity(),
+            x_fit.shape()
+        );
+        let (embedding, elapsed) = timings.measure("fit", || {
+            Spinner::run("Running PaCMAP", || embed(x_fit, point.configure(&base)))
+        });
+        let embedding = embedding?;
+        let millis = elapsed.as_millis();
+
+        let result = timings.time("scoring", || {
+            Spinner::run("Scoring embedding", || GridResult {
+                point,
+                millis,
+                trustworthiness: trustworthiness(x, embedding.view(), cli.trustworthiness_k),
+                knn_accuracy: labels.map(|l| knn_accuracy(embedding.view(), l, cli.knn_accuracy_k)),
+            })
+        });
+        info!(
+            "Completed in {millis} ms with trustworthiness {:.4}",
+            result.trustworthiness
+        );
+        results.push(result);
+    }
+
+    timings.time("export", || write_grid_csv(&results, &cli.grid_out))?;
+    info!(
+        "Done! Grid search summary saved to {}",
+        cli.grid_out.display()
+    );
+    check_interrupted("the remaining grid runs")
+}
@@ -0,0 +1,130 @@
+//! Reproducible subsampling of the input
+//!
+//! Lets a quick run embed a random subset of a large dataset, drawing the same
+//! subset whenever the same seed is used. Rows are either drawn from the whole
+//! dataset or, for labeled data, from each class in proportion to its size, so
+//! the class balance of imbalanced data survives the sampling.
+
+use clap::ValueEnum;
+use ndarray::{Array1, Array2, ArrayView2, Axis};
+use rand::rngs::SmallRng;
+use rand::seq::index;
+use rand::SeedableRng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Ways of choosing the rows of a sample
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleStrategy {
+    /// Draw rows from the whole dataset, so small classes may be missed by chance
+    #[default]
+    Random,
+
+    /// Draw rows from each class in proportion to its size; needs labels
+    Stratified,
+}
+
+/// Selects `n` distinct row indices out of `n_rows`, in ascending order
+///
+/// Requests for more rows than are available are clamped to every row, with a
+/// warning, rather than treated as an error.
+///
+/// # Arguments
+/// * `n_rows` - Number of rows available
+/// * `n` - Number of rows to select
+/// * `seed` - Seed of the random number generator choosing the rows
+pub fn sample_indices(n_rows: usize, n: usize, seed: u64) -> Vec<usize> {
+    if n >= n_rows {
+        if n > n_rows {
+            warn!("Requested a sample of {n} rows but only {n_rows} are available; using all rows");
+        }
+        return (0..n_rows).collect();
+    }
+
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut indices = index::sample(&mut rng, n_rows, n).into_vec();
+
+    // Keep the subset in dataset order
+    indices.sort_unstable();
+    indices
+}
+
+/// Selects `n` distinct row indices, in ascending order, drawing from each
+/// class in proportion to its size
+///
+/// Each class gets its share of `n` rounded down, and the rows left over go one
+/// each to the classes whose shares lost the most to rounding, ties going to
+/// the lower label. Classes smaller than `n_rows / n` rows can therefore get no
+/// rows at all. Requests for more rows than are available are clamped to every
+/// row, with a warning, like [`sample_indices`].
+///
+/// # Arguments
+/// * `labels` - Class label of each row
+/// * `n` - Number of rows to select
+/// * `seed` - Seed of the random number generator choosing the rows
+pub fn stratified_sample_indices(labels: &Array1<i32>, n: usize, seed: u64) -> Vec<usize> {
+    let n_rows = labels.len();
+    if n >= n_rows {
+        return sample_indices(n_rows, n, seed);
+    }
+
+    let mut classes: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+    for (row, &label) in labels.iter().enumerate() {
+        classes.entry(label).or_default().push(row);
+    }
+
+    // Largest remainder apportionment of the n rows over the classes
+    let mut quotas: Vec<(usize, usize)> = classes
+        .values()
+        .map(|rows| (rows.len() * n / n_rows, rows.len() * n % n_rows))
+        .collect();
+    let left = n - quotas.iter().map(|&(quota, _)| quota).sum::<usize>();
+    let mut by_remainder: Vec<usize> = (0..quotas.len()).collect();
+    by_remainder.sort_by(|&a, &b| quotas[b].1.cmp(&quotas[a].1).then(a.cmp(&b)));
+    for &class in by_remainder.iter().take(left) {
+        quotas[class].0 += 1;
+    }
+
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut indices: Vec<usize> = classes
+        .values()
+        .zip(&quotas)
+        .flat_map(|(rows, &(quota, _))| {
+            index::sample(&mut rng, rows.len(), quota)
+                .into_iter()
+                .map(|i| rows[i])
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    // Keep the subset in dataset order
+    indices.sort_unstable();
+    indices
+}
+
+/// Randomly selects `n` rows of the data along with their labels
+///
+/// # Arguments
+/// * `x` - Input data of shape (n_samples, n_features)
+/// * `labels` - Class labels of shape (n_samples,)
+/// * `n` - Number of rows to keep, clamped to n_samples
+/// * `seed` - Seed of the random number generator choosing the rows
+///
+/// # Returns
+/// A tuple containing:
+/// * Selected rows of shape (n, n_features)
+/// * Labels of the selected rows of shape (n,)
+pub fn subsample(
+    x: ArrayView2<f32>,
+    labels: &Array1<i32>,
+    n: usize,
+    seed: u64,
+) -> (Array2<f32>, Array1<i32>) {
+    let indices = sample_indices(x.nrows(), n, seed);
+    (
+        x.select(Axis(0), &indices),
+        labels.select(Axis(0), &indices),
+    )
+}
@@ -0,0 +1,255 @@
+//! Saving and reloading computed embeddings
+//!
+//! Stores an embedding together with its labels and the settings that
+//! produced it in a single self-describing JSON file, so the visualization can
+//! be restyled without downloading the data or running PaCMAP again.
+
+use crate::export::create_parent_dir;
+use anyhow::{ensure, Context, Result};
+use ndarray::{Array1, Array2, ArrayView2};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the saved embedding file layout
+const FORMAT_VERSION: u32 = 1;
+
+/// Settings of the run that produced an embedding
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunConfig {
+    /// Name of the embedded dataset or input file
+    pub dataset: String,
+
+    /// Number of rows randomly sampled before embedding, if any
+    pub sample: Option<usize>,
+
+    /// How the sampled rows were chosen
+    #[serde(default = "default_sample_strategy")]
+    pub sample_strategy: String,
+
+    /// Normalization applied to the input
+    pub normalize: String,
+
+    /// Distance metric deciding which samples were neighbors
+    #[serde(default = "default_metric")]
+    pub metric: String,
+
+    /// Number of principal components the input was reduced to, if any
+    pub pca: Option<usize>,
+
+    /// Labels of the classes kept before embedding, if the other rows were dropped
+    #[serde(default)]
+    pub classes: Option<Vec<i32>>,
+
+    /// Number of nearest neighbors used to form neighbor pairs
+    pub neighbors: usize,
+
+    /// Ratio of mid-near pairs to nearest neighbor pairs
+    pub mid_near_ratio: f32,
+
+    /// Ratio of far pairs to nearest neighbor pairs
+    pub far_pair_ratio: f32,
+
+    /// Number of dimensions in the embedding
+    pub embedding_dimensions: usize,
+
+    /// Seed of PaCMAP's random sampling and initialization, if any
+    pub seed: Option<u64>,
+}
+
+impl RunConfig {
+    /// Returns the names of the settings that differ from `other`
+    pub fn differences(&self, other: &RunConfig) -> Vec<&'static str> {
+        let mut differences = Vec::new();
+        let mut check = |name, same: bool| {
+            if !same {
+                differences.push(name);
+            }
+        };
+        check("dataset", self.dataset == other.dataset);
+        check("sample", self.sample == other.sample);
+        check(
+            "sample-strategy",
+            self.sample_strategy == other.sample_strategy,
+        );
+        check("normalize", self.normalize == other.normalize);
+        check("metric", self.metric == other.metric);
+        check("pca", self.pca == other.pca);
+        check("classes", self.classes == other.classes);
+        check("neighbors", self.neighbors == other.neighbors);
+        check(
+            "mid-near-ratio",
+            self.mid_near_ratio == other.mid_near_ratio,
+        );
+        check(
+            "far-pair-ratio",
+            self.far_pair_ratio == other.far_pair_ratio,
+        );
+        check(
+            "embedding-dimensions",
+            self.embedding_dimensions == other.embedding_dimensions,
+        );
+        check("seed", self.seed == other.seed);
+        differences
+    }
+}
+
+/// Sample strategy of embeddings saved before the strategy could be chosen
+fn default_sample_strategy() -> String {
+    "random".to_string()
+}
+
+/// Metric of embeddings saved before the metric could be chosen
+fn default_metric() -> String {
+    "euclidean".to_string()
+}
+
+/// An embedding with everything needed to plot it again
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedEmbedding {
+    /// Version of the file layout
+    pub format_version: u32,
+
+    /// Settings of the run that produced the embedding
+    pub config: RunConfig,
+
+    /// Human-readable class names, indexed by label
+    pub class_names: Vec<String>,
+
+    /// Row of the original data behind each point, if the data was subsampled
+    pub ids: Option<Vec<usize>>,
+
+    /// Class label of each point, if known
+    pub labels: Option<Vec<i32>>,
+
+    /// Continuous value each point is colored by, if any
+    #[serde(default)]
+    pub colors: Option<Vec<f32>>,
+
+    /// Shape of the embedding as (n_samples, n_dims)
+    pub shape: (usize, usize),
+
+    /// Embedding coordinates in row-major order
+    pub coordinates: Vec<f32>,
+}
+
+impl SavedEmbedding {
+    /// Collects an embedding and its metadata for saving
+    ///
+    /// # Arguments
+    /// * `config` - Settings of the run that produced the embedding
+    /// * `embedding` - Array of shape (n_samples, n_dims) containing embedded coordinates
+    /// * `labels` - Class labels of shape (n_samples,), if known
+    /// * `colors` - Continuous values of shape (n_samples,) to color by, if any
+    /// * `ids` - Row of the original data behind each point, if subsampled
+    /// * `class_names` - Human-readable class names, indexed by label
+    pub fn new(
+        config: RunConfig,
+        embedding: ArrayView2<f32>,
+        labels: Option<&Array1<i32>>,
+        colors: Option<&Array1<f32>>,
+        ids: Option<&[usize]>,
+        class_names: &[&str],
+    ) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            config,
+            class_names: class_names.iter().map(|name| name.to_string()).collect(),
+            ids: ids.map(<[usize]>::to_vec),
+            labels: labels.map(|labels| labels.to_vec()),
+            colors: colors.map(|colors| colors.to_vec()),
+            shape: embedding.dim(),
+            coordinates: embedding.iter().copied().collect(),
+        }
+    }
+
+    /// Returns the embedding coordinates as an array of shape (n_samples, n_dims)
+    ///
+    /// # Errors
+    /// Returns an error if the coordinates do not match the stored shape
+    pub fn embedding(&self) -> Result<Array2<f32>> {
+        Array2::from_shape_vec(self.shape, self.coordinates.clone())
+            .context("Saved embedding coordinates do not match its shape")
+    }
+
+    /// Returns the class labels as an array of shape (n_samples,), if known
+    pub fn labels(&self) -> Option<Array1<i32>> {
+        self.labels.clone().map(Array1::from_vec)
+    }
+
+    /// Returns the values the points are colored by as an array of shape
+    /// (n_samples,), if any
+    pub fn colors(&self) -> Option<Array1<f32>> {
+        self.colors.clone().map(Array1::from_vec)
+    }
+
+    /// Writes the embedding to a JSON file, creating parent directories as needed
+    ///
+    /// Coordinates are written in their shortest round-trip form, so loading
+    /// the file reproduces the embedding exactly. The file is written under a
+    /// temporary name with `.partial` appended, such as `run.json.partial`, and
+    /// renamed into place, so a `plot --watch` of the path never reads it
+    /// half-written.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be created, written or moved into place
+    pub fn save(&self, path: &Path) -> Result<()> {
+        create_parent_dir(path)?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let file = File::create(&partial)
+            .with_context(|| format!("Error creating embedding file {}", partial.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)
+            .and_then(|()| writer.flush().map_err(serde_json::Error::io))
+            .with_context(|| format!("Error writing embedding file {}", partial.display()))?;
+        fs::rename(&partial, path).with_context(|| {
+            format!(
+                "Error moving embedding file into place at {}",
+                path.display()
+            )
+        })
+    }
+
+    /// Reads an embedding saved with [`SavedEmbedding::save`]
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, is not a saved embedding,
+    /// was written by an unsupported version, or is internally inconsistent
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Error opening embedding file {}", path.display()))?;
+        let saved: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Error reading embedding file {}", path.display()))?;
+
+        ensure!(
+            saved.format_version == FORMAT_VERSION,
+            "Embedding file {} has unsupported format version {}, expected {FORMAT_VERSION}",
+            path.display(),
+            saved.format_version
+        );
+        let (n, dims) = saved.shape;
+        ensure!(
+            saved.coordinates.len() == n * dims,
+            "Embedding file {} holds {} coordinates, expected {n} x {dims}",
+            path.display(),
+            saved.coordinates.len()
+        );
+        for (name, len) in [
+            ("labels", saved.labels.as_ref().map(Vec::len)),
+            ("colors", saved.colors.as_ref().map(Vec::len)),
+            ("ids", saved.ids.as_ref().map(Vec::len)),
+        ] {
+            if let Some(len) = len {
+                ensure!(
+                    len == n,
+                    "Embedding file {} holds {len} {name} for {n} points",
+                    path.display()
+                );
+            }
+        }
+        Ok(saved)
+    }
+}
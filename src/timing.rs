@@ -0,0 +1,111 @@
+//! Wall-clock timing of pipeline phases
+//!
+//! Records how long each phase of a run takes so the slowest step can be
+//! identified, and summarizes the breakdown at the end of the run. Each timed
+//! phase also runs inside a span on [`PROFILE_TARGET`] carrying the phase's
+//! name, which `--profile` records into a trace.
+
+use crate::profile::PROFILE_TARGET;
+use std::time::{Duration, Instant};
+use tracing::{info, info_span};
+
+/// Durations of the phases of a run, in the order they ran
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Creates an empty set of timings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` and records its duration under `phase`
+    ///
+    /// # Arguments
+    /// * `phase` - Name of the phase shown in the summary
+    /// * `f` - Work done in the phase
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        self.measure(phase, f).0
+    }
+
+    /// Runs `f`, records its duration under `phase` and also returns the duration
+    ///
+    /// # Arguments
+    /// * `phase` - Name of the phase shown in the summary
+    /// * `f` - Work done in the phase
+    ///
+    /// # Returns
+    /// The result of `f` and how long this run of it took
+    pub fn measure<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> (T, Duration) {
+        let start = Instant::now();
+        let result = info_span!(target: PROFILE_TARGET, "phase", phase).in_scope(f);
+        let elapsed = start.elapsed();
+        self.record(phase, elapsed);
+        (result, elapsed)
+    }
+
+    /// Records that `phase` took `duration`
+    ///
+    /// Durations of a phase recorded more than once are added together.
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// Returns the duration of each recorded phase, in the order they ran
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// Returns the duration of `phase`, if it was recorded
+    pub fn get(&self, phase: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(name, _)| *name == phase)
+            .map(|&(_, duration)| duration)
+    }
+
+    /// Returns the combined duration of all phases
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|&(_, duration)| duration).sum()
+    }
+
+    /// Logs a table of the phase durations and their share of the total
+    ///
+    /// Each row also carries its `phase`, `duration_ms` and `share_percent` as
+    /// fields, so JSON logs can be aggregated without parsing the table.
+    pub fn log_summary(&self) {
+        let total = self.total();
+        let width = self
+            .phases
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        info!("Timing breakdown:");
+        for &(name, duration) in &self.phases {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                duration.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            let duration_ms = duration.as_secs_f64() * 1000.0;
+            info!(
+                phase = name,
+                duration_ms,
+                share_percent = share,
+                "  {name:<width$}  {duration_ms:>10.1} ms  {share:>5.1}%"
+            );
+        }
+        let duration_ms = total.as_secs_f64() * 1000.0;
+        info!(
+            phase = "total",
+            duration_ms, "  {:<width$}  {duration_ms:>10.1} ms", "total"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Re-running a step whenever a file changes
+//!
+//! The file is polled for its modification time and size instead of being
+//! watched for change events. Polling a single file twice a second costs next
+//! to nothing and behaves the same on every platform and file system,
+//! including network mounts, where change events are unreliable.
+//!
+//! A change is acted on once the file has looked the same for two polls in a
+//! row, so a file still being written is not read halfway through. Watching
+//! ends when Ctrl-C is pressed.
+
+use crate::interrupt::interrupted;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Time between two looks at the watched file
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a look at the file shows: its modification time and size, if it exists
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+/// Calls `on_change` once the file exists and again after every change, until
+/// Ctrl-C is pressed
+///
+/// A file that is missing, or disappears, is waited for, so the watch can
+/// start before the step writing the file has run.
+///
+/// # Arguments
+/// * `path` - File to watch
+/// * `interval` - Time between two looks at the file
+/// * `on_change` - Step to run, which handles its own failures
+pub fn watch_file(path: &Path, interval: Duration, on_change: impl FnMut()) {
+    watch_file_until(path, interval, interrupted, on_change);
+}
+
+/// Calls `on_change` once the file exists and again after every change, until
+/// `stop` returns true
+///
+/// Works like [`watch_file`], with the end of the watch chosen by the caller.
+///
+/// # Arguments
+/// * `path` - File to watch
+/// * `interval` - Time between two looks at the file
+/// * `stop` - Checked before each look, ending the watch once it returns true
+/// * `on_change` - Step to run, which handles its own failures
+pub fn watch_file_until(
+    path: &Path,
+    interval: Duration,
+    mut stop: impl FnMut() -> bool,
+    mut on_change: impl FnMut(),
+) {
+    let mut handled: Stamp = None;
+    let mut previous: Stamp = None;
+    while !stop() {
+        let stamp = fs::metadata(path)
+            .ok()
+            .map(|metadata| (metadata.modified().ok(), metadata.len()));
+        if stamp.is_some() && stamp == previous && stamp != handled {
+            on_change();
+            handled = stamp;
+        }
+        previous = stamp;
+        thread::sleep(interval);
+    }
+}
@@ -0,0 +1,40 @@
+//! Synthetic data shared by the integration tests and the benchmark
+
+use ndarray::{Array1, Array2};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
+
+/// Draws Gaussian blobs with unit variance, blob `b` centered at
+/// `spacing * b` along every axis
+///
+/// # Arguments
+/// * `seed` - Seed of the random number generator
+/// * `blobs` - Number of blobs
+/// * `points_per_blob` - Number of points drawn from each blob
+/// * `features` - Number of features of each point
+/// * `spacing` - Distance between neighboring blob centers along each axis
+///
+/// # Returns
+/// A tuple containing:
+/// * Points of shape (blobs * points_per_blob, features)
+/// * Blob index of each point of shape (blobs * points_per_blob,)
+pub fn gaussian_blobs(
+    seed: u64,
+    blobs: usize,
+    points_per_blob: usize,
+    features: usize,
+    spacing: f32,
+) -> (Array2<f32>, Array1<i32>) {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let n = blobs * points_per_blob;
+    let labels = Array1::from_shape_fn(n, |i| (i / points_per_blob) as i32);
+    let x = Array2::from_shape_fn((n, features), |(i, _)| {
+        // Box-Muller transform of two uniform samples into a standard normal one
+        let u: f32 = 1.0 - rng.gen::<f32>();
+        let v: f32 = rng.gen();
+        let normal = (-2.0 * u.ln()).sqrt() * (TAU * v).cos();
+        labels[i] as f32 * spacing + normal
+    });
+    (x, labels)
+}
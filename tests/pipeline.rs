@@ -0,0 +1,558 @@
+//! End-to-end tests of the embedding pipeline on small synthetic data
+//!
+//! Everything here is generated locally, so the tests run in seconds and
+//! never touch the network.
+
+mod common;
+
+use anyhow::Result;
+use ndarray::{array, s, Array1, Array2, Axis};
+use ndarray_npy::read_npy;
+use pacmap::Configuration;
+use pacmap_rs_example::anchors::{anchored_initialization, Anchor};
+use pacmap_rs_example::distance::Metric;
+use pacmap_rs_example::export::{labels_npy_path, write_embedding_csv, write_embedding_npy};
+use pacmap_rs_example::graph::{
+    graph_distances_path, graph_from_pairs, pacmap_neighbor_graph, write_graph_npy,
+};
+use pacmap_rs_example::input::{load_annotations, load_csv, load_npy, FeatureColumns, LabelColumn};
+use pacmap_rs_example::metrics::{
+    class_labels, class_summaries, knn_accuracy, knn_predictions, neighbor_confusion,
+    neighbor_recall, silhouette, snapshot_movement, trustworthiness,
+};
+use pacmap_rs_example::plot::{create_plot, AxisScale, HoverInfo, Labels, PlotStyle};
+use pacmap_rs_example::sample::stratified_sample_indices;
+use pacmap_rs_example::watch::watch_file_until;
+use pacmap_rs_example::{
+    add_embedding_traces, build_scatter, embed, embedding_bounds, fit, PipelineError,
+};
+use plotly::common::Title;
+use plotly::{Layout, Plot};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of Gaussian blobs in the synthetic dataset
+const BLOBS: usize = 3;
+
+/// Number of points drawn from each blob
+const POINTS_PER_BLOB: usize = 100;
+
+/// Number of features of each point
+const FEATURES: usize = 10;
+
+/// Draws well-separated Gaussian blobs with unit variance
+///
+/// Blob `b` is centered at 10 * b along every axis, so the blobs are far
+/// apart relative to their spread.
+///
+/// # Returns
+/// A tuple containing:
+/// * Points of shape (300, 10)
+/// * Blob index of each point of shape (300,)
+fn gaussian_blobs(seed: u64) -> (Array2<f32>, Array1<i32>) {
+    common::gaussian_blobs(seed, BLOBS, POINTS_PER_BLOB, FEATURES, 10.0)
+}
+
+/// Returns a small embedding with both coordinates and labels to round-trip
+fn sample_embedding() -> (Array2<f32>, Array1<i32>) {
+    let embedding = Array2::from_shape_fn((5, 2), |(i, j)| i as f32 * 0.1 - j as f32 / 3.0);
+    let labels = Array1::from_vec(vec![0, 1, 2, 1, 0]);
+    (embedding, labels)
+}
+
+#[test]
+fn embedding_keeps_blobs_separated() -> Result<()> {
+    let (x, labels) = gaussian_blobs(7);
+    let config = Configuration::builder().seed(42).build();
+
+    let embedding = embed(x.view(), config)?;
+
+    assert_eq!(embedding.dim(), (BLOBS * POINTS_PER_BLOB, 2));
+    let accuracy = knn_accuracy(embedding.view(), &labels, 10);
+    assert!(accuracy > 0.95, "k-NN accuracy {accuracy} is too low");
+    let score = trustworthiness(x.view(), embedding.view(), 10);
+    assert!(score > 0.8, "trustworthiness {score} is too low");
+    Ok(())
+}
+
+#[test]
+fn embedding_has_configured_dimensions() -> Result<()> {
+    let (x, _) = gaussian_blobs(11);
+    for dims in [2, 3] {
+        let config = Configuration::builder()
+            .embedding_dimensions(dims)
+            .seed(1)
+            .build();
+
+        let embedding = embed(x.view(), config)?;
+
+        assert_eq!(embedding.dim(), (x.nrows(), dims));
+    }
+    Ok(())
+}
+
+#[test]
+fn fit_matches_seeded_embedding() -> Result<()> {
+    let (x, _) = gaussian_blobs(13);
+
+    let fitted = fit(x.view(), Configuration::default(), 5)?;
+    let embedded = embed(x.view(), Configuration::builder().seed(5).build())?;
+
+    assert_eq!(fitted, embedded);
+    Ok(())
+}
+
+#[test]
+fn anchored_initialization_places_anchors() -> Result<()> {
+    // PCA of 2D data is an affine map, so anchoring points at their own
+    // coordinates must map every other point back onto its coordinates too
+    let (x, _) = gaussian_blobs(17);
+    let x = x.slice(s![.., ..2]).to_owned();
+    let anchors: Vec<Anchor> = [0, 42, 150, 299]
+        .into_iter()
+        .map(|row| Anchor {
+            row,
+            position: x.row(row).to_vec(),
+        })
+        .collect();
+
+    let layout = anchored_initialization(x.view(), &anchors, 2)?;
+
+    for anchor in &anchors {
+        assert_eq!(layout.row(anchor.row).to_vec(), anchor.position);
+    }
+    let error = (&layout - &x)
+        .iter()
+        .fold(0.0_f32, |max, e| max.max(e.abs()));
+    assert!(
+        error < 1e-3,
+        "layout differs from the data by up to {error}"
+    );
+    Ok(())
+}
+
+#[test]
+fn csv_export_round_trips() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("embedding.csv");
+    let (embedding, labels) = sample_embedding();
+
+    write_embedding_csv(embedding.view(), Some(&labels), &path)?;
+    let data = load_csv(&path, Some(&LabelColumn::Named("label".into())), None, None)?;
+
+    assert_eq!(data.x, embedding);
+    assert_eq!(data.labels, Some(labels));
+    assert_eq!(data.colors, None);
+    Ok(())
+}
+
+#[test]
+fn csv_text_labels_become_named_classes() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("pets.csv");
+    std::fs::write(&path, "0.5,1,dog\n2,0.25,cat\n1,1,dog\n0,3,bird\n")?;
+
+    let data = load_csv(&path, Some(&LabelColumn::Last), None, None)?;
+
+    // Without a header the first row is still data, and classes are numbered alphabetically
+    assert_eq!(data.x.nrows(), 4);
+    assert_eq!(data.class_names, ["bird", "cat", "dog"]);
+    assert_eq!(data.labels, Some(array![2, 1, 2, 0]));
+    Ok(())
+}
+
+#[test]
+fn csv_feature_selection_reads_only_chosen_columns() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("pixels.csv");
+    std::fs::write(
+        &path,
+        "label,id,pixel_0,pixel_1,note\n3,a,0.5,1,x\n7,b,2,0.25,y\n",
+    )?;
+    let label = "#1".parse::<LabelColumn>()?;
+
+    let by_pattern = load_csv(&path, Some(&label), None, Some(&"pixel_*".parse()?))?;
+    assert_eq!(by_pattern.x, array![[0.5, 1.0], [2.0, 0.25]]);
+    assert_eq!(by_pattern.labels, Some(array![3, 7]));
+    // Ranges are 1-based and inclusive, and the file's column order wins over the list's
+    let by_range = load_csv(&path, Some(&label), None, Some(&"#4,#3-3".parse()?))?;
+    assert_eq!(by_range.x, by_pattern.x);
+
+    for (spec, message) in [
+        ("pixel_2", "not found"),
+        ("voxel_*", "matches no"),
+        ("#3-9", "only 5 columns"),
+        ("#1-4", "label column"),
+    ] {
+        let error = load_csv(&path, Some(&label), None, Some(&spec.parse()?)).unwrap_err();
+        assert!(error.to_string().contains(message), "{spec}: {error}");
+    }
+    assert!("#5-3".parse::<FeatureColumns>().is_err());
+    assert!("#0".parse::<LabelColumn>().is_err());
+
+    // A number that is a header name selects that column, as in pandas exports, unless
+    // marked as a position with `#`
+    std::fs::write(&path, "label,0,1\n3,0.5,1\n7,2,0.25\n")?;
+    let named = load_csv(&path, Some(&"label".parse()?), None, Some(&"1".parse()?))?;
+    assert_eq!(named.x, array![[1.0], [0.25]]);
+    let positioned = load_csv(&path, Some(&"label".parse()?), None, Some(&"#2".parse()?))?;
+    assert_eq!(positioned.x, array![[0.5], [2.0]]);
+
+    // A text ID column left out of a headerless file does not make its first row a header,
+    // and bare numbers are positions there
+    std::fs::write(&path, "a,0.5,1,3\nb,2,0.25,7\n")?;
+    for (label, features) in [("#4", "#2-3"), ("4", "2-3")] {
+        let headerless = load_csv(&path, Some(&label.parse()?), None, Some(&features.parse()?))?;
+        assert_eq!(headerless.x, by_pattern.x);
+        assert_eq!(headerless.labels, Some(array![3, 7]));
+    }
+    let error = load_csv(&path, Some(&"label".parse()?), None, None).unwrap_err();
+    assert!(error.to_string().contains("such as #1"), "{error}");
+    Ok(())
+}
+
+#[test]
+fn annotations_skip_a_header_but_not_a_negative_index() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("notes.csv");
+    std::fs::write(&path, "index,text\n4,\"outlier, far left\"\n")?;
+    assert_eq!(
+        load_annotations(&path)?,
+        [(4, "outlier, far left".to_string())]
+    );
+
+    std::fs::write(&path, "-1,first\n4,second\n")?;
+    let error = load_annotations(&path).unwrap_err();
+    assert!(error.to_string().contains("'-1'"), "{error}");
+    Ok(())
+}
+
+#[test]
+fn npy_export_round_trips() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("embedding.npy");
+    let (embedding, labels) = sample_embedding();
+
+    write_embedding_npy(embedding.view(), Some(&labels), &path)?;
+    let loaded_labels: Array1<i32> = read_npy(labels_npy_path(&path))?;
+
+    assert_eq!(load_npy(&path)?, embedding);
+    assert_eq!(loaded_labels, labels);
+    Ok(())
+}
+
+#[test]
+fn neighbor_graph_stays_within_blobs_and_round_trips() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("graph.npy");
+    let (x, labels) = gaussian_blobs(19);
+
+    let graph = pacmap_neighbor_graph(x.view(), 10, 8_000)?;
+    write_graph_npy(&graph, &path)?;
+    let indices: Array2<u32> = read_npy(&path)?;
+    let distances: Array2<f32> = read_npy(graph_distances_path(&path))?;
+
+    assert_eq!(indices.dim(), (x.nrows(), 10));
+    for ((i, j), &neighbor) in indices.indexed_iter() {
+        let neighbor = neighbor as usize;
+        assert!(neighbor != i && labels[neighbor] == labels[i]);
+        let distance = (&x.row(i) - &x.row(neighbor)).mapv(|d| d * d).sum().sqrt();
+        assert!((distances[[i, j]] - distance).abs() < 1e-3);
+    }
+    let provided = graph_from_pairs(x.view(), graph.pair_neighbors().view(), Metric::Euclidean);
+    assert_eq!(provided.indices, indices);
+    Ok(())
+}
+
+#[test]
+fn scatter_colors_sparse_labels_by_rank() -> Result<()> {
+    let (embedding, _) = sample_embedding();
+    let labels = Array1::from_vec(vec![1000, -3, 7, 1000, -3]);
+
+    let traces = build_scatter(embedding.view(), &labels)?;
+
+    // Each distinct label gets the next palette color, named after its own value
+    let traces: Vec<serde_json::Value> = traces
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?;
+    let names: Vec<_> = traces.iter().map(|trace| trace["name"].clone()).collect();
+    assert_eq!(names, ["-3", "7", "1000"]);
+    let colors: Vec<_> = traces
+        .iter()
+        .map(|trace| trace["marker"]["color"].clone())
+        .collect();
+    assert!(colors.iter().all(serde_json::Value::is_string));
+    assert!(colors[0] != colors[1] && colors[1] != colors[2] && colors[0] != colors[2]);
+    Ok(())
+}
+
+#[test]
+fn max_points_keeps_class_proportions() -> Result<()> {
+    let labels: Array1<i32> = [(0, 900), (1, 90), (2, 10)]
+        .into_iter()
+        .flat_map(|(label, n)| std::iter::repeat_n(label, n))
+        .collect();
+    let embedding = Array2::from_shape_fn((labels.len(), 2), |(i, j)| (i * (j + 1)) as f32);
+    let style = PlotStyle {
+        max_points: Some(100),
+        ..PlotStyle::default()
+    };
+
+    let plot = create_plot(
+        embedding.view(),
+        Some(&Labels::Discrete(labels)),
+        &[],
+        &HoverInfo::default(),
+        &style,
+        "Capped",
+    )?;
+
+    // Each class keeps its share of the drawn points, and the rarest at least one
+    let drawn: Vec<usize> = plot
+        .data()
+        .iter()
+        .map(|trace| {
+            serde_json::to_value(trace).map(|trace| trace["x"].as_array().map_or(0, Vec::len))
+        })
+        .collect::<Result<_, _>>()?;
+    assert_eq!(drawn, [90, 9, 1]);
+    Ok(())
+}
+
+#[test]
+fn embedding_bounds_span_coordinates_and_axes() -> Result<()> {
+    let embedding = array![[1.0, -2.0, 9.0], [f32::NAN, 4.0, 0.0], [-3.0, 0.5, 0.0]];
+
+    assert_eq!(embedding_bounds(embedding.view()), (-3.0, 1.0, -2.0, 4.0));
+
+    // The plot's axes span the bounds with a 5% margin on each side
+    let plot = create_plot(
+        embedding.slice(s![.., ..2]),
+        None,
+        &[],
+        &HoverInfo::default(),
+        &PlotStyle::default(),
+        "Bounds",
+    )?;
+    let layout = serde_json::to_value(plot.layout())?;
+    assert_eq!(layout["xaxis"]["range"], serde_json::json!([-3.2, 1.2]));
+    assert_eq!(layout["yaxis"]["range"], serde_json::json!([-2.3, 4.3]));
+    Ok(())
+}
+
+#[test]
+fn axis_scales_set_log_type_and_symlog_ticks() -> Result<()> {
+    let embedding = array![[1.0_f32, -99.0], [10.0, 0.0], [100.0, 99.0]];
+    let style = PlotStyle {
+        x_scale: AxisScale::Log,
+        y_scale: AxisScale::Symlog,
+        ..PlotStyle::default()
+    };
+
+    let plot = create_plot(
+        embedding.view(),
+        None,
+        &[],
+        &HoverInfo::default(),
+        &style,
+        "Scales",
+    )?;
+
+    // Log ranges are in powers of ten, padded by 5% of the two decades
+    let layout = serde_json::to_value(plot.layout())?;
+    assert_eq!(layout["xaxis"]["type"], "log");
+    let range = layout["xaxis"]["range"].as_array().unwrap();
+    assert!((range[0].as_f64().unwrap() + 0.1).abs() < 1e-9);
+    assert!((range[1].as_f64().unwrap() - 2.1).abs() < 1e-9);
+    assert_eq!(
+        layout["yaxis"]["ticktext"],
+        serde_json::json!(["-100", "-10", "-1", "0", "1", "10", "100"])
+    );
+
+    // Points are drawn at their symlog positions, and log axes reject zero
+    let traces = serde_json::to_value(plot.data())?;
+    let y = traces[0]["y"][0].as_f64().unwrap();
+    assert!((y + 2.0).abs() < 1e-6, "y {y}");
+    let zero = array![[0.0_f32, 1.0], [1.0, 2.0]];
+    assert!(create_plot(zero.view(), None, &[], &HoverInfo::default(), &style, "").is_err());
+    Ok(())
+}
+
+#[test]
+fn embedding_traces_join_callers_plot() -> Result<()> {
+    let (embedding, labels) = sample_embedding();
+    let mut plot = Plot::new();
+    plot.set_layout(Layout::new().title(Title::with_text("Dashboard")));
+
+    add_embedding_traces(&mut plot, embedding.view(), &labels)?;
+    add_embedding_traces(&mut plot, embedding.view(), &labels)?;
+
+    // One trace per class and embedding, with the caller's layout kept
+    assert_eq!(plot.data().len(), 6);
+    assert!(plot.to_json().contains("Dashboard"));
+    Ok(())
+}
+
+#[test]
+fn plotting_a_3d_embedding_in_2d_is_a_shape_error() {
+    let embedding = Array2::zeros((4, 3));
+    let labels = Array1::zeros(4);
+
+    let result = build_scatter(embedding.view(), &labels);
+
+    assert!(matches!(result, Err(PipelineError::Shape(_))));
+}
+
+#[test]
+fn neighbor_confusion_of_separated_blobs_is_diagonal() {
+    // The blobs are far apart in the input space already, so use it as the embedding
+    let (x, labels) = gaussian_blobs(3);
+    let k = 5;
+
+    let confusion = neighbor_confusion(x.view(), &labels, k);
+
+    assert_eq!(class_labels(&labels), vec![0, 1, 2]);
+    assert_eq!(
+        confusion,
+        Array2::from_diag(&Array1::from_elem(BLOBS, (POINTS_PER_BLOB * k) as u32))
+    );
+}
+
+#[test]
+fn silhouette_and_class_summaries_of_two_pairs() {
+    let embedding = array![[0.0, 0.0], [0.0, 1.0], [10.0, 0.0], [10.0, 1.0]];
+    let labels = array![4, 4, 9, 9];
+
+    // Every point is 1 from its partner and 10 or sqrt(101) from the other pair
+    let b = (10.0 + 101.0_f64.sqrt()) / 2.0;
+    let score = silhouette(embedding.view(), &labels);
+    assert!((score - (1.0 - 1.0 / b)).abs() < 1e-6, "silhouette {score}");
+
+    let summaries = class_summaries(embedding.view(), &labels);
+    assert_eq!(summaries.len(), 2);
+    assert_eq!((summaries[0].label, summaries[0].count), (4, 2));
+    assert_eq!(summaries[0].centroid, [0.0, 0.5]);
+    assert_eq!(summaries[1].centroid, [10.0, 0.5]);
+    assert!(summaries.iter().all(|summary| summary.mean_distance == 1.0));
+}
+
+#[test]
+fn snapshot_movement_compares_last_two_layouts() {
+    let start = array![[1.0_f32, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]];
+    let shifted = &start + &array![[1.0_f32, 0.0]];
+    let snapshots = ndarray::stack(Axis(0), &[start.view(), start.view(), shifted.view()]).unwrap();
+
+    let movement = snapshot_movement(snapshots.view()).unwrap();
+
+    assert!((movement - 1.0).abs() < 1e-9, "movement {movement}");
+    assert_eq!(snapshot_movement(snapshots.slice(s![..1, .., ..])), None);
+}
+
+#[test]
+fn metrics_break_distance_ties_by_index() {
+    // The origin is equally far from the four other points, so only the
+    // tie-break decides which of them is its nearest neighbor
+    let embedding = array![[0.0, 0.0], [1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]];
+    let labels = Array1::from(vec![7, 7, 8, 8, 8]);
+
+    // Point 0 takes the label of point 1, and all others take the label of point 0
+    assert_eq!(
+        knn_predictions(embedding.view(), &labels, 1),
+        Array1::from_elem(5, 7)
+    );
+    assert_eq!(knn_accuracy(embedding.view(), &labels, 1), 0.4);
+    assert_eq!(
+        neighbor_confusion(embedding.view(), &labels, 1),
+        array![[2, 0], [3, 0]]
+    );
+
+    // Ties are ranked the same way in both spaces, so the embedding matches itself
+    assert_eq!(trustworthiness(embedding.view(), embedding.view(), 2), 1.0);
+    assert_eq!(neighbor_recall(embedding.view(), embedding.view(), 2), 1.0);
+}
+
+#[test]
+fn embedding_is_stable_under_row_permutation() -> Result<()> {
+    let (x, labels) = gaussian_blobs(5);
+    let mut order: Vec<usize> = (0..x.nrows()).collect();
+    order.shuffle(&mut SmallRng::seed_from_u64(9));
+    let shuffled_x = x.select(Axis(0), &order);
+    let shuffled_labels = labels.select(Axis(0), &order);
+    let config = || Configuration::builder().seed(42).build();
+
+    let embedding = embed(x.view(), config())?;
+    let shuffled = embed(shuffled_x.view(), config())?;
+
+    // Row i of the shuffled run is input row order[i], so scatter it back there
+    let mut restored = Array2::zeros(shuffled.dim());
+    for (i, &row) in order.iter().enumerate() {
+        restored.row_mut(row).assign(&shuffled.row(i));
+    }
+    let accuracy = knn_accuracy(shuffled.view(), &shuffled_labels, 10);
+    assert!(
+        accuracy > 0.95,
+        "k-NN accuracy {accuracy} of the shuffled run is too low"
+    );
+    // The layouts may differ by a rotation or reflection, so compare neighborhoods instead.
+    // Points placed at random within their blob would share about a tenth of them.
+    let recall = neighbor_recall(embedding.view(), restored.view(), 10);
+    assert!(
+        recall > 0.2,
+        "neighbor recall {recall} between the runs is too low"
+    );
+    Ok(())
+}
+
+#[test]
+fn stratified_sample_keeps_class_proportions() {
+    let labels = Array1::from_shape_fn(1000, |i| match i % 100 {
+        0 => 2,
+        1..=9 => 1,
+        _ => 0,
+    });
+
+    let indices = stratified_sample_indices(&labels, 100, 3);
+
+    let counts: Vec<usize> = (0..3)
+        .map(|class| indices.iter().filter(|&&i| labels[i] == class).count())
+        .collect();
+    assert_eq!(counts, [90, 9, 1]);
+    assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(stratified_sample_indices(&labels, 100, 3), indices);
+}
+
+#[test]
+fn watch_fires_once_per_settled_change() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("embedding.json");
+    let interval = Duration::from_millis(20);
+    let settle = Duration::from_millis(300);
+    let mut changes = 0;
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(|| -> std::io::Result<()> {
+            // Nothing fires while the file is missing, then once per write
+            thread::sleep(settle);
+            std::fs::write(&path, "first")?;
+            thread::sleep(settle);
+            std::fs::write(&path, "second, longer")?;
+            thread::sleep(settle);
+            Ok(())
+        });
+        let start = Instant::now();
+        watch_file_until(
+            &path,
+            interval,
+            || start.elapsed() > settle * 4,
+            || changes += 1,
+        );
+        writer.join().expect("writer thread panicked")
+    })?;
+
+    assert_eq!(changes, 2);
+    Ok(())
+}